@@ -1,6 +1,6 @@
 use aligned_array::{A32, Aligned};
 use criterion::{criterion_group, criterion_main, Criterion};
-use diffusion::{diffusion::{DiffusionFunc, mrxsm::MRXSM}, utils};
+use diffusion::{config::SearchConfig, diffusion::{DiffusionFunc, mrxsm::MRXSM}, utils};
 use rand::Rng;
 use rand_distr::{Distribution, Geometric};
 
@@ -9,7 +9,7 @@ fn diffuse_benchmark(c: &mut Criterion) {
     let mut rng = rand::thread_rng();
     let mut xs: Aligned<A32, _> = Aligned([0_u64; 4096]);
     for i in 0..xs.len() { xs[i] = rng.gen(); }
-    let f = MRXSM::random(&mut rng);
+    let f = MRXSM::random(&SearchConfig::default(), &mut rng);
 
     c.bench_function("diffuse_sequential", |b| {
         b.iter(|| {
@@ -0,0 +1,567 @@
+use std::{
+	error::Error,
+	fmt::{self, Display, Formatter},
+	fs, io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use crate::{
+	diffusion::any_diffusion::Family,
+	evaluation::{differences::DifferenceKind, EvaluatorKind},
+	evolution::{
+		checkpoint::CheckpointFormat, history::HistoryFormat, islands::Topology, selection::SelectionKind,
+		BijectivityPolicy, Racing,
+	},
+	utils::{
+		corpus::{Corpus, CorpusError, CorpusMode},
+		distance::DistanceKind,
+		sample_source::{SampleDistribution, SampleSource},
+		word,
+		xoshiro::{splitmix64, Xoshiro256PlusPlus},
+	},
+};
+
+use rand::SeedableRng;
+
+/** All parameters of a genetic search. A configuration can be loaded from a JSON file (missing fields fall back to
+   their defaults) and individual values can be overridden from the command line. Either way, `validate` has to be
+   called before the configuration is used; `from_args` already takes care of that. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+	/** The family of diffusion functions to search. */
+	pub family: Family,
+	/** The width (in bits) of the words the diffusion functions operate on. One of `word::WIDTHS`. */
+	pub word_width: u32,
+	/** The evaluation strategy used as the loss of a candidate. */
+	pub evaluator: EvaluatorKind,
+	/** The distance of bit-flip histograms to Bin(word_width, 0.5) that the Wasserstein evaluators use. */
+	pub distance: DistanceKind,
+	/** The number of generations each thread runs, on top of those of a checkpoint it continues from. */
+	pub n_generations: u32,
+	/** If set, each thread instead runs until it reaches this generation, counted from the start of the search. */
+	pub until_generation: Option<u32>,
+	pub n_threads: u32,
+	/** The number of threads that evaluate the members of a generation in parallel (on the same samples), per thread
+	   of `n_threads`. */
+	pub n_workers: u32,
+	pub generation_size: u32,
+	pub elitism: u32,
+	pub n_samples: u32,
+	/** The distribution the samples are drawn from. Ignored if `corpus` is set. */
+	pub samples: SampleDistribution,
+	/** A file of keys to draw the samples from instead (see `Corpus::load`). It must hold at least `n_samples` distinct
+	   keys. */
+	pub corpus: Option<PathBuf>,
+	/** How the samples are drawn from `corpus`. */
+	pub corpus_mode: CorpusMode,
+	/** Used for Wasserstein evaluators. Must be a divisor of `n_samples`. Samples are 64 bits wide, so for 128-bit
+	   words, two of them make up one input and `n_samples / n_rounds` must be even. */
+	pub n_rounds: u32,
+	/** How the parents of the children are picked (see `selection::SelectionKind`). */
+	pub selection: SelectionKind,
+	/** The number of competitors in the tournaments of the `tournament` and `probabilistic_tournament` selections. */
+	pub tournament_size: u32,
+	pub crossover_bits: u32,
+	pub mutation_odds: u32,
+	/** Number of generations after which we exchange the samples. Must be a power of 2. */
+	pub sample_lifetime: u32,
+	/** How the search treats candidates that aren't bijections. */
+	pub bijectivity: BijectivityPolicy,
+	/** Added to the loss of non-bijective candidates if `bijectivity` is `penalise`. */
+	pub bijectivity_penalty: f32,
+	/** Whether and against which cutoff children are raced, i.e. given up on as soon as they're clearly worse. */
+	pub racing: Racing,
+	/** The number of rounds that a child is evaluated on before racing may give up on it. At least 2. */
+	pub racing_min_rounds: u32,
+	/** How many standard errors below its partial loss the cutoff has to be for racing to give up on a child. */
+	pub racing_z: f32,
+	/** Number of generations after which the threads exchange their best functions (see `islands::Island`). 0 for
+	   never. */
+	pub migration_interval: u32,
+	/** The number of functions each thread sends to each of its neighbours. */
+	pub migration_size: u32,
+	/** Which threads are neighbours. */
+	pub topology: Topology,
+	/** The input differences the `wasserstein_worst` evaluator looks at (see `differences::InputDifference::set`). */
+	pub differences: Vec<DifferenceKind>,
+	/** Number of random masks for `DifferenceKind::Sparse`. */
+	pub sparse_masks: u32,
+	/** Number of bits set in each of the random masks. */
+	pub sparse_weight: u32,
+	/** Seed of the random masks, so that all candidates (and threads) see the same ones. */
+	pub difference_seed: u64,
+	/** The master seed that all random numbers of a search are derived from (see `rng`). If it's not set, `main` draws
+	   one at startup and prints it, so that any run can be repeated. */
+	pub seed: Option<u64>,
+	/** How the state of each thread is stored. */
+	pub checkpoint_format: CheckpointFormat,
+	/** The number of functions each thread keeps in its hall of fame (see `hall_of_fame::HallOfFame`). */
+	pub hall_of_fame_size: u32,
+	/** The number of best candidates of each thread that take the final exam at the end of a search, along with the
+	   hall of fame (see `exam::Exam`). 0 skips the exam. */
+	pub exam_top: u32,
+	/** How many times `n_samples` fresh samples the final exam scores the candidates on. */
+	pub exam_factor: u32,
+	/** Whether and how each thread logs the statistics of every generation (see `history::History`). */
+	pub history: HistoryFormat,
+}
+
+impl Default for SearchConfig {
+	fn default() -> Self {
+		#[cfg(feature = "profile")]
+		let n_generations = 1;
+		#[cfg(not(feature = "profile"))]
+		let n_generations = 100;
+		Self {
+			family: Family::default(),
+			word_width: 64,
+			evaluator: EvaluatorKind::default(),
+			distance: DistanceKind::default(),
+			n_generations,
+			until_generation: None,
+			n_threads: 8,
+			n_workers: 1,
+			generation_size: 512,
+			elitism: 50,
+			n_samples: 100000,
+			samples: SampleDistribution::default(),
+			corpus: None,
+			corpus_mode: CorpusMode::default(),
+			n_rounds: 100,
+			selection: SelectionKind::default(),
+			tournament_size: 4,
+			crossover_bits: 32,
+			mutation_odds: 8,
+			sample_lifetime: 8,
+			bijectivity: BijectivityPolicy::default(),
+			bijectivity_penalty: 1.0,
+			racing: Racing::default(),
+			racing_min_rounds: 5,
+			racing_z: 3.0,
+			migration_interval: 0,
+			migration_size: 2,
+			topology: Topology::default(),
+			differences: DifferenceKind::ALL.to_vec(),
+			sparse_masks: 64,
+			sparse_weight: 3,
+			difference_seed: 0,
+			seed: None,
+			checkpoint_format: CheckpointFormat::default(),
+			hall_of_fame_size: 10,
+			exam_top: 5,
+			exam_factor: 100,
+			history: HistoryFormat::default(),
+		}
+	}
+}
+
+impl SearchConfig {
+	#[inline(always)]
+	pub fn n_samples_per_round(&self) -> u32 { self.n_samples / self.n_rounds }
+
+	#[inline(always)]
+	pub fn n_children(&self) -> u32 { self.generation_size - self.elitism }
+
+	/** An independent generator for the given purpose on the given island (i.e. thread), derived from `seed` (0 if
+	   it isn't set). */
+	pub fn rng(&self, island: u32, stream: RngStream) -> Xoshiro256PlusPlus {
+		let mut state = self.seed.unwrap_or(0);
+		Xoshiro256PlusPlus::seed_from_u64(splitmix64(&mut state) ^ ((island as u64) << 8 | stream as u64))
+	}
+
+	/** The source of the samples: the corpus if one is configured (which is loaded here), `samples` otherwise. */
+	pub fn sample_source(&self) -> Result<Arc<dyn SampleSource>, CorpusError> {
+		match &self.corpus {
+			Some(path) => Ok(Arc::new(Corpus::load(path, self.corpus_mode, self.n_samples as usize)?)),
+			None => Ok(Arc::new(self.samples.clone())),
+		}
+	}
+
+	/** Loads a configuration from a JSON file. The result is _not_ validated. */
+	pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+		let path = path.as_ref();
+		let s = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+		serde_json::from_str(&s).map_err(|e| ConfigError::Json(path.to_path_buf(), e))
+	}
+
+	/** Builds a configuration from command-line arguments (without the program name). If `--config <file>` is given,
+	   that file is used as the base configuration, otherwise the defaults are. Every other flag is of the form
+	   `--<name> <value>` (e.g. `--generation-size 1024`) and overrides the respective value of the base configuration.
+	   The result is validated. */
+	pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+		let mut config_file = None;
+		let mut overrides = Vec::new();
+		let mut args = args.into_iter();
+		while let Some(flag) = args.next() {
+			let value = args.next().ok_or_else(|| ConfigError::MissingValue(flag.clone()))?;
+			if flag == "--config" {
+				config_file = Some(value);
+			} else {
+				overrides.push((flag, value));
+			}
+		}
+		let mut config = match config_file {
+			Some(path) => Self::from_json_file(path)?,
+			None => Self::default(),
+		};
+		for (flag, value) in overrides.iter() {
+			config.set_flag(flag, value)?;
+		}
+		config.validate()?;
+		Ok(config)
+	}
+
+	/** Overrides a single value given as a command-line flag `--<name>` (with dashes instead of underscores). */
+	pub fn set_flag(&mut self, flag: &str, value: &str) -> Result<(), ConfigError> {
+		let invalid = || ConfigError::InvalidValue(flag.to_string(), value.to_string());
+		let field = match flag {
+			"--family" => {
+				self.family = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--evaluator" => {
+				self.evaluator = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--distance" => {
+				self.distance = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--bijectivity" => {
+				self.bijectivity = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--bijectivity-penalty" => {
+				self.bijectivity_penalty = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--racing" => {
+				self.racing = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--racing-z" => {
+				self.racing_z = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--selection" => {
+				self.selection = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--topology" => {
+				self.topology = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--differences" => {
+				self.differences = value.split(',').map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--samples" => {
+				self.samples = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--corpus" => {
+				self.corpus = Some(PathBuf::from(value));
+				return Ok(());
+			}
+			"--corpus-mode" => {
+				self.corpus_mode = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--seed" => {
+				self.seed = Some(value.parse().map_err(|_| invalid())?);
+				return Ok(());
+			}
+			"--until-generation" => {
+				self.until_generation = Some(value.parse().map_err(|_| invalid())?);
+				return Ok(());
+			}
+			"--history" => {
+				self.history = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--checkpoint-format" => {
+				self.checkpoint_format = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--difference-seed" => {
+				self.difference_seed = value.parse().map_err(|_| invalid())?;
+				return Ok(());
+			}
+			"--word-width" => &mut self.word_width,
+			"--n-generations" => &mut self.n_generations,
+			"--n-threads" => &mut self.n_threads,
+			"--n-workers" => &mut self.n_workers,
+			"--generation-size" => &mut self.generation_size,
+			"--elitism" => &mut self.elitism,
+			"--n-samples" => &mut self.n_samples,
+			"--n-rounds" => &mut self.n_rounds,
+			"--tournament-size" => &mut self.tournament_size,
+			"--crossover-bits" => &mut self.crossover_bits,
+			"--mutation-odds" => &mut self.mutation_odds,
+			"--sample-lifetime" => &mut self.sample_lifetime,
+			"--racing-min-rounds" => &mut self.racing_min_rounds,
+			"--migration-interval" => &mut self.migration_interval,
+			"--migration-size" => &mut self.migration_size,
+			"--sparse-masks" => &mut self.sparse_masks,
+			"--sparse-weight" => &mut self.sparse_weight,
+			"--hall-of-fame-size" => &mut self.hall_of_fame_size,
+			"--exam-top" => &mut self.exam_top,
+			"--exam-factor" => &mut self.exam_factor,
+			_ => return Err(ConfigError::UnknownFlag(flag.to_string())),
+		};
+		*field = value.parse().map_err(|_| invalid())?;
+		Ok(())
+	}
+
+	/** Checks all the constraints between parameters that the search relies on. */
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		let check = |cond: bool, msg: &str| if cond { Ok(()) } else { Err(ConfigError::Invalid(msg.to_string())) };
+		check(word::WIDTHS.contains(&self.word_width), "word_width must be one of 16, 32, 64 and 128")?;
+		check(self.n_threads > 0, "n_threads must be positive")?;
+		check(self.n_workers > 0, "n_workers must be positive")?;
+		check(self.n_rounds > 0, "n_rounds must be positive")?;
+		check(self.n_samples > 0, "n_samples must be positive")?;
+		check(self.n_samples.is_multiple_of(self.n_rounds), "n_rounds must be a divisor of n_samples")?;
+		check(self.word_width != 128 || self.n_samples_per_round() & 1 == 0,
+			"n_samples / n_rounds must be even for 128-bit words")?;
+		if matches!(self.evaluator, EvaluatorKind::AvalancheSseArith | EvaluatorKind::AvalancheSseGeom) {
+			let n_words = self.n_samples_per_round() / (self.word_width / 64).max(1);
+			check(n_words & 1 == 0 && n_words < 2048,
+				"avalanche evaluators need an even number of words per round (n_samples / n_rounds) below 2048")?;
+		}
+		check(self.generation_size > self.elitism, "generation_size must be greater than elitism")?;
+		check(self.n_children() & 1 == 0, "generation_size - elitism must be even")?;
+		check(self.tournament_size > 0, "tournament_size must be positive")?;
+		check(!self.selection.needs_cases() || matches!(self.evaluator, EvaluatorKind::WassersteinArith
+			| EvaluatorKind::WassersteinGeom | EvaluatorKind::WassersteinWorst),
+			"lexicase selection needs one of the Wasserstein evaluators")?;
+		check(self.crossover_bits > 0 && self.crossover_bits < 33, "crossover_bits must be in 1..=32")?;
+		check(self.mutation_odds > 0, "mutation_odds must be positive")?;
+		check(self.sample_lifetime.count_ones() == 1, "sample_lifetime must be a power of 2")?;
+		check(self.bijectivity_penalty.is_finite() && self.bijectivity_penalty >= 0.0,
+			"bijectivity_penalty must be non-negative")?;
+		check(self.exam_factor > 0, "exam_factor must be positive")?;
+		check(self.racing_min_rounds >= 2, "racing_min_rounds must be at least 2")?;
+		check(self.racing_min_rounds <= self.n_rounds || self.racing == Racing::Off,
+			"racing_min_rounds can't exceed n_rounds")?;
+		check(self.racing_z.is_finite() && self.racing_z > 0.0, "racing_z must be positive")?;
+		check(!self.differences.is_empty(), "differences must not be empty")?;
+		check(self.sparse_weight > 0 && self.sparse_weight <= self.word_width,
+			"sparse_weight must be in 1..=word_width")?;
+		check(self.bijectivity != BijectivityPolicy::Reject || self.family.has_bijections(),
+			"can't reject non-bijective functions of a family without bijections")
+	}
+}
+
+
+/** What the random numbers of a `SearchConfig::rng` are used for. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngStream {
+	/** The initial population, selection, crossover and mutation. */
+	Evolution,
+	/** The samples that functions are evaluated on. */
+	Samples,
+	/** The samples of the final exam (see `exam::Exam`). */
+	Exam,
+	/** The samples of the p-values that are reported along with the results. */
+	Report,
+}
+
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(PathBuf, io::Error),
+	Json(PathBuf, serde_json::Error),
+	UnknownFlag(String),
+	MissingValue(String),
+	InvalidValue(String, String),
+	Invalid(String),
+}
+
+impl Display for ConfigError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Io(path, e) => write!(f, "couldn't read {}: {}", path.display(), e),
+			ConfigError::Json(path, e) => write!(f, "invalid configuration {}: {}", path.display(), e),
+			ConfigError::UnknownFlag(flag) => write!(f, "unknown flag {}", flag),
+			ConfigError::MissingValue(flag) => write!(f, "missing value for {}", flag),
+			ConfigError::InvalidValue(flag, value) => write!(f, "invalid value for {}: {}", flag, value),
+			ConfigError::Invalid(msg) => write!(f, "invalid configuration: {}", msg),
+		}
+	}
+}
+
+impl Error for ConfigError {}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(s: &str) -> Vec<String> { s.split_whitespace().map(String::from).collect() }
+
+	#[test]
+	fn test_default_is_valid() {
+		assert!(SearchConfig::default().validate().is_ok());
+	}
+
+	fn invalid(s: &str) -> bool { matches!(SearchConfig::from_args(args(s)), Err(ConfigError::Invalid(_))) }
+
+	fn invalid_value(s: &str) -> bool { matches!(SearchConfig::from_args(args(s)), Err(ConfigError::InvalidValue(..))) }
+
+	#[test]
+	fn test_from_args() {
+		let config = SearchConfig::from_args(args("--generation-size 1024 --elitism 100 --n-rounds 10 --family RXSM \
+			--evaluator wasserstein_geom")).unwrap();
+		assert_eq!(Family::RXSM, config.family);
+		assert_eq!(EvaluatorKind::WassersteinGeom, config.evaluator);
+		assert_eq!(1024, config.generation_size);
+		assert_eq!(100, config.elitism);
+		assert_eq!(10, config.n_rounds);
+		assert_eq!(SearchConfig::default().n_samples, config.n_samples);
+
+		assert!(invalid("--elitism 51"));
+		assert!(invalid("--n-rounds 7"));
+		assert!(invalid("--sample-lifetime 6"));
+		assert!(invalid("--crossover-bits 33"));
+		assert!(matches!(SearchConfig::from_args(args("--elitism")), Err(ConfigError::MissingValue(_))));
+		assert!(invalid_value("--elitism x"));
+		assert!(matches!(SearchConfig::from_args(args("--foo 1")), Err(ConfigError::UnknownFlag(_))));
+		assert!(invalid_value("--family MXS"));
+		assert!(invalid_value("--evaluator w1"));
+	}
+
+	#[test]
+	fn test_bijectivity_args() {
+		let config = SearchConfig::from_args(args("--bijectivity penalise --bijectivity-penalty 0.5")).unwrap();
+		assert_eq!(BijectivityPolicy::Penalise, config.bijectivity);
+		assert_eq!(0.5, config.bijectivity_penalty);
+		assert!(invalid("--bijectivity reject --family MXR"));
+	}
+
+	#[test]
+	fn test_word_width_args() {
+		assert_eq!(32, SearchConfig::from_args(args("--word-width 32")).unwrap().word_width);
+		assert!(invalid("--word-width 8"));
+		assert!(invalid("--word-width 128 --n-samples 300 --n-rounds 100"));
+	}
+
+	#[test]
+	fn test_avalanche_args() {
+		assert!(SearchConfig::from_args(args("--evaluator avalanche_sse_geom")).is_ok());
+		assert!(invalid("--evaluator avalanche_sse_arith --n-rounds 10"));
+	}
+
+	#[test]
+	fn test_difference_args() {
+		let config =
+			SearchConfig::from_args(args("--differences bits,deltas --sparse-masks 3 --difference-seed 7")).unwrap();
+		assert_eq!(vec![DifferenceKind::Bits, DifferenceKind::Deltas], config.differences);
+		assert_eq!(3, config.sparse_masks);
+		assert_eq!(7, config.difference_seed);
+		assert!(invalid_value("--differences bits,nibbles"));
+		assert!(invalid("--word-width 16 --sparse-weight 17"));
+	}
+
+	#[test]
+	fn test_sample_args() {
+		let config = SearchConfig::from_args(args("--samples 0.5*gray+0.5*pointer:4")).unwrap();
+		assert_eq!("0.5*gray+0.5*pointer:4", config.samples.to_string());
+		assert!(invalid_value("--samples counter:0"));
+	}
+
+	#[test]
+	fn test_corpus_args() {
+		let config = SearchConfig::from_args(args("--corpus keys.bin --corpus-mode subsample")).unwrap();
+		assert_eq!(Some(PathBuf::from("keys.bin")), config.corpus);
+		assert_eq!(CorpusMode::Subsample, config.corpus_mode);
+		assert!(invalid_value("--corpus-mode shuffle"));
+	}
+
+	#[test]
+	fn test_distance_args() {
+		let config = SearchConfig::from_args(args("--distance kolmogorov_smirnov")).unwrap();
+		assert_eq!(DistanceKind::KolmogorovSmirnov, config.distance);
+		assert!(invalid_value("--distance euclid"));
+	}
+
+	#[test]
+	fn test_racing_args() {
+		let config =
+			SearchConfig::from_args(args("--racing tournament --racing-min-rounds 10 --racing-z 2.5")).unwrap();
+		assert_eq!(Racing::Tournament, config.racing);
+		assert_eq!(10, config.racing_min_rounds);
+		assert_eq!(2.5, config.racing_z);
+		assert!(invalid("--racing-min-rounds 1"));
+		assert!(invalid("--racing elite --racing-min-rounds 20 --n-rounds 10"));
+	}
+
+	#[test]
+	fn test_parallel_args() {
+		assert_eq!(4, SearchConfig::from_args(args("--n-workers 4")).unwrap().n_workers);
+		assert!(invalid("--n-workers 0"));
+		let config =
+			SearchConfig::from_args(args("--migration-interval 16 --migration-size 3 --topology random")).unwrap();
+		assert_eq!((16, 3, Topology::Random), (config.migration_interval, config.migration_size, config.topology));
+	}
+
+	#[test]
+	fn test_seed_args() {
+		let config = SearchConfig::from_args(args("--seed 42")).unwrap();
+		assert_eq!(Some(42), config.seed);
+		assert_eq!(config.rng(1, RngStream::Samples), config.rng(1, RngStream::Samples));
+		assert_ne!(config.rng(1, RngStream::Samples), config.rng(2, RngStream::Samples));
+		assert_ne!(config.rng(1, RngStream::Samples), config.rng(1, RngStream::Evolution));
+		assert_ne!(config.rng(1, RngStream::Samples), SearchConfig::default().rng(1, RngStream::Samples));
+	}
+
+	#[test]
+	fn test_generation_args() {
+		let config = SearchConfig::from_args(args("--n-generations 10 --until-generation 250")).unwrap();
+		assert_eq!(10, config.n_generations);
+		assert_eq!(Some(250), config.until_generation);
+		assert_eq!(None, SearchConfig::default().until_generation);
+		assert!(invalid_value("--until-generation -1"));
+	}
+
+	#[test]
+	fn test_output_args() {
+		let config = SearchConfig::from_args(args("--checkpoint-format binary --history json_lines")).unwrap();
+		assert_eq!(CheckpointFormat::Binary, config.checkpoint_format);
+		assert_eq!(HistoryFormat::JsonLines, config.history);
+		assert!(invalid_value("--history xml"));
+	}
+
+	#[test]
+	fn test_hall_of_fame_and_exam_args() {
+		assert_eq!(3, SearchConfig::from_args(args("--hall-of-fame-size 3")).unwrap().hall_of_fame_size);
+		let config = SearchConfig::from_args(args("--exam-top 2 --exam-factor 10")).unwrap();
+		assert_eq!((2, 10), (config.exam_top, config.exam_factor));
+		assert_ne!(config.rng(0, RngStream::Samples), config.rng(0, RngStream::Exam));
+		assert!(invalid("--exam-factor 0"));
+	}
+
+	#[test]
+	fn test_selection_args() {
+		let config = SearchConfig::from_args(args("--selection linear_rank:1.8")).unwrap();
+		assert_eq!(SelectionKind::LinearRank { pressure: 1.8 }, config.selection);
+		assert!(invalid_value("--selection rank"));
+		assert!(invalid("--selection lexicase --evaluator bic_arith"));
+	}
+
+	#[test]
+	fn test_json() {
+		let json = r#"{"family": "MRXS", "evaluator": "wasserstein_geom", "n_threads": 2, "n_samples": 1000,
+			"samples": "sparse:2"}"#;
+		let config: SearchConfig = serde_json::from_str(json).unwrap();
+		assert_eq!(Family::MRXS, config.family);
+		assert_eq!(EvaluatorKind::WassersteinGeom, config.evaluator);
+		assert_eq!(2, config.n_threads);
+		assert_eq!(1000, config.n_samples);
+		assert_eq!(SampleDistribution::Sparse { max_weight: 2 }, config.samples);
+		assert_eq!(SearchConfig::default().generation_size, config.generation_size);
+		assert!(serde_json::from_str::<SearchConfig>(r#"{"n_thread": 2}"#).is_err());
+	}
+}
@@ -0,0 +1,282 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+};
+
+use rand::Rng;
+
+use crate::{config::SearchConfig, utils::word::Word};
+
+use super::{
+	inverse::{InversionError, InvertibleDiffusion},
+	mrxr::MRXR, mrxs::MRXS, mrxsm::MRXSM, mxr::MXR, rxsm::RXSM, DiffusionFunc, DiffusionVisitor, ParseFuncError,
+};
+
+
+/** The families of diffusion functions we know about. */
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Family {
+	MXR,
+	MRXR,
+	MRXS,
+	RXSM,
+	#[default]
+	MRXSM,
+}
+
+impl Family {
+	/** Whether the family contains any bijections at all. `x ^= x.rotate_right(s)` never is one. */
+	pub fn has_bijections(&self) -> bool {
+		!matches!(self, Family::MXR | Family::MRXR)
+	}
+}
+
+impl Display for Family {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		let name = match self {
+			Family::MXR => "MXR",
+			Family::MRXR => "MRXR",
+			Family::MRXS => "MRXS",
+			Family::RXSM => "RXSM",
+			Family::MRXSM => "MRXSM",
+		};
+		write!(f, "{}", name)
+	}
+}
+
+impl FromStr for Family {
+	type Err = ParseFuncError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.trim() {
+			"MXR" | "MROR" => Ok(Family::MXR),
+			"MRXR" => Ok(Family::MRXR),
+			"MRXS" => Ok(Family::MRXS),
+			"RXSM" => Ok(Family::RXSM),
+			"MRXSM" => Ok(Family::MRXSM),
+			name => Err(ParseFuncError::UnknownFamily(name.to_string())),
+		}
+	}
+}
+
+
+/** A diffusion function of any of the families we know about, which allows picking the family at runtime (see
+   `SearchConfig::family`). Calling `diffuse` on it has to dispatch on every call, so evaluations should go through
+   `DiffusionFunc::accept` instead, which dispatches only once and then runs monomorphised code. */
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum AnyDiffusion<W = u64> {
+	MXR(MXR<W>),
+	MRXR(MRXR<W>),
+	MRXS(MRXS<W>),
+	RXSM(RXSM<W>),
+	MRXSM(MRXSM<W>),
+}
+
+/** Evaluates `$body` with `$f` bound to the function wrapped by `$any`. */
+macro_rules! dispatch {
+	($any:expr, $f:ident => $body:expr) => {
+		match $any {
+			AnyDiffusion::MXR($f) => $body,
+			AnyDiffusion::MRXR($f) => $body,
+			AnyDiffusion::MRXS($f) => $body,
+			AnyDiffusion::RXSM($f) => $body,
+			AnyDiffusion::MRXSM($f) => $body,
+		}
+	};
+}
+
+impl<W> AnyDiffusion<W> {
+	pub fn family(&self) -> Family {
+		match self {
+			AnyDiffusion::MXR(_) => Family::MXR,
+			AnyDiffusion::MRXR(_) => Family::MRXR,
+			AnyDiffusion::MRXS(_) => Family::MRXS,
+			AnyDiffusion::RXSM(_) => Family::RXSM,
+			AnyDiffusion::MRXSM(_) => Family::MRXSM,
+		}
+	}
+}
+
+impl<W: Word> Display for AnyDiffusion<W> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		dispatch!(self, func => func.fmt(f))
+	}
+}
+
+impl<W: Word> DiffusionFunc for AnyDiffusion<W> {
+	type Word = W;
+
+	#[inline(always)]
+	fn diffuse(&self, x: W) -> W {
+		dispatch!(self, f => f.diffuse(x))
+	}
+
+	/** Returns a random function of the family given by `config.family`. */
+	fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self {
+		match config.family {
+			Family::MXR => AnyDiffusion::MXR(MXR::random(config, rng)),
+			Family::MRXR => AnyDiffusion::MRXR(MRXR::random(config, rng)),
+			Family::MRXS => AnyDiffusion::MRXS(MRXS::random(config, rng)),
+			Family::RXSM => AnyDiffusion::RXSM(RXSM::random(config, rng)),
+			Family::MRXSM => AnyDiffusion::MRXSM(MRXSM::random(config, rng)),
+		}
+	}
+
+	/** _Note:_ Both parents have to belong to the same family. */
+	fn crossover(&self, other: &Self, config: &SearchConfig, rng: &mut impl Rng) -> [Self; 2] {
+		match (self, other) {
+			(AnyDiffusion::MXR(f), AnyDiffusion::MXR(g)) => f.crossover(g, config, rng).map(AnyDiffusion::MXR),
+			(AnyDiffusion::MRXR(f), AnyDiffusion::MRXR(g)) => f.crossover(g, config, rng).map(AnyDiffusion::MRXR),
+			(AnyDiffusion::MRXS(f), AnyDiffusion::MRXS(g)) => f.crossover(g, config, rng).map(AnyDiffusion::MRXS),
+			(AnyDiffusion::RXSM(f), AnyDiffusion::RXSM(g)) => f.crossover(g, config, rng).map(AnyDiffusion::RXSM),
+			(AnyDiffusion::MRXSM(f), AnyDiffusion::MRXSM(g)) => f.crossover(g, config, rng).map(AnyDiffusion::MRXSM),
+			_ => panic!("can't cross {} with {}", self, other),
+		}
+	}
+
+	#[inline(always)]
+	fn accept<V: DiffusionVisitor<W>>(&self, visitor: V) -> V::Output {
+		dispatch!(self, f => visitor.visit(f))
+	}
+}
+
+impl<W: Word> InvertibleDiffusion for AnyDiffusion<W> {
+	fn check_bijective(&self) -> Result<(), InversionError> {
+		dispatch!(self, f => f.check_bijective())
+	}
+
+	fn undiffuse(&self, y: W) -> Result<W, InversionError> {
+		dispatch!(self, f => f.undiffuse(y))
+	}
+}
+
+impl<W: Word> FromStr for AnyDiffusion<W> {
+	type Err = ParseFuncError;
+
+	/** Parses the `Display` output of any diffusion function, picking the family by the name in front of the braces. */
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (name, _) = super::split_func(s)?;
+		match super::split_width(name).0 {
+			"MXR" | "MROR" => s.parse().map(AnyDiffusion::MXR),
+			"MRXR" => s.parse().map(AnyDiffusion::MRXR),
+			"MRXS" => s.parse().map(AnyDiffusion::MRXS),
+			"RXSM" => s.parse().map(AnyDiffusion::RXSM),
+			"MRXSM" => s.parse().map(AnyDiffusion::MRXSM),
+			_ => Err(ParseFuncError::UnknownFamily(name.to_string())),
+		}
+	}
+}
+
+impl<W> From<MXR<W>> for AnyDiffusion<W> {
+	fn from(func: MXR<W>) -> Self { AnyDiffusion::MXR(func) }
+}
+
+impl<W> From<MRXR<W>> for AnyDiffusion<W> {
+	fn from(func: MRXR<W>) -> Self { AnyDiffusion::MRXR(func) }
+}
+
+impl<W> From<MRXS<W>> for AnyDiffusion<W> {
+	fn from(func: MRXS<W>) -> Self { AnyDiffusion::MRXS(func) }
+}
+
+impl<W> From<RXSM<W>> for AnyDiffusion<W> {
+	fn from(func: RXSM<W>) -> Self { AnyDiffusion::RXSM(func) }
+}
+
+impl<W> From<MRXSM<W>> for AnyDiffusion<W> {
+	fn from(func: MRXSM<W>) -> Self { AnyDiffusion::MRXSM(func) }
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::Rng;
+
+	use super::*;
+
+	fn round_trip(f: impl Into<AnyDiffusion>) {
+		let f = f.into();
+		let s = f.to_string();
+		assert_eq!(f, s.parse().unwrap(), "{}", s);
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let mut rng = rand::thread_rng();
+		let config = SearchConfig::default();
+		for _ in 0..100 {
+			round_trip(MXR::random(&config, &mut rng));
+			round_trip(MRXR::random(&config, &mut rng));
+			round_trip(MRXS::random(&config, &mut rng));
+			round_trip(RXSM::random(&config, &mut rng));
+			round_trip(MRXSM::random(&config, &mut rng));
+		}
+	}
+
+	#[test]
+	fn test_dispatch() {
+		struct Diffuse(u64);
+
+		impl<W: Word> DiffusionVisitor<W> for Diffuse {
+			type Output = u128;
+
+			fn visit<F: DiffusionFunc<Word = W>>(self, f: &F) -> u128 { f.diffuse(W::from_sample(&[self.0])).to_u128() }
+		}
+
+		let mut rng = rand::thread_rng();
+		for family in [Family::MXR, Family::MRXR, Family::MRXS, Family::RXSM, Family::MRXSM] {
+			let config = SearchConfig { family, ..Default::default() };
+			let f: AnyDiffusion = AnyDiffusion::random(&config, &mut rng);
+			assert_eq!(family, f.family());
+			assert_eq!(family, f.family().to_string().parse().unwrap());
+			let g = AnyDiffusion::random(&config, &mut rng);
+			for child in f.crossover(&g, &config, &mut rng) {
+				assert_eq!(family, child.family());
+			}
+			for _ in 0..100 {
+				let x = rng.gen();
+				let y = dispatch!(&f, func => func.diffuse(x));
+				assert_eq!(y, f.diffuse(x));
+				assert_eq!(y as u128, f.accept(Diffuse(x)));
+			}
+		}
+	}
+
+	#[test]
+	fn test_parse() {
+		let f: AnyDiffusion = "MRXSM{m1:0xb520c891288cb35, m2:0xb018200835e0008d, s1:21, s2:59}".parse().unwrap();
+		assert_eq!(AnyDiffusion::MRXSM(MRXSM::new(0xb520c891288cb35, 0xb018200835e0008d, 21, 59)), f);
+		let f: AnyDiffusion = "MROR{m:0x3, s:7}".parse().unwrap();
+		assert_eq!(AnyDiffusion::MXR(MXR::new(3, 7)), f);
+		assert_eq!("MROR{m:0x3, s:7}", f.to_string());
+		let f: AnyDiffusion = "RXSM{s2: 60, s1: 3, m: 5}".parse().unwrap();
+		assert_eq!(AnyDiffusion::RXSM(RXSM::new(5, 3, 60)), f);
+		let s = rand::thread_rng().gen_range(0..64_u32);
+		assert_eq!(Ok(AnyDiffusion::MXR(MXR::new(1_u64, s))), format!("MXR{{m:1, s:{}}}", s).parse());
+		let f: AnyDiffusion<u32> = "MRXSM32{m1:0x5, m2:0x7, s1:21, s2:29}".parse().unwrap();
+		assert_eq!(AnyDiffusion::MRXSM(MRXSM::new(5_u32, 7, 21, 29)), f);
+		let f: AnyDiffusion<u128> = "RXSM128{m:0xb520c891288cb35b018200835e0008d, s1:3, s2:124}".parse().unwrap();
+		assert_eq!(AnyDiffusion::RXSM(RXSM::new(0xb520c891288cb35b018200835e0008d_u128, 3, 124)), f);
+	}
+
+	#[test]
+	fn test_parse_errors() {
+		assert_eq!(Err(ParseFuncError::UnknownFamily("MXS".to_string())), "MXS{m:1, s:2}".parse::<AnyDiffusion>());
+		assert_eq!(Err(ParseFuncError::Malformed("MRXS".to_string())), "MRXS".parse::<AnyDiffusion>());
+		assert_eq!(Err(ParseFuncError::MissingParam("s2")), "MRXS{m:1, s1:2}".parse::<AnyDiffusion>());
+		assert_eq!(Err(ParseFuncError::OutOfRange { param: "s", value: 64, min: 0, max: 63 }),
+			"MXR{m:1, s:64}".parse::<AnyDiffusion>());
+		assert_eq!(Err(ParseFuncError::OutOfRange { param: "s1", value: 63, min: 0, max: 62 }),
+			"MRXR{m:1, s1:63, s2:63}".parse::<AnyDiffusion>());
+		assert_eq!(Err(ParseFuncError::WrongFamily { expected: "MRXSM", found: "MRXS".to_string() }),
+			"MRXS{m:1, s1:2, s2:60}".parse::<MRXSM>());
+		assert_eq!(Err(ParseFuncError::WrongWidth { expected: 64, found: 16 }),
+			"MRXS16{m:1, s1:2, s2:14}".parse::<AnyDiffusion>());
+		assert_eq!(Err(ParseFuncError::OutOfRange { param: "m", value: 0x10000, min: 0, max: 0xffff }),
+			"MXR16{m:0x10000, s:3}".parse::<AnyDiffusion<u16>>());
+		assert_eq!(Err(ParseFuncError::OutOfRange { param: "s", value: 16, min: 0, max: 15 }),
+			"MXR16{m:1, s:16}".parse::<AnyDiffusion<u16>>());
+	}
+}
@@ -0,0 +1,285 @@
+use std::{
+	error::Error,
+	fmt::{self, Display, Formatter},
+};
+
+use crate::utils::word::Word;
+
+use super::DiffusionFunc;
+
+
+/** A diffusion function that can be inverted, provided that it actually is a bijection for its parameters. */
+pub trait InvertibleDiffusion: DiffusionFunc {
+	/** Decides whether this function is a bijection, combining algebraic rules with exhaustive checks on reduced-width
+	   models (see `check_data_dependent_shift`). The error describes why it isn't one. */
+	fn check_bijective(&self) -> Result<(), InversionError>;
+
+	#[inline(always)]
+	fn is_bijective(&self) -> bool { self.check_bijective().is_ok() }
+
+	/** Returns the unique `x` with `self.diffuse(x) == y`. Never returns `Ambiguous` or `NoPreimage` if
+	   `check_bijective` succeeds. */
+	fn undiffuse(&self, y: Self::Word) -> Result<Self::Word, InversionError>;
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InversionError {
+	/** The function is not a bijection for its parameters (or for any parameters of its family). */
+	NotBijective(String),
+	/** The value has more than one preimage. */
+	Ambiguous(u128),
+	/** The value has no preimage. */
+	NoPreimage(u128),
+}
+
+impl Display for InversionError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			InversionError::NotBijective(why) => write!(f, "not a bijection: {}", why),
+			InversionError::Ambiguous(y) => write!(f, "{:#x} has more than one preimage", y),
+			InversionError::NoPreimage(y) => write!(f, "{:#x} has no preimage", y),
+		}
+	}
+}
+
+impl Error for InversionError {}
+
+
+/** Returns the inverse of `m` modulo 2<sup>bits</sup>, which exists iff `m` is odd. */
+pub fn mul_inverse<W: Word>(m: W) -> Option<W> {
+	if m & W::ONE == W::ZERO {
+		return None;
+	}
+	// Newton's method: each step doubles the number of correct low bits and m * m == 1 mod 8 already holds.
+	let two = W::ONE << 1;
+	let mut inv = m;
+	let mut correct_bits = 3;
+	while correct_bits < W::BITS {
+		inv = inv.wrapping_mul(two.wrapping_sub(m.wrapping_mul(inv)));
+		correct_bits *= 2;
+	}
+	Some(inv)
+}
+
+pub(super) fn check_multiplier<W: Word>(name: &str, m: W) -> Result<W, InversionError> {
+	mul_inverse(m).ok_or_else(|| InversionError::NotBijective(format!("{} = {:#x} is even", name, m)))
+}
+
+/** `x ^= x.rotate_right(s)` is linear with both 0 and !0 in its kernel, so it is never a bijection (no matter whether
+   `s` depends on `x` or not). */
+pub(super) fn xor_rotation_error() -> InversionError {
+	InversionError::NotBijective(String::from("x ^= x.rotate_right(s) maps both 0 and !0 to 0"))
+}
+
+/** Inverts `x ^= x >> t` for a fixed shift `0 < t < bits`. */
+#[inline(always)]
+pub fn unxorshift<W: Word>(y: W, t: u32) -> W {
+	debug_assert!(t > 0 && t < W::BITS);
+	let mut x = y;
+	let mut shift = t;
+	while shift < W::BITS {
+		x ^= y >> shift;
+		shift += t;
+	}
+	x
+}
+
+/** If `s1 == 0`, the data-dependent shift `x ^= x >> (s1 + (x >> s2))` maps every `x < 2^s2` to 0. */
+fn check_s1(s1: u32) -> Result<(), InversionError> {
+	if s1 == 0 {
+		return Err(InversionError::NotBijective(String::from("s1 = 0 maps every x with x >> s2 == 0 to 0")));
+	}
+	Ok(())
+}
+
+/** Decides whether the data-dependent shift `x ^= x >> (s1 + (x >> s2))` is a bijection.
+
+   For `s1 > 0`, the total shift `t` is positive. Once `t` is known, `x` is uniquely determined by `unxorshift`, and the
+   top `k = bits - s2` bits of `x` (which determine `t`) only depend on the top `k` bits of `y`. So the shift is a
+   bijection iff its reduced-width model `x ^= x >> (s1 + x)` on `k` bits is one, which we check exhaustively. (With the
+   current restrictions on the shifts, that model is the identity since `s1 + x` exceeds the bit length of `x`, so
+   `s1 > 0` is sufficient. The check keeps this honest should the restrictions ever change.) */
+pub(super) fn check_data_dependent_shift<W: Word>(s1: u32, s2: u32) -> Result<(), InversionError> {
+	check_s1(s1)?;
+	let k = W::BITS - s2;
+	debug_assert!(k <= 7);
+	let mut seen = 0_u128;
+	for x in 0..1_u64 << k {
+		let y = x ^ x.checked_shr(s1 + x as u32).unwrap_or(0);
+		if seen & (1 << y) != 0 {
+			return Err(InversionError::NotBijective(format!(
+				"s1 = {} and s2 = {} map several values of the top {} bits to {:#x}", s1, s2, k, y)));
+		}
+		seen |= 1 << y;
+	}
+	Ok(())
+}
+
+/** Inverts the data-dependent shift `x ^= x >> (s1 + (x >> s2))` (with the restrictions on the shifts from `shifts`).
+
+   The total shift `t` depends on the top `bits - s2` bits of `x`, which are unchanged iff `t >= bits - s2`. In general
+   (i.e. if `s1 < bits - s2`), we don't know `t` beforehand but there are only few possible values, so we simply invert
+   the shift for each of them and check which ones are consistent. */
+pub fn unxorshift_data_dependent<W: Word>(y: W, s1: u32, s2: u32) -> Result<W, InversionError> {
+	check_s1(s1)?;
+	let n_selectors = W::MAX.wrapping_shr(s2).low_u32();
+	let mut result = None;
+	for selector in 0..=n_selectors {
+		let t = s1 + selector;
+		let x = unxorshift(y, t);
+		if x.wrapping_shr(s2).low_u32() == selector {
+			if result.is_some() {
+				return Err(InversionError::Ambiguous(y.to_u128()));
+			}
+			result = Some(x);
+		}
+	}
+	result.ok_or(InversionError::NoPreimage(y.to_u128()))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::Rng;
+
+	use super::*;
+	use crate::{
+		config::SearchConfig,
+		diffusion::{
+			any_diffusion::{AnyDiffusion, Family},
+			mrxr::MRXR,
+			mrxs::MRXS,
+			mrxsm::MRXSM,
+			mxr::MXR,
+			rxsm::RXSM,
+		},
+	};
+
+	#[test]
+	fn test_mul_inverse() {
+		let mut rng = rand::thread_rng();
+		assert_eq!(None, mul_inverse(0_u64));
+		assert_eq!(Some(1_u64), mul_inverse(1));
+		for _ in 0..1000 {
+			let m = rng.gen::<u64>() | 1;
+			assert_eq!(1, m.wrapping_mul(mul_inverse(m).unwrap()));
+			assert_eq!(None, mul_inverse(m ^ 1));
+			let m = rng.gen::<u128>() | 1;
+			assert_eq!(1, m.wrapping_mul(mul_inverse(m).unwrap()));
+			let m = rng.gen::<u16>() | 1;
+			assert_eq!(1, m.wrapping_mul(mul_inverse(m).unwrap()));
+		}
+	}
+
+	#[test]
+	fn test_unxorshift() {
+		let mut rng = rand::thread_rng();
+		for t in 1..64 {
+			let x: u64 = rng.gen();
+			assert_eq!(x, unxorshift(x ^ (x >> t), t));
+		}
+	}
+
+	fn check_round_trip<F: InvertibleDiffusion>(f: &F, rng: &mut impl Rng) {
+		if f.check_bijective().is_err() {
+			return;
+		}
+		for _ in 0..100 {
+			let x = F::Word::random(rng);
+			assert_eq!(Ok(x), f.undiffuse(f.diffuse(x)), "{}", f);
+		}
+	}
+
+	fn check_round_trips<W: Word>(rng: &mut impl Rng) {
+		let config = SearchConfig::default();
+		for _ in 0..100 {
+			check_round_trip(&MRXS::<W>::random(&config, rng), rng);
+			check_round_trip(&RXSM::<W>::random(&config, rng), rng);
+			check_round_trip(&MRXSM::<W>::random(&config, rng), rng);
+			for family in [Family::MRXS, Family::RXSM, Family::MRXSM] {
+				let config = SearchConfig { family, ..Default::default() };
+				check_round_trip(&AnyDiffusion::<W>::random(&config, rng), rng);
+			}
+		}
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let mut rng = rand::thread_rng();
+		check_round_trips::<u16>(&mut rng);
+		check_round_trips::<u32>(&mut rng);
+		check_round_trips::<u64>(&mut rng);
+		check_round_trips::<u128>(&mut rng);
+		let f = MRXSM::<u64>::new(0xb520c891288cb35, 0xb018200835e0008d, 21, 59);
+		assert!(f.check_bijective().is_ok());
+		for _ in 0..1000 {
+			let x: u64 = rng.gen();
+			assert_eq!(Ok(x), f.undiffuse(f.diffuse(x)));
+		}
+	}
+
+	/** The data-dependent shift on 16 bits, with the same number of selector bits `k` as the 64-bit one. */
+	fn is_bijective_16(s1: u32, k: u32) -> bool {
+		let mut seen = vec![false; 1 << 16];
+		for x in 0..=u16::MAX {
+			let y = x ^ x.checked_shr(s1 + (x >> (16 - k)) as u32).unwrap_or(0);
+			if seen[y as usize] {
+				return false;
+			}
+			seen[y as usize] = true;
+		}
+		true
+	}
+
+	#[test]
+	fn test_reduced_width_model() {
+		for s1 in 1..8 {
+			for k in 1..=5 {
+				assert_eq!(Ok(()), check_data_dependent_shift::<u64>(s1, 64 - k));
+				assert!(is_bijective_16(s1, k), "s1 = {}, k = {}", s1, k);
+				for _ in 0..1000 {
+					let x = rand::random::<u64>();
+					let y = x ^ x.wrapping_shr(s1 + x.wrapping_shr(64 - k) as u32);
+					assert_eq!(Ok(x), unxorshift_data_dependent(y, s1, 64 - k));
+				}
+			}
+		}
+		// s1 = 0 collapses all x < 2^s2, no matter the width.
+		assert!(!is_bijective_16(0, 4));
+		assert!(check_data_dependent_shift::<u64>(0, 60).is_err());
+	}
+
+	#[test]
+	fn test_exhaustive_16() {
+		let mut rng = rand::thread_rng();
+		let config = SearchConfig::default();
+		for _ in 0..20 {
+			let f = MRXSM::<u16>::random(&config, &mut rng);
+			let mut seen = vec![false; 1 << 16];
+			for x in 0..=u16::MAX {
+				seen[f.diffuse(x) as usize] = true;
+			}
+			assert_eq!(f.is_bijective(), seen.into_iter().all(|s| s), "{}", f);
+		}
+	}
+
+	#[test]
+	fn test_not_bijective() {
+		let mut rng = rand::thread_rng();
+		let config = SearchConfig::default();
+		for _ in 0..100 {
+			let f = MXR::<u64>::random(&config, &mut rng);
+			assert!(matches!(f.check_bijective(), Err(InversionError::NotBijective(_))));
+			assert!(matches!(f.undiffuse(rng.gen()), Err(InversionError::NotBijective(_))));
+			let f = MRXR::<u32>::random(&config, &mut rng);
+			assert!(matches!(f.check_bijective(), Err(InversionError::NotBijective(_))));
+		}
+		assert!(matches!(MRXS::new(2_u64, 21, 59).check_bijective(), Err(InversionError::NotBijective(_))));
+		assert!(matches!(RXSM::new(1_u64, 0, 59).check_bijective(), Err(InversionError::NotBijective(_))));
+		assert!(matches!(MRXSM::new(1_u64, 4, 21, 59).check_bijective(), Err(InversionError::NotBijective(_))));
+		// s1 = 0 maps every x < 2^s2 to 0.
+		assert_eq!(Err(InversionError::NotBijective(String::from("s1 = 0 maps every x with x >> s2 == 0 to 0"))),
+			RXSM::new(1_u64, 0, 63).undiffuse(0));
+	}
+}
@@ -1,11 +1,15 @@
-use std::{arch::x86_64::__m256i, fmt::Display, mem};
+use std::{
+	error::Error,
+	fmt::{self, Display, Formatter},
+};
 
-use aligned_array::{A32, Aligned};
 use rand::Rng;
 
-use crate::globals::{CROSSOVER_BITS, MUTATION_ODDS};
+use crate::{config::SearchConfig, utils::word::Word};
 
 mod shifts;
+pub mod any_diffusion;
+pub mod inverse;
 pub mod mrxsm;
 pub mod mrxs;
 pub mod rxsm;
@@ -13,24 +17,35 @@ pub mod mxr;
 pub mod mrxr;
 
 pub trait DiffusionFunc: Sized + Display {
-    fn diffuse(&self, x: u64) -> u64;
+	/** The width of inputs and outputs. All families are generic over it, with `u64` as the default. */
+	type Word: Word;
 
-	fn diffuse4(&self, xs: __m256i) -> __m256i {
-		let xs = unsafe { mem::transmute::<__m256i, [u64; 4]>(xs) };
-		let mut ys: Aligned<A32, _> = Aligned([0_u64; 4]);
-		for (x, y) in xs.into_iter().zip(ys.iter_mut()) {
-			*y = self.diffuse(x);
-		}
-		unsafe { mem::transmute::<Aligned<A32, [u64; 4]>, __m256i>(ys) }
+    fn diffuse(&self, x: Self::Word) -> Self::Word;
+
+    fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self;
+
+    fn crossover(&self, other: &Self, config: &SearchConfig, rng: &mut impl Rng) -> [Self; 2];
+
+	/** Calls `visitor` with the concrete diffusion function. For all families but `AnyDiffusion`, that's just `self`.
+	   `AnyDiffusion` dispatches (once) to the function it wraps, so that the visitor runs monomorphised code. */
+	#[inline(always)]
+	fn accept<V: DiffusionVisitor<Self::Word>>(&self, visitor: V) -> V::Output {
+		visitor.visit(self)
 	}
+}
 
-    fn random(rng: &mut impl Rng) -> Self;
+/** Code that is generic over the concrete type of a diffusion function on words of type `W`, typically the inner loop
+   of an evaluation. See `DiffusionFunc::accept`. */
+pub trait DiffusionVisitor<W: Word> {
+	type Output;
 
-    fn crossover(&self, other: &Self, rng: &mut impl Rng) -> [Self; 2];
+	fn visit<F: DiffusionFunc<Word = W>>(self, f: &F) -> Self::Output;
 }
 
-fn crossover(parent1: u64, parent2: u64, rng: &mut impl Rng) -> [u64; 2] {
-	let mask = 1_u64.wrapping_shl(CROSSOVER_BITS) - 1;
+/** `config.crossover_bits` is relative to 64-bit words and scaled to the actual width. */
+fn crossover<W: Word>(parent1: W, parent2: W, config: &SearchConfig, rng: &mut impl Rng) -> [W; 2] {
+	let n_bits = (config.crossover_bits * W::BITS / 64).max(1);
+	let mask = W::ONE.wrapping_shl(n_bits).wrapping_sub(W::ONE);
 	// println!("Crossover mask: {:#x}", mask);
 	let mask = mask.rotate_right(rng.gen());
 	let inv_mask = !mask;
@@ -40,11 +55,11 @@ fn crossover(parent1: u64, parent2: u64, rng: &mut impl Rng) -> [u64; 2] {
 }
 
 /** Each bit is flipped with a 1/odds probability. */
-fn mutate(x: u64, rng: &mut impl Rng) -> u64 {
-	let mut mask = 0_u64;
-	let mut bit = 0x8000_0000_0000_0000_u64;
-	for _ in 0..64 {
-		if rng.gen_range(0..MUTATION_ODDS) == 0_u32 {
+fn mutate<W: Word>(x: W, config: &SearchConfig, rng: &mut impl Rng) -> W {
+	let mut mask = W::ZERO;
+	let mut bit = W::ONE << (W::BITS - 1);
+	for _ in 0..W::BITS {
+		if rng.gen_range(0..config.mutation_odds) == 0_u32 {
 			mask |= bit
 		}
 		bit >>= 1;
@@ -52,3 +67,169 @@ fn mutate(x: u64, rng: &mut impl Rng) -> u64 {
 	// println!("Mutating {} bits (expected: {:.2}, mask: {:#x})", mask.count_ones(), 64.0/m as f32, mask);
 	x ^ (x & mask)
 }
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseFuncError {
+	/** The input isn't of the form `NAME{key:value, ...}`. */
+	Malformed(String),
+	/** The name doesn't belong to any family of diffusion functions. */
+	UnknownFamily(String),
+	/** The name belongs to a different family than the one requested. */
+	WrongFamily { expected: &'static str, found: String },
+	/** The function operates on words of a different width (in bits) than the one requested. */
+	WrongWidth { expected: u32, found: u32 },
+	MissingParam(&'static str),
+	UnknownParam(String),
+	DuplicateParam(&'static str),
+	InvalidValue { param: &'static str, value: String },
+	OutOfRange { param: &'static str, value: u128, min: u128, max: u128 },
+}
+
+impl Display for ParseFuncError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			ParseFuncError::Malformed(s) => write!(f, "expected a function of the form NAME{{key:value, ...}}, got {}", s),
+			ParseFuncError::UnknownFamily(name) => write!(f, "unknown family of diffusion functions {}", name),
+			ParseFuncError::WrongFamily { expected, found } => write!(f, "expected a {} function, got {}", expected, found),
+			ParseFuncError::WrongWidth { expected, found } => {
+				write!(f, "expected a function on {}-bit words, got one on {}-bit words", expected, found)
+			}
+			ParseFuncError::MissingParam(param) => write!(f, "missing parameter {}", param),
+			ParseFuncError::UnknownParam(param) => write!(f, "unknown parameter {}", param),
+			ParseFuncError::DuplicateParam(param) => write!(f, "parameter {} is given more than once", param),
+			ParseFuncError::InvalidValue { param, value } => write!(f, "invalid value for {}: {}", param, value),
+			ParseFuncError::OutOfRange { param, value, min, max } => {
+				write!(f, "{} = {} is out of range (must be in {}..={})", param, value, min, max)
+			}
+		}
+	}
+}
+
+impl Error for ParseFuncError {}
+
+/** Writes the name of a family followed by the word width, e.g. `MRXSM32{`. The width is omitted for 64-bit words. */
+fn write_name<W: Word>(f: &mut Formatter, name: &str) -> fmt::Result {
+	if W::BITS == 64 {
+		write!(f, "{}{{", name)
+	} else {
+		write!(f, "{}{}{{", name, W::BITS)
+	}
+}
+
+/** Splits a name written by `write_name` into the name of the family and the word width. */
+fn split_width(name: &str) -> (&str, u32) {
+	let family = name.trim_end_matches(|c: char| c.is_ascii_digit());
+	(family, name[family.len()..].parse().unwrap_or(64))
+}
+
+/** Splits the `Display` output of a diffusion function (e.g. `MRXSM{m1:0x..., m2:0x..., s1:21, s2:59}`) into its name
+   and the part between the braces. */
+fn split_func(s: &str) -> Result<(&str, &str), ParseFuncError> {
+	let malformed = || ParseFuncError::Malformed(s.to_string());
+	let (name, body) = s.trim().split_once('{').ok_or_else(malformed)?;
+	let body = body.strip_suffix('}').ok_or_else(malformed)?;
+	Ok((name.trim(), body))
+}
+
+/** Parses the `Display` output of a diffusion function called `names[0]` (or one of its aliases) on words of type `W`
+   and returns its parameters in the order of `keys`. Parameters may be given in any order and their values either in
+   decimal or in hexadecimal (with a `0x` prefix). */
+fn parse_params<W: Word, const N: usize>(s: &str, names: &[&'static str], keys: [&'static str; N])
+	-> Result<[u128; N], ParseFuncError> {
+	let (name, body) = split_func(s)?;
+	let (family, width) = split_width(name);
+	if !names.contains(&family) {
+		return Err(ParseFuncError::WrongFamily { expected: names[0], found: name.to_string() });
+	}
+	if width != W::BITS {
+		return Err(ParseFuncError::WrongWidth { expected: W::BITS, found: width });
+	}
+	let mut vals = [None; N];
+	for field in body.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+		let (key, val) = field.split_once(':').ok_or_else(|| ParseFuncError::Malformed(s.to_string()))?;
+		let key = key.trim();
+		let i = keys.iter().position(|k| *k == key).ok_or_else(|| ParseFuncError::UnknownParam(key.to_string()))?;
+		if vals[i].is_some() {
+			return Err(ParseFuncError::DuplicateParam(keys[i]));
+		}
+		let val = val.trim();
+		let parsed = match val.strip_prefix("0x") {
+			Some(hex) => u128::from_str_radix(hex, 16),
+			None => val.parse(),
+		};
+		vals[i] = Some(parsed.map_err(|_| ParseFuncError::InvalidValue { param: keys[i], value: val.to_string() })?);
+	}
+	let mut result = [0_u128; N];
+	for ((res, val), key) in result.iter_mut().zip(vals).zip(keys) {
+		*res = val.ok_or(ParseFuncError::MissingParam(key))?;
+	}
+	Ok(result)
+}
+
+/** Checks that a parsed value fits into a word. */
+fn check_word<W: Word>(param: &'static str, value: u128) -> Result<W, ParseFuncError> {
+	W::from_u128(value).ok_or(ParseFuncError::OutOfRange { param, value, min: 0, max: W::MAX.to_u128() })
+}
+
+/** Checks that a parsed shift is in `min..=max`. */
+fn check_range(param: &'static str, value: u128, min: u32, max: u32) -> Result<u32, ParseFuncError> {
+	if value < min as u128 || value > max as u128 {
+		return Err(ParseFuncError::OutOfRange { param, value, min: min as u128, max: max as u128 });
+	}
+	Ok(value as u32)
+}
+
+/** Checks the shifts of the data-dependent shift `x >> (s1 + (x >> s2))` (see `shifts`). */
+fn check_shifts<W: Word>(s1: u128, s2: u128) -> Result<(u32, u32), ParseFuncError> {
+	let s1 = check_range("s1", s1, 0, shifts::max_s1(W::BITS))?;
+	let s2 = check_range("s2", s2, shifts::min_s2(W::BITS, s1), W::BITS - 1)?;
+	Ok((s1, s2))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::{mrxsm::MRXSM, *};
+
+	#[test]
+	fn test_parse_display_output() {
+		let mut rng = rand::thread_rng();
+		let config = SearchConfig::default();
+		for _ in 0..100 {
+			let f = MRXSM::<u64>::random(&config, &mut rng);
+			assert_eq!(f, f.to_string().parse().unwrap());
+		}
+		assert_eq!(Ok([10, 16]), parse_params::<u64, 2>(" F{a:10, b:0x10} ", &["F"], ["a", "b"]));
+		assert_eq!(Ok([1, 10]), parse_params::<u64, 2>("G{b:10, a:1}", &["F", "G"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::MissingParam("b")), parse_params::<u64, 2>("F{a:10}", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::UnknownParam("c".to_string())),
+			parse_params::<u64, 2>("F{a:10, b:1, c:2}", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::DuplicateParam("a")), parse_params::<u64, 2>("F{a:10, a:1}", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::InvalidValue { param: "b", value: "0xg".to_string() }),
+			parse_params::<u64, 2>("F{a:10, b:0xg}", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::WrongFamily { expected: "F", found: "G".to_string() }),
+			parse_params::<u64, 2>("G{a:10, b:1}", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::Malformed("F{a:10, b:1".to_string())),
+			parse_params::<u64, 2>("F{a:10, b:1", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::OutOfRange { param: "s2", value: 10, min: 59, max: 63 }), check_shifts::<u64>(21, 10));
+
+		// Word widths other than 64 bits are part of the name.
+		for _ in 0..100 {
+			let f = MRXSM::<u32>::random(&config, &mut rng);
+			assert!(f.to_string().starts_with("MRXSM32{"));
+			assert_eq!(f, f.to_string().parse().unwrap());
+			let f = MRXSM::<u128>::random(&config, &mut rng);
+			assert_eq!(f, f.to_string().parse().unwrap());
+		}
+		assert_eq!(Ok([1, 2]), parse_params::<u32, 2>("F32{a:1, b:2}", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::WrongWidth { expected: 64, found: 32 }),
+			parse_params::<u64, 2>("F32{a:1, b:2}", &["F"], ["a", "b"]));
+		assert_eq!(Err(ParseFuncError::WrongWidth { expected: 16, found: 64 }),
+			parse_params::<u16, 2>("F{a:1, b:2}", &["F"], ["a", "b"]));
+		assert_eq!(Ok(0xffff_u16), check_word("m", 0xffff));
+		assert_eq!(Err(ParseFuncError::OutOfRange { param: "m", value: 0x10000, min: 0, max: 0xffff }),
+			check_word::<u16>("m", 0x10000));
+		assert_eq!(Err(ParseFuncError::OutOfRange { param: "s2", value: 9, min: 13, max: 15 }), check_shifts::<u16>(5, 9));
+	}
+}
@@ -1,32 +1,35 @@
-use std::{fmt::{self, Display, Formatter}, mem};
+use std::{fmt::{self, Display, Formatter}, mem, str::FromStr};
 
 use rand::Rng;
 
+use crate::{config::SearchConfig, utils::word::Word};
+
 use super::{
-	shifts::{MAX_S1, MIN_S2},
-	DiffusionFunc,
+	shifts,
+	inverse::{self, InversionError, InvertibleDiffusion},
+	DiffusionFunc, ParseFuncError,
 };
 
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct MRXR {
-	pub m: u64,
+pub struct MRXR<W = u64> {
+	pub m: W,
 	pub s1: u32,
 	pub s2: u32,
 }
 
-impl MRXR {
+impl<W: Word> MRXR<W> {
 	#[inline(always)]
-	pub fn new(m: u64, s1: u32, s2: u32) -> Self {
-		let s1 = s1.min(MAX_S1);
-		let s2 = s2.max(MIN_S2[s1 as usize]);
+	pub fn new(m: W, s1: u32, s2: u32) -> Self {
+		let s1 = s1.min(shifts::max_s1(W::BITS));
+		let s2 = s2.max(shifts::min_s2(W::BITS, s1));
 		MRXR { m, s1, s2 }
 	}
 }
 
-impl Display for MRXR {
+impl<W: Word> Display for MRXR<W> {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-		write!(f, "MRXR{{")?;
+		super::write_name::<W>(f, "MRXR")?;
 		write!(f, "m:{:#x}, ", self.m)?;
 		write!(f, "s1:{}, ", self.s1)?;
 		write!(f, "s2:{}", self.s2)?;
@@ -34,28 +37,41 @@ impl Display for MRXR {
 	}
 }
 
-impl DiffusionFunc for MRXR {
+impl<W: Word> FromStr for MRXR<W> {
+	type Err = ParseFuncError;
+
+	/** Parses the output of `Display`. */
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let [m, s1, s2] = super::parse_params::<W, 3>(s, &["MRXR"], ["m", "s1", "s2"])?;
+		let (s1, s2) = super::check_shifts::<W>(s1, s2)?;
+		Ok(Self::new(super::check_word("m", m)?, s1, s2))
+	}
+}
+
+impl<W: Word> DiffusionFunc for MRXR<W> {
+	type Word = W;
+
 	#[inline(always)]
-	fn diffuse(&self, mut x: u64) -> u64 {
-		x = x.overflowing_mul(self.m).0;
-		let s = self.s1 + x.wrapping_shr(self.s2) as u32;
+	fn diffuse(&self, mut x: W) -> W {
+		x = x.wrapping_mul(self.m);
+		let s = self.s1 + x.wrapping_shr(self.s2).low_u32();
 		x ^= x.rotate_right(s);
 		x
 	}
 
 	#[inline(always)]
-	fn random(rng: &mut impl Rng) -> Self {
+	fn random(_config: &SearchConfig, rng: &mut impl Rng) -> Self {
 		// Note: Expected number of set bits in a random integer is half.
-		let m = rng.gen::<u64>() | 1;
-		let s1: u32 = rng.gen_range(0..=MAX_S1);
-		let s2: u32 = rng.gen_range(MIN_S2[s1 as usize]..=63);
+		let m = W::random(rng) | W::ONE;
+		let s1: u32 = rng.gen_range(0..=shifts::max_s1(W::BITS));
+		let s2: u32 = rng.gen_range(shifts::min_s2(W::BITS, s1)..W::BITS);
 		Self::new(m, s1, s2)
 	}
 
 	#[inline(always)]
-	fn crossover(&self, other: &Self, rng: &mut impl Rng) -> [Self; 2] {
+	fn crossover(&self, other: &Self, config: &SearchConfig, rng: &mut impl Rng) -> [Self; 2] {
 		let mut result = [MRXR::default(), MRXR::default()];
-		let ms = super::crossover(self.m, other.m, rng);
+		let ms = super::crossover(self.m, other.m, config, rng);
 		for i in 0..2 {
 			let (mut s_min, mut s_max) = (self.s1, other.s1);
 			if s_min > s_max {
@@ -66,10 +82,21 @@ impl DiffusionFunc for MRXR {
 			if s_min > s_max {
 				mem::swap(&mut s_min, &mut s_max);
 			}
-			let s2: u32 = rng.gen_range(0.max(s_min - 1)..=63.min(s_max + 1));
-			let m = super::mutate(ms[i], rng) | 1;
+			let s2: u32 = rng.gen_range(s_min.saturating_sub(1)..=(W::BITS - 1).min(s_max + 1));
+			let m = super::mutate(ms[i], config, rng) | W::ONE;
 			result[i] = Self::new(m, s1, s2);
 		}
 		result
 	}
 }
+
+impl<W: Word> InvertibleDiffusion for MRXR<W> {
+	/** Never a bijection, see `inverse::xor_rotation_error`. */
+	fn check_bijective(&self) -> Result<(), InversionError> {
+		Err(inverse::xor_rotation_error())
+	}
+
+	fn undiffuse(&self, _y: W) -> Result<W, InversionError> {
+		Err(inverse::xor_rotation_error())
+	}
+}
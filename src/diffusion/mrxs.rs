@@ -2,37 +2,55 @@ use std::{
 	arch::x86_64::{__m256i, _mm256_add_epi64, _mm256_set1_epi64x, _mm256_srlv_epi64, _mm256_xor_epi64},
 	fmt::{self, Display, Formatter},
 	mem,
+	str::FromStr,
 };
 
 use rand::Rng;
 
-use crate::utils;
+use crate::{config::SearchConfig, utils::{self, word::Word}};
 
 use super::{
-	shifts::{MAX_S1, MIN_S2},
-	DiffusionFunc,
+	shifts,
+	inverse::{self, InversionError, InvertibleDiffusion},
+	DiffusionFunc, ParseFuncError,
 };
 
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct MRXS {
-	pub m: u64,
+pub struct MRXS<W = u64> {
+	pub m: W,
 	pub s1: u32,
 	pub s2: u32,
 }
 
-impl MRXS {
+impl<W: Word> MRXS<W> {
 	#[inline(always)]
-	pub fn new(m: u64, s1: u32, s2: u32) -> Self {
-		let s1 = s1.min(MAX_S1);
-		let s2 = s2.max(MIN_S2[s1 as usize]);
+	pub fn new(m: W, s1: u32, s2: u32) -> Self {
+		let s1 = s1.min(shifts::max_s1(W::BITS));
+		let s2 = s2.max(shifts::min_s2(W::BITS, s1));
 		MRXS { m, s1, s2 }
 	}
 }
 
-impl Display for MRXS {
+impl MRXS<u64> {
+	/** Diffuses four words at once using SIMD instructions. */
+	#[inline(always)]
+	pub fn diffuse4(&self, mut xs: __m256i) -> __m256i {
+		unsafe {
+			let m = _mm256_set1_epi64x(self.m as i64);
+			let s1 = _mm256_set1_epi64x(self.s1 as i64);
+			let s2 = _mm256_set1_epi64x(self.s2 as i64);
+			xs = utils::mul_m256i64(xs, m);
+			let s = _mm256_add_epi64(s1, _mm256_srlv_epi64(xs, s2));
+			xs = _mm256_xor_epi64(xs, _mm256_srlv_epi64(xs, s));
+		}
+		xs
+	}
+}
+
+impl<W: Word> Display for MRXS<W> {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-		write!(f, "MRXS{{")?;
+		super::write_name::<W>(f, "MRXS")?;
 		write!(f, "m:{:#x}, ", self.m)?;
 		write!(f, "s1:{}, ", self.s1)?;
 		write!(f, "s2:{}", self.s2)?;
@@ -40,40 +58,40 @@ impl Display for MRXS {
 	}
 }
 
-impl DiffusionFunc for MRXS {
-	#[inline(always)]
-	fn diffuse(&self, mut x: u64) -> u64 {
-		x = x.overflowing_mul(self.m).0;
-		x ^= x.wrapping_shr(self.s1 + x.wrapping_shr(self.s2) as u32);
-		x
+impl<W: Word> FromStr for MRXS<W> {
+	type Err = ParseFuncError;
+
+	/** Parses the output of `Display`. */
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let [m, s1, s2] = super::parse_params::<W, 3>(s, &["MRXS"], ["m", "s1", "s2"])?;
+		let (s1, s2) = super::check_shifts::<W>(s1, s2)?;
+		Ok(Self::new(super::check_word("m", m)?, s1, s2))
 	}
+}
+
+impl<W: Word> DiffusionFunc for MRXS<W> {
+	type Word = W;
 
 	#[inline(always)]
-	fn diffuse4(&self, mut xs: __m256i) -> __m256i {
-		unsafe {
-			let m = _mm256_set1_epi64x(self.m as i64);
-			let s1 = _mm256_set1_epi64x(self.s1 as i64);
-			let s2 = _mm256_set1_epi64x(self.s2 as i64);
-			xs = utils::mul_m256i64(xs, m);
-			let s = _mm256_add_epi64(s1, _mm256_srlv_epi64(xs, s2));
-			xs = _mm256_xor_epi64(xs, _mm256_srlv_epi64(xs, s));
-		}
-		xs
+	fn diffuse(&self, mut x: W) -> W {
+		x = x.wrapping_mul(self.m);
+		x ^= x.wrapping_shr(self.s1 + x.wrapping_shr(self.s2).low_u32());
+		x
 	}
 
 	#[inline(always)]
-	fn random(rng: &mut impl Rng) -> Self {
+	fn random(_config: &SearchConfig, rng: &mut impl Rng) -> Self {
 		// Note: Expected number of set bits in a random integer is half.
-		let m = rng.gen::<u64>() | 1;
-		let s1: u32 = rng.gen_range(0..=MAX_S1);
-		let s2: u32 = rng.gen_range(MIN_S2[s1 as usize]..=63);
+		let m = W::random(rng) | W::ONE;
+		let s1: u32 = rng.gen_range(0..=shifts::max_s1(W::BITS));
+		let s2: u32 = rng.gen_range(shifts::min_s2(W::BITS, s1)..W::BITS);
 		Self::new(m, s1, s2)
 	}
 
 	#[inline(always)]
-	fn crossover(&self, other: &Self, rng: &mut impl Rng) -> [Self; 2] {
+	fn crossover(&self, other: &Self, config: &SearchConfig, rng: &mut impl Rng) -> [Self; 2] {
 		let mut result = [MRXS::default(), MRXS::default()];
-		let ms = super::crossover(self.m, other.m, rng);
+		let ms = super::crossover(self.m, other.m, config, rng);
 		for i in 0..2 {
 			let (mut s_min, mut s_max) = (self.s1, other.s1);
 			if s_min > s_max {
@@ -84,14 +102,27 @@ impl DiffusionFunc for MRXS {
 			if s_min > s_max {
 				mem::swap(&mut s_min, &mut s_max);
 			}
-			let s2: u32 = rng.gen_range(0.max(s_min - 1)..=63.min(s_max + 1));
-			let m = super::mutate(ms[i], rng) | 1;
+			let s2: u32 = rng.gen_range(s_min.saturating_sub(1)..=(W::BITS - 1).min(s_max + 1));
+			let m = super::mutate(ms[i], config, rng) | W::ONE;
 			result[i] = Self::new(m, s1, s2);
 		}
 		result
 	}
 }
 
+impl<W: Word> InvertibleDiffusion for MRXS<W> {
+	fn check_bijective(&self) -> Result<(), InversionError> {
+		inverse::check_multiplier("m", self.m)?;
+		inverse::check_data_dependent_shift::<W>(self.s1, self.s2)
+	}
+
+	fn undiffuse(&self, y: W) -> Result<W, InversionError> {
+		let m_inv = inverse::check_multiplier("m", self.m)?;
+		let x = inverse::unxorshift_data_dependent(y, self.s1, self.s2)?;
+		Ok(x.wrapping_mul(m_inv))
+	}
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -102,8 +133,9 @@ mod tests {
 	#[test]
 	fn test_diffuse4() {
 		let mut rng = rand::thread_rng();
+		let config = SearchConfig::default();
 		for _ in 0..100 {
-			let f = MRXS::random(&mut rng);
+			let f = MRXS::<u64>::random(&config, &mut rng);
 			let xs: Aligned<A32, [u64; 4]> = Aligned(rng.gen());
 			let ds = f.diffuse4(unsafe { mem::transmute::<Aligned<A32, [u64; 4]>, __m256i>(xs.clone()) });
 			let ds = unsafe { mem::transmute::<__m256i, [u64; 4]>(ds) };
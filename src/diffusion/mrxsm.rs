@@ -1,33 +1,36 @@
-use std::{fmt::{self, Display, Formatter}, mem};
+use std::{fmt::{self, Display, Formatter}, mem, str::FromStr};
 
 use rand::Rng;
 
+use crate::{config::SearchConfig, utils::word::Word};
+
 use super::{
-	shifts::{MAX_S1, MIN_S2},
-	DiffusionFunc,
+	shifts,
+	inverse::{self, InversionError, InvertibleDiffusion},
+	DiffusionFunc, ParseFuncError,
 };
 
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct MRXSM {
-	pub m1: u64,
-	pub m2: u64,
+pub struct MRXSM<W = u64> {
+	pub m1: W,
+	pub m2: W,
 	pub s1: u32,
 	pub s2: u32,
 }
 
-impl MRXSM {
+impl<W: Word> MRXSM<W> {
 	#[inline(always)]
-	pub fn new(m1: u64, m2: u64, s1: u32, s2: u32) -> Self {
-		let s1 = s1.min(MAX_S1);
-		let s2 = s2.max(MIN_S2[s1 as usize]);
+	pub fn new(m1: W, m2: W, s1: u32, s2: u32) -> Self {
+		let s1 = s1.min(shifts::max_s1(W::BITS));
+		let s2 = s2.max(shifts::min_s2(W::BITS, s1));
 		MRXSM { m1, m2, s1, s2 }
 	}
 }
 
-impl Display for MRXSM {
+impl<W: Word> Display for MRXSM<W> {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-		write!(f, "MRXSM{{")?;
+		super::write_name::<W>(f, "MRXSM")?;
 		write!(f, "m1:{:#x}, ", self.m1)?;
 		write!(f, "m2:{:#x}, ", self.m2)?;
 		write!(f, "s1:{}, ", self.s1)?;
@@ -36,35 +39,48 @@ impl Display for MRXSM {
 	}
 }
 
-impl DiffusionFunc for MRXSM {
+impl<W: Word> FromStr for MRXSM<W> {
+	type Err = ParseFuncError;
+
+	/** Parses the output of `Display`. */
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let [m1, m2, s1, s2] = super::parse_params::<W, 4>(s, &["MRXSM"], ["m1", "m2", "s1", "s2"])?;
+		let (s1, s2) = super::check_shifts::<W>(s1, s2)?;
+		Ok(Self::new(super::check_word("m1", m1)?, super::check_word("m2", m2)?, s1, s2))
+	}
+}
+
+impl<W: Word> DiffusionFunc for MRXSM<W> {
+	type Word = W;
+
 	#[inline(always)]
-	fn diffuse(&self, mut x: u64) -> u64 {
-		x = x.overflowing_mul(self.m1).0;
+	fn diffuse(&self, mut x: W) -> W {
+		x = x.wrapping_mul(self.m1);
 		// Generates many more instructions
 		// x ^= x.checked_shr(s1 + (x.checked_shr(s2).unwrap_or(0) as u32)).unwrap_or(0);
 		// x ^= x >> (s1 + ((x >> s2) as u32));
 
 		// This is the default (scalar) behaviour on x86 (shifting by 64 is the same as not shifting at all)
-		x ^= x.wrapping_shr(self.s1 + x.wrapping_shr(self.s2) as u32);
-		x = x.overflowing_mul(self.m2).0;
+		x ^= x.wrapping_shr(self.s1 + x.wrapping_shr(self.s2).low_u32());
+		x = x.wrapping_mul(self.m2);
 		x
 	}
 
 	#[inline(always)]
-	fn random(rng: &mut impl Rng) -> Self {
+	fn random(_config: &SearchConfig, rng: &mut impl Rng) -> Self {
 		// Note: Expected number of set bits in a random integer is half.
-		let m1 = rng.gen::<u64>() | 1;
-		let m2 = rng.gen::<u64>() | 1;
-		let s1: u32 = rng.gen_range(0..=MAX_S1);
-		let s2: u32 = rng.gen_range(MIN_S2[s1 as usize]..=63);
+		let m1 = W::random(rng) | W::ONE;
+		let m2 = W::random(rng) | W::ONE;
+		let s1: u32 = rng.gen_range(0..=shifts::max_s1(W::BITS));
+		let s2: u32 = rng.gen_range(shifts::min_s2(W::BITS, s1)..W::BITS);
 		Self::new(m1, m2, s1, s2)
 	}
 
 	#[inline(always)]
-	fn crossover(&self, other: &Self, rng: &mut impl Rng) -> [Self; 2] {
+	fn crossover(&self, other: &Self, config: &SearchConfig, rng: &mut impl Rng) -> [Self; 2] {
 		let mut result = [MRXSM::default(), MRXSM::default()];
-		let m1s = super::crossover(self.m1, other.m1, rng);
-		let m2s = super::crossover(self.m2, other.m2, rng);
+		let m1s = super::crossover(self.m1, other.m1, config, rng);
+		let m2s = super::crossover(self.m2, other.m2, config, rng);
 		for i in 0..2 {
 			let (mut s_min, mut s_max) = (self.s1, other.s1);
 			if s_min > s_max {
@@ -75,11 +91,26 @@ impl DiffusionFunc for MRXSM {
 			if s_min > s_max {
 				mem::swap(&mut s_min, &mut s_max);
 			}
-			let s2: u32 = rng.gen_range(0.max(s_min - 1)..=63.min(s_max + 1));
-			let m1 = super::mutate(m1s[i], rng) | 1;
-			let m2 = super::mutate(m2s[i], rng) | 1;
+			let s2: u32 = rng.gen_range(s_min.saturating_sub(1)..=(W::BITS - 1).min(s_max + 1));
+			let m1 = super::mutate(m1s[i], config, rng) | W::ONE;
+			let m2 = super::mutate(m2s[i], config, rng) | W::ONE;
 			result[i] = Self::new(m1, m2, s1, s2);
 		}
 		result
 	}
 }
+
+impl<W: Word> InvertibleDiffusion for MRXSM<W> {
+	fn check_bijective(&self) -> Result<(), InversionError> {
+		inverse::check_multiplier("m1", self.m1)?;
+		inverse::check_multiplier("m2", self.m2)?;
+		inverse::check_data_dependent_shift::<W>(self.s1, self.s2)
+	}
+
+	fn undiffuse(&self, y: W) -> Result<W, InversionError> {
+		let m1_inv = inverse::check_multiplier("m1", self.m1)?;
+		let m2_inv = inverse::check_multiplier("m2", self.m2)?;
+		let x = inverse::unxorshift_data_dependent(y.wrapping_mul(m2_inv), self.s1, self.s2)?;
+		Ok(x.wrapping_mul(m1_inv))
+	}
+}
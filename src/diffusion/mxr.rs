@@ -1,64 +1,93 @@
 use std::{
 	fmt::{self, Display, Formatter},
 	mem,
+	str::FromStr,
 };
 
 use rand::Rng;
 
-use super::DiffusionFunc;
+use crate::{config::SearchConfig, utils::word::Word};
+
+use super::{
+	inverse::{self, InversionError, InvertibleDiffusion},
+	DiffusionFunc, ParseFuncError,
+};
 
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct MXR {
-	pub m: u64,
+pub struct MXR<W = u64> {
+	pub m: W,
 	pub s: u32,
 }
 
-impl MXR {
+impl<W: Word> MXR<W> {
 	#[inline(always)]
-	pub fn new(m: u64, s: u32) -> Self {
-		let s = s.min(63);
+	pub fn new(m: W, s: u32) -> Self {
+		let s = s.min(W::BITS - 1);
 		MXR { m, s }
 	}
 }
 
-impl Display for MXR {
+impl<W: Word> Display for MXR<W> {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-		write!(f, "MROR{{")?;
+		super::write_name::<W>(f, "MROR")?;
 		write!(f, "m:{:#x}, ", self.m)?;
 		write!(f, "s:{}", self.s)?;
 		write!(f, "}}")
 	}
 }
 
-impl DiffusionFunc for MXR {
+impl<W: Word> FromStr for MXR<W> {
+	type Err = ParseFuncError;
+
+	/** Parses the output of `Display` (also accepting the name of the type, `MXR`, in place of `MROR`). */
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let [m, s] = super::parse_params::<W, 2>(s, &["MROR", "MXR"], ["m", "s"])?;
+		Ok(Self::new(super::check_word("m", m)?, super::check_range("s", s, 0, W::BITS - 1)?))
+	}
+}
+
+impl<W: Word> DiffusionFunc for MXR<W> {
+	type Word = W;
+
 	#[inline(always)]
-	fn diffuse(&self, mut x: u64) -> u64 {
-		x = x.overflowing_mul(self.m).0;
+	fn diffuse(&self, mut x: W) -> W {
+		x = x.wrapping_mul(self.m);
 		x ^= x.rotate_right(self.s);
 		x
 	}
 	#[inline(always)]
-	fn random(rng: &mut impl Rng) -> Self {
+	fn random(_config: &SearchConfig, rng: &mut impl Rng) -> Self {
 		// Note: Expected number of set bits in a random integer is half.
-		let m = rng.gen::<u64>() | 1;
-		let s: u32 = rng.gen_range(0..64);
+		let m = W::random(rng) | W::ONE;
+		let s: u32 = rng.gen_range(0..W::BITS);
 		Self::new(m, s)
 	}
 
 	#[inline(always)]
-	fn crossover(&self, other: &Self, rng: &mut impl Rng) -> [Self; 2] {
+	fn crossover(&self, other: &Self, config: &SearchConfig, rng: &mut impl Rng) -> [Self; 2] {
 		let mut result = [MXR::default(), MXR::default()];
-		let ms = super::crossover(self.m, other.m, rng);
+		let ms = super::crossover(self.m, other.m, config, rng);
 		for i in 0..2 {
 			let (mut s_min, mut s_max) = (self.s, other.s);
 			if s_min > s_max {
 				mem::swap(&mut s_min, &mut s_max);
 			}
-			let m = super::mutate(ms[i], rng) | 1;
+			let m = super::mutate(ms[i], config, rng) | W::ONE;
 			let s = rng.gen_range(s_min..=s_max);
 			result[i] = Self::new(m, s);
 		}
 		result
 	}
 }
+
+impl<W: Word> InvertibleDiffusion for MXR<W> {
+	/** Never a bijection, see `inverse::xor_rotation_error`. */
+	fn check_bijective(&self) -> Result<(), InversionError> {
+		Err(inverse::xor_rotation_error())
+	}
+
+	fn undiffuse(&self, _y: W) -> Result<W, InversionError> {
+		Err(inverse::xor_rotation_error())
+	}
+}
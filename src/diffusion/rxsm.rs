@@ -1,32 +1,35 @@
-use std::{fmt::{self, Display, Formatter}, mem};
+use std::{fmt::{self, Display, Formatter}, mem, str::FromStr};
 
 use rand::Rng;
 
+use crate::{config::SearchConfig, utils::word::Word};
+
 use super::{
-	shifts::{MAX_S1, MIN_S2},
-	DiffusionFunc,
+	shifts,
+	inverse::{self, InversionError, InvertibleDiffusion},
+	DiffusionFunc, ParseFuncError,
 };
 
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct RXSM {
-	pub m: u64,
+pub struct RXSM<W = u64> {
+	pub m: W,
 	pub s1: u32,
 	pub s2: u32,
 }
 
-impl RXSM {
+impl<W: Word> RXSM<W> {
 	#[inline(always)]
-	pub fn new(m: u64, s1: u32, s2: u32) -> Self {
-		let s1 = s1.min(MAX_S1);
-		let s2 = s2.max(MIN_S2[s1 as usize]);
+	pub fn new(m: W, s1: u32, s2: u32) -> Self {
+		let s1 = s1.min(shifts::max_s1(W::BITS));
+		let s2 = s2.max(shifts::min_s2(W::BITS, s1));
 		RXSM { m, s1, s2 }
 	}
 }
 
-impl Display for RXSM {
+impl<W: Word> Display for RXSM<W> {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-		write!(f, "RXSM{{")?;
+		super::write_name::<W>(f, "RXSM")?;
 		write!(f, "m:{:#x}, ", self.m)?;
 		write!(f, "s1:{}, ", self.s1)?;
 		write!(f, "s2:{}", self.s2)?;
@@ -34,26 +37,39 @@ impl Display for RXSM {
 	}
 }
 
-impl DiffusionFunc for RXSM {
+impl<W: Word> FromStr for RXSM<W> {
+	type Err = ParseFuncError;
+
+	/** Parses the output of `Display`. */
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let [m, s1, s2] = super::parse_params::<W, 3>(s, &["RXSM"], ["m", "s1", "s2"])?;
+		let (s1, s2) = super::check_shifts::<W>(s1, s2)?;
+		Ok(Self::new(super::check_word("m", m)?, s1, s2))
+	}
+}
+
+impl<W: Word> DiffusionFunc for RXSM<W> {
+	type Word = W;
+
 	#[inline(always)]
-	fn diffuse(&self, mut x: u64) -> u64 {
-		x ^= x.wrapping_shr(self.s1 + x.wrapping_shr(self.s2) as u32);
-		x = x.overflowing_mul(self.m).0;
+	fn diffuse(&self, mut x: W) -> W {
+		x ^= x.wrapping_shr(self.s1 + x.wrapping_shr(self.s2).low_u32());
+		x = x.wrapping_mul(self.m);
 		x
 	}
 	#[inline(always)]
-	fn random(rng: &mut impl Rng) -> Self {
+	fn random(_config: &SearchConfig, rng: &mut impl Rng) -> Self {
 		// Note: Expected number of set bits in a random integer is half.
-		let m = rng.gen::<u64>() | 1;
-		let s1: u32 = rng.gen_range(0..=MAX_S1);
-		let s2: u32 = rng.gen_range(MIN_S2[s1 as usize]..=63);
+		let m = W::random(rng) | W::ONE;
+		let s1: u32 = rng.gen_range(0..=shifts::max_s1(W::BITS));
+		let s2: u32 = rng.gen_range(shifts::min_s2(W::BITS, s1)..W::BITS);
 		Self::new(m, s1, s2)
 	}
 
 	#[inline(always)]
-	fn crossover(&self, other: &Self, rng: &mut impl Rng) -> [Self; 2] {
+	fn crossover(&self, other: &Self, config: &SearchConfig, rng: &mut impl Rng) -> [Self; 2] {
 		let mut result = [RXSM::default(), RXSM::default()];
-		let ms = super::crossover(self.m, other.m, rng);
+		let ms = super::crossover(self.m, other.m, config, rng);
 		for i in 0..2 {
 			let (mut s_min, mut s_max) = (self.s1, other.s1);
 			if s_min > s_max {
@@ -64,10 +80,22 @@ impl DiffusionFunc for RXSM {
 			if s_min > s_max {
 				mem::swap(&mut s_min, &mut s_max);
 			}
-			let s2: u32 = rng.gen_range(0.max(s_min - 1)..=63.min(s_max + 1));
-			let m = super::mutate(ms[i], rng) | 1;
+			let s2: u32 = rng.gen_range(s_min.saturating_sub(1)..=(W::BITS - 1).min(s_max + 1));
+			let m = super::mutate(ms[i], config, rng) | W::ONE;
 			result[i] = Self::new(m, s1, s2);
 		}
 		result
 	}
 }
+
+impl<W: Word> InvertibleDiffusion for RXSM<W> {
+	fn check_bijective(&self) -> Result<(), InversionError> {
+		inverse::check_multiplier("m", self.m)?;
+		inverse::check_data_dependent_shift::<W>(self.s1, self.s2)
+	}
+
+	fn undiffuse(&self, y: W) -> Result<W, InversionError> {
+		let m_inv = inverse::check_multiplier("m", self.m)?;
+		inverse::unxorshift_data_dependent(y.wrapping_mul(m_inv), self.s1, self.s2)
+	}
+}
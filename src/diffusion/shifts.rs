@@ -1,24 +1,21 @@
 /* There is a problem with right bit-shift being inconsistent on x86. For scalar operands, both SHRX and SHRD mask
  * the count (number of bits to shift), meaning that e.g. shifting by 64 is the same as not shifting at all.
  * On the other hand, VPSRLQ (a.k.a. _mm256_srl_epi64 & _mm256_srli_epi64) returns 0 if the shift is > 63.
- * We could use Rust's checked_shr but that leads to additional instructions. Let's just restrict to a shift < 64. */
-pub const MAX_TOTAL_SHIFT: u32 = 63;
+ * We could use Rust's checked_shr but that leads to additional instructions. Let's just restrict to a shift < 64
+ * (or, in general, to a shift smaller than the word width `bits`). */
+#[inline(always)]
+pub const fn max_total_shift(bits: u32) -> u32 { bits - 1 }
 
 /* This also means that we need to restict s1 because the total shift is s1 + (x >> s2) > s1 (we can't shift x to 0). */
-pub const MAX_S1: u32 = MAX_TOTAL_SHIFT - 1;
-
-
-const fn init_min_s2() -> [u32; MAX_S1 as usize + 1] {
-	let mut min_s2: [u32; MAX_S1 as usize + 1] = [0; MAX_S1 as usize + 1];
-	let mut s1 = 0_u32;
-	while s1 <= MAX_S1 {
-		let log2_floor = 31 - (MAX_TOTAL_SHIFT + 1 - s1).leading_zeros() as i32;
-		min_s2[s1 as usize] = 64 - log2_floor as u32;
-		s1 += 1;
-	}
-	min_s2
+#[inline(always)]
+pub const fn max_s1(bits: u32) -> u32 { max_total_shift(bits) - 1 }
+
+/* To ensure x >> (s1 + (x >> s2)) has total shift <= m, need s2 >= bits - log2(m + 1 - s1). */
+#[inline(always)]
+pub const fn min_s2(bits: u32, s1: u32) -> u32 {
+	let log2_floor = 31 - (max_total_shift(bits) + 1 - s1).leading_zeros();
+	bits - log2_floor
 }
-pub const MIN_S2: [u32; MAX_S1 as usize + 1] = init_min_s2();
 
 
 
@@ -29,20 +26,34 @@ mod tests {
 
 	use super::*;
 
-	fn calc_min_s2(s1: u32) -> u32 {
-		debug_assert!(s1 <= MAX_S1);
-		// To ensure x >> (s1 + (x >> s2)) has total shift <= m, need s2 >= 64 - log2(m + 1 - s1)
-		64 - (MAX_TOTAL_SHIFT + 1 - s1).log2_floor() as u32
+	/** The table we used to hardcode for 64-bit words. */
+	const MIN_S2_64: [u32; 63] = init_min_s2_64();
+
+	const fn init_min_s2_64() -> [u32; 63] {
+		let mut min_s2: [u32; 63] = [0; 63];
+		let mut s1 = 0_u32;
+		while s1 <= 62 {
+			let log2_floor = 31 - (63 + 1 - s1).leading_zeros() as i32;
+			min_s2[s1 as usize] = 64 - log2_floor as u32;
+			s1 += 1;
+		}
+		min_s2
 	}
 
 	#[test]
 	fn test_max_shift() {
-		for s1 in 0_u32..=MAX_S1 {
-			let s2 = MIN_S2[s1 as usize];
-			print!("{}, {}", s1, s2);
-			assert_eq!(s2, calc_min_s2(s1));
-			let s = s1 + u64::MAX.wrapping_shr(s2) as u32;
-			assert!(s <= MAX_TOTAL_SHIFT);
+		for (s1, &s2) in MIN_S2_64.iter().enumerate() {
+			assert_eq!(s2, min_s2(64, s1 as u32));
+			assert_eq!(s2, 64 - (64 - s1 as u32).log2_floor() as u32);
+		}
+		for bits in [16_u32, 32, 64, 128] {
+			for s1 in 0..=max_s1(bits) {
+				let s2 = min_s2(bits, s1);
+				let s = s1 + u128::MAX.wrapping_shr(128 - bits + s2) as u32;
+				assert!(s <= max_total_shift(bits), "bits: {}, s1: {}", bits, s1);
+				// s2 is as small as possible.
+				assert!(s1 + u128::MAX.wrapping_shr(128 - bits + s2 - 1) as u32 > max_total_shift(bits));
+			}
 		}
 	}
 }
@@ -1,31 +1,37 @@
-#![allow(dead_code)]
+#[cfg(test)]
 use aligned_array::{Aligned, A32};
 use std::{arch::x86_64::*, fmt::Display, mem};
 
-use crate::{diffusion::DiffusionFunc, utils::{self, bitset}};
+use crate::{
+	diffusion::{DiffusionFunc, DiffusionVisitor},
+	utils::{self, bitset, word::{Chunk, Word}},
+};
+
 
+/** For each input bit (row), how often each output bit (column) flipped when the input bit was flipped. Each row is
+   stored as `W::BITS / 8` aligned chunks of eight counters. The buffer is sized by the word width, so that counting
+   doesn't allocate.
 
-/** OBS: These are mirrored from how they apear in our Jupyter notebook because that's more cache efficient. */
+   OBS: These are mirrored from how they apear in our Jupyter notebook because that's more cache efficient. */
 #[derive(Debug, PartialEq, Eq)]
-pub struct AvalancheDiagram {
+pub struct AvalancheDiagram<W: Word> {
 	expected: u32,
-	vals: Aligned<A32, [[u32; 64]; 64]>,
+	vals: W::AvalancheCounts,
 }
 
-impl Display for AvalancheDiagram {
+impl<W: Word> Display for AvalancheDiagram<W> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		writeln!(f, "AvalancheDiagram{{vals:")?;
         write!(f, "[")?;
-		for i in 0..64 {
-			let row = &self.vals[i];
+		let n_rows = W::BITS as usize;
+		for (i, row) in self.rows().enumerate() {
 			write!(f, "[")?;
-			#[allow(clippy::needless_range_loop)]
-			for j in 0..64 {
-				let x = row[j];
+			let mut vals = row.iter().flat_map(|chunk| chunk.iter()).peekable();
+			while let Some(x) = vals.next() {
 				write!(f, "{}", x)?;
-				if j != 63 { write!(f, ",")?; }
+				if vals.peek().is_some() { write!(f, ",")?; }
 			}
-			if i == 63 { writeln!(f, "]]")?; }
+			if i == n_rows - 1 { writeln!(f, "]]")?; }
 			else { writeln!(f, "],")?; }
 		}
 
@@ -34,13 +40,25 @@ impl Display for AvalancheDiagram {
 }
 
 
-impl AvalancheDiagram {
+impl AvalancheDiagram<u64> {
+	/** A diagram of a function on 64-bit words. */
+	#[cfg(test)]
 	pub fn new(n_samples: u32, vals: Aligned<A32, [[u32; 64]; 64]>) -> Self {
+		let mut chunks = u64::ZERO_AVALANCHE_COUNTS;
+		for (chunk, c) in chunks.iter_mut().zip(vals.iter().flat_map(|row| row.chunks_exact(8))) {
+			chunk.copy_from_slice(c);
+		}
+		Self::from_chunks(n_samples, chunks)
+	}
+}
+
+impl<W: Word> AvalancheDiagram<W> {
+	fn from_chunks(n_samples: u32, vals: W::AvalancheCounts) -> Self {
 		#[cfg(debug_assertions)]
 		{
 			assert!(n_samples & 1 == 0); // only allow even number of samples
-			for row in vals.iter() {
-				for &val in row {
+			for chunk in vals.as_ref().iter() {
+				for &val in chunk.iter() {
 					assert!(val <= n_samples);
 				}
 			}
@@ -48,13 +66,32 @@ impl AvalancheDiagram {
 		Self { expected: n_samples >> 1, vals }
 	}
 
-	pub fn of(f: &impl DiffusionFunc, samples: &[u64]) -> Self {
-		let mut vals: Aligned<A32, _> = Aligned([[0_u32; 64]; 64]);
-		for &x in samples {
+	/** The chunks of all rows. */
+	#[inline(always)]
+	fn chunks(&self) -> &[Chunk] {
+		self.vals.as_ref()
+	}
+
+	#[inline(always)]
+	fn rows(&self) -> impl Iterator<Item = &[Chunk]> {
+		self.chunks().chunks_exact(W::BITS as usize / 8)
+	}
+
+	/** Words are built from `W::SAMPLE_LEN` consecutive samples. */
+	#[inline(always)]
+	pub fn of(f: &impl DiffusionFunc<Word = W>, samples: &[u64]) -> Self {
+		f.accept(AvalancheOf(samples))
+	}
+
+	fn count<F: DiffusionFunc<Word = W>>(f: &F, samples: &[u64]) -> Self {
+		let row_len = W::BITS as usize / 8;
+		let mut vals = W::ZERO_AVALANCHE_COUNTS;
+		let mut n_samples = 0_u32;
+		for sample in samples.chunks_exact(W::SAMPLE_LEN) {
+			let x = W::from_sample(sample);
 			let h = f.diffuse(x);
-			for shift in 0..64 {
-				let row = &mut vals[shift];
-				let mut diff = (h ^ f.diffuse(x ^ (1 << shift))) as u64;
+			for (shift, row) in vals.as_mut().chunks_exact_mut(row_len).enumerate() {
+				let mut diff = (h ^ f.diffuse(x ^ (W::ONE << shift as u32))).to_u128();
 				// Very slow
 				// loop {
 				//     let first_set = diff.trailing_zeros();
@@ -70,15 +107,15 @@ impl AvalancheDiagram {
 				// 	diff ^= least_set_bit;
 				// }
                 // Fast
-                for k in (0..64).step_by(16) {
+                for chunks in row.chunks_exact_mut(2) {
                     let byte1 = diff as u8;
                     let byte2 = (diff >> 8) as u8;
                     diff >>= 16;
                     unsafe {
                         let bits1 = mem::transmute::<[u32; 8], __m256i>(*bitset::get_set_bits(byte1));
                         let bits2 = mem::transmute::<[u32; 8], __m256i>(*bitset::get_set_bits(byte2));
-                        let ptr1 = row.as_mut_ptr().offset(k);
-                        let ptr2 = ptr1.offset(8);
+                        let ptr1 = chunks[0].as_mut_ptr();
+                        let ptr2 = chunks[1].as_mut_ptr();
                         let prev1 = utils::read_m256i(ptr1);
                         let prev2 = utils::read_m256i(ptr2);
                         utils::write_m256i(_mm256_add_epi32(prev1, bits1), ptr1);
@@ -86,15 +123,18 @@ impl AvalancheDiagram {
                     }
                 }
 			}
+			n_samples += 1;
 		}
-		Self::new(samples.len() as u32, vals)
+		Self::from_chunks(n_samples, vals)
 	}
 
+	/** A straightforward (but slow) implementation of `sse`. */
+	#[cfg(test)]
 	pub fn sse_reference(&self) -> f32 {
 		debug_assert!(self.expected < 1024);
 		let mut result = 0_f32;
-		for row in self.vals.iter() {
-			for val in row {
+		for chunk in self.chunks().iter() {
+			for val in chunk.iter() {
 				let err = val.wrapping_sub(self.expected);
 				let err = err.wrapping_mul(err);
 				result += err as f32/self.expected as f32;
@@ -110,28 +150,36 @@ impl AvalancheDiagram {
 			let mut accum = _mm256_set1_epi32(0);
 			/* I tried unrolling the inner loop, adding a second accumulator and having one big loop instead of nesting
 			them. None of it seems to make any difference. */
-			for i in 0..64 {
-				let row = &self.vals[i];
-				let mut row_ptr = row.as_ptr();
-				for _ in 0..8 {
-					// let vals = _mm256_load_epi32(row_ptr as *const i32);  // tanks performance
-					let vals = utils::read_m256i(row_ptr); // much faster
-					let err = _mm256_sub_epi32(vals, exp);
-					// NOTE: _mm256_mul_epi32 operates on low 32 bits of 4 64-bit ints; so that's not what we want.
-					let err = _mm256_mullo_epi32(err, err);
-					accum = _mm256_add_epi32(accum, err);
-					row_ptr = row_ptr.offset(8);
-				}
+			for chunk in self.chunks().iter() {
+				// let vals = _mm256_load_epi32(row_ptr as *const i32);  // tanks performance
+				let vals = utils::read_m256i(chunk.as_ptr()); // much faster
+				let err = _mm256_sub_epi32(vals, exp);
+				// NOTE: _mm256_mul_epi32 operates on low 32 bits of 4 64-bit ints; so that's not what we want.
+				let err = _mm256_mullo_epi32(err, err);
+				accum = _mm256_add_epi32(accum, err);
 			}
-			// Note: self.expected < 2**10 and f32 is exact for integers < 2**23.
-			utils::hsum_m256i32(accum) as u32 as f32/self.expected as f32
+			/* Note: self.expected < 2**10, so each lane sums at most bits**2 / 8 <= 2**11 squared errors < 2**20 and
+			fits into a u32. Their total might not, so we only add them up as u64. */
+			let total: u64 = utils::m256i_to_u32x8(accum).iter().map(|&x| x as u64).sum();
+			total as f32/self.expected as f32
 		}
 	}
 }
 
+
+/** Dispatches `AvalancheDiagram::of` to the concrete type of the diffusion function. */
+struct AvalancheOf<'a>(&'a [u64]);
+
+impl<W: Word> DiffusionVisitor<W> for AvalancheOf<'_> {
+	type Output = AvalancheDiagram<W>;
+
+	#[inline(always)]
+	fn visit<F: DiffusionFunc<Word = W>>(self, f: &F) -> AvalancheDiagram<W> { AvalancheDiagram::count(f, self.0) }
+}
+
 #[cfg(test)]
 mod tests {
-	use crate::diffusion::mrxsm::MRXSM;
+	use crate::{config::SearchConfig, diffusion::{mrxsm::MRXSM, DiffusionFunc}};
 
 use super::*;
 	use rand::Rng;
@@ -183,7 +231,7 @@ use super::*;
 
 	#[test]
 	fn avalanche_diagram_of_func() {
-		let f = MRXSM::new(0x6eed0e9da4d94a4f, 0x6eed0e9da4d94a4f, 32, 60);
+		let f = MRXSM::new(0x6eed0e9da4d94a4f_u64, 0x6eed0e9da4d94a4f, 32, 60);
 		let mut rng = rand::thread_rng();
 		const N_SAMPLES: usize = 1000;
 		let mut samples = [0_u64; N_SAMPLES];
@@ -197,4 +245,22 @@ use super::*;
 		assert!(sse <= 2200.0);
 	}
 
+	#[test]
+	fn test_word_widths() {
+		let mut rng = rand::thread_rng();
+		let samples: Vec<u64> = (0..1000).map(|_| rng.gen()).collect();
+		let f = MRXSM::new(0x6eed0e9da4d94a4f_u64, 0x6eed0e9da4d94a4f, 32, 60);
+		let diag = AvalancheDiagram::of(&f, &samples);
+		assert_eq!(64 * 8, diag.chunks().len());
+		let f = MRXSM::<u16>::random(&SearchConfig::default(), &mut rng);
+		let diag = AvalancheDiagram::of(&f, &samples);
+		assert_eq!(16 * 2, diag.chunks().len());
+		assert_eq!(500, diag.expected);
+		assert!((diag.sse() - diag.sse_reference()).abs() / diag.sse() < 0.001);
+		let f = MRXSM::<u128>::random(&SearchConfig::default(), &mut rng);
+		let diag = AvalancheDiagram::of(&f, &samples);
+		assert_eq!(128 * 16, diag.chunks().len());
+		assert_eq!(250, diag.expected);
+		assert!((diag.sse() - diag.sse_reference()).abs() / diag.sse() < 0.001);
+	}
 }
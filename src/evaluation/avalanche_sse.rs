@@ -0,0 +1,160 @@
+use std::fmt::{self, Display, Formatter};
+
+use rand::Rng;
+
+use crate::{
+	config::SearchConfig, diffusion::DiffusionFunc,
+	evaluation::{avalanche::AvalancheDiagram, stats::{self, RoundStats}},
+	utils::word::Word,
+};
+
+use super::Evaluator;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+
+/** 'Evaluator' that uses the sum of squared errors of the avalanche diagram as its loss function. Given a perfectly
+   random function, flipping any input bit flips every output bit with probability 1/2 (strict avalanche criterion). The
+   avalanche diagram counts, for each pair of input and output bit, how often that happened and the loss is the squared
+   deviation from the expected count, summed over all pairs (and scaled by the expected count, so that it doesn't grow
+   with the number of samples). As for the Wasserstein evaluators, we subdivide the samples and average over the rounds.
+
+   Over the course of the genetic algorithm the losses are averaged over the generations that this 'Evaluator' lives.
+   With `GEOM == false`, that's the arithmetic mean, otherwise the geometric mean (see `WassersteinArith` and
+   `WassersteinGeom` for the trade-off). */
+pub struct AvalancheSse<F: DiffusionFunc, const GEOM: bool = false> {
+	/** The sum of the losses so far (of `log2(loss + 1)` for the geometric mean). */
+	sum: f32,
+	/** The SSE of each round, for the standard error of the loss. */
+	#[serde(default)]
+	rounds: RoundStats,
+	loss: f32,
+	age: u32,
+	func: F
+}
+
+impl<F: DiffusionFunc, const GEOM: bool> AvalancheSse<F, GEOM> {
+	/** Folds the loss of the latest generation into the running mean and returns the updated mean. */
+	#[inline(always)]
+	fn update_loss(&mut self, loss: f32) -> f32 {
+		self.age += 1;
+		self.loss = if GEOM {
+			self.sum += (loss + 1.0).log2();
+			(self.sum / self.age as f32).exp2() - 1.0
+		} else {
+			self.sum += loss;
+			self.sum / self.age as f32
+		};
+		self.loss
+	}
+}
+
+impl<F: DiffusionFunc, const GEOM: bool> Evaluator<F> for AvalancheSse<F, GEOM> {
+	fn new(func: F) -> Self {
+		Self { sum: 0.0, rounds: RoundStats::default(), loss: f32::MAX, age: 0, func }
+	}
+
+	fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self {
+		Self::new(F::random(config, rng))
+	}
+
+	fn get_age(&self) -> u32 { self.age }
+
+	fn get_loss(&self) -> f32 { self.loss }
+
+	/** The standard error of the arithmetic mean over all rounds (which, with `GEOM`, the loss doesn't exceed). */
+	fn get_std_error(&self) -> f32 { self.rounds.std_error(0) }
+
+	fn get_p_value(&self, _config: &SearchConfig) -> Option<f64> {
+		if self.age == 0 {
+			return None;
+		}
+		// For a random function, each of the bits^2 entries of a round is 1/2 chi-squared with one degree of freedom,
+		// i.e. has mean and variance 1/2.
+		let half = (F::Word::BITS * F::Word::BITS) as f64 / 2.0;
+		Some(stats::p_worst_of(self.rounds.mean(0), 1, self.rounds.n_rounds(), half, half.sqrt()))
+	}
+
+	fn get_func(&self) -> &F { &self.func }
+
+	fn update(&mut self, samples: &[u64], config: &SearchConfig) -> f32 {
+		self.evaluate(samples, config, None);
+		self.loss
+	}
+
+	fn update_racing(&mut self, samples: &[u64], config: &SearchConfig, cutoff: f32) -> bool {
+		self.evaluate(samples, config, Some(cutoff))
+	}
+}
+
+impl<F: DiffusionFunc, const GEOM: bool> AvalancheSse<F, GEOM> {
+	/** Evaluates all rounds or, racing against a `cutoff`, only until the loss is bound to exceed it. Returns whether
+	   all rounds were evaluated. */
+	fn evaluate(&mut self, samples: &[u64], config: &SearchConfig, cutoff: Option<f32>) -> bool {
+		debug_assert!(samples.len() == config.n_samples as usize);
+		let n_samples_per_round = config.n_samples_per_round() as usize;
+		let mut rounds = RoundStats::new(1);
+		let mut bound = None;
+		for samples in samples.chunks_exact(n_samples_per_round) {
+			rounds.add_round(&[AvalancheDiagram::of(&self.func, samples).sse()]);
+			bound = cutoff.and_then(|cutoff| stats::race_lost(&rounds, config, cutoff));
+			if bound.is_some() {
+				break;
+			}
+		}
+		let loss = rounds.mean(0);
+		self.rounds.merge(rounds);
+		self.update_loss(loss);
+		if let Some(bound) = bound {
+			self.loss = bound;
+		}
+		bound.is_none()
+	}
+}
+
+impl<F: DiffusionFunc, const GEOM: bool> Display for AvalancheSse<F, GEOM> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "{}{{", if GEOM { "SSEG" } else { "SSEA" })?;
+		write!(f, "loss:{}, ", self.get_loss())?;
+		write!(f, "age:{}, ", self.get_age())?;
+		write!(f, "func:{}", self.get_func())?;
+		write!(f, "}}")
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{diffusion::mrxsm::MRXSM, utils::sample_buffer::SampleBuffer};
+
+	#[test]
+	fn test_update_loss() {
+		let f = MRXSM::<u64>::default();
+		let mut arith = AvalancheSse::<_, false>::new(f.clone());
+		let mut geom = AvalancheSse::<_, true>::new(f);
+		for loss in [1.0, 3.0, 7.0] {
+			arith.update_loss(loss);
+			geom.update_loss(loss);
+		}
+		assert_eq!(11.0 / 3.0, arith.get_loss());
+		// (2 * 4 * 8)^(1/3) - 1
+		assert!((geom.get_loss() - 3.0).abs() < 1e-5);
+	}
+
+	#[test]
+	fn test_update() {
+		let config = SearchConfig { n_samples: 10_000, n_rounds: 10, ..Default::default() };
+		let samples = SampleBuffer::random(config.n_samples as usize, &mut rand::thread_rng());
+		let good = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let bad = MRXSM::new(0x3_u64, 0x5, 0, 63);
+		let mut good = AvalancheSse::<_, false>::new(good);
+		let mut bad = AvalancheSse::<_, false>::new(bad);
+		let loss = good.update(&samples, &config);
+		// For a random function, each of the 64 * 64 entries contributes 1/2 in expectation.
+		assert!((1900.0..2200.0).contains(&loss), "loss: {}", loss);
+		assert!(bad.update(&samples, &config) > 10.0 * loss);
+		assert!(good.get_std_error() < 0.1 * loss);
+		assert!(good.get_p_value(&config).unwrap() > 1e-4);
+		assert!(bad.get_p_value(&config).unwrap() < 1e-10);
+	}
+}
@@ -0,0 +1,144 @@
+use crate::{
+	diffusion::{DiffusionFunc, DiffusionVisitor},
+	utils::word::Word,
+};
+
+/** The Bit Independence Criterion (BIC): for a perfectly random function, flipping any input bit flips each pair of
+   output bits independently of each other. For each input bit, this measures the correlation (phi coefficient) between
+   the flips of any two output bits and keeps the largest absolute one. */
+#[derive(Debug, PartialEq)]
+pub struct BitIndependence {
+	/** One entry per input bit. */
+	max_corrs: Vec<f32>,
+	/** Input bit, the two output bits and their correlation of the worst pair overall. */
+	worst: (u32, u32, u32, f32),
+}
+
+impl BitIndependence {
+
+	/** Analyses `f` on the given samples. Words of `F::Word` are built from `F::Word::SAMPLE_LEN` consecutive samples.
+
+	   This is considerably more expensive than `Bitflips::of` because it has to look at all pairs of output bits. To
+	   keep that cheap-ish, the flips of each output bit are collected in masks over (up to) 64 samples at a time, so
+	   that a single `count_ones` covers a pair of output bits on 64 samples. */
+	#[inline(always)]
+	pub fn of(f: &impl DiffusionFunc, samples: &[u64]) -> Self {
+		f.accept(BitIndependenceOf(samples))
+	}
+
+	fn count<F: DiffusionFunc>(f: &F, samples: &[u64]) -> Self {
+		let bits = F::Word::BITS as usize;
+		let words: Vec<(F::Word, F::Word)> = samples.chunks_exact(F::Word::SAMPLE_LEN).map(|sample| {
+			let x = F::Word::from_sample(sample);
+			(x, f.diffuse(x))
+		}).collect();
+		let n_samples = words.len() as u32;
+		// flips[j]: how often output bit j flipped, both[j * bits + k]: how often output bits j < k flipped together
+		let mut flips = vec![0_u32; bits];
+		let mut both = vec![0_u32; bits * bits];
+		// cols[j]: bit s is set iff output bit j flipped for sample s of the current batch
+		let mut cols = vec![0_u64; bits];
+		let mut max_corrs = vec![0_f32; bits];
+		let mut worst = (0, 0, 0, 0_f32);
+		for (i, max_corr) in max_corrs.iter_mut().enumerate() {
+			flips.fill(0);
+			both.fill(0);
+			for batch in words.chunks(64) {
+				cols.fill(0);
+				for (s, &(x, h)) in batch.iter().enumerate() {
+					let diff = (h ^ f.diffuse(x ^ (F::Word::ONE << i as u32))).to_u128();
+					for (j, col) in cols.iter_mut().enumerate() {
+						*col |= ((diff >> j) as u64 & 1) << s;
+					}
+				}
+				for (j, (&col_j, both_j)) in cols.iter().zip(both.chunks_exact_mut(bits)).enumerate() {
+					flips[j] += col_j.count_ones();
+					for (&col_k, both_jk) in cols[j + 1..].iter().zip(both_j[j + 1..].iter_mut()) {
+						*both_jk += (col_j & col_k).count_ones();
+					}
+				}
+			}
+			for j in 0..bits {
+				for k in j + 1..bits {
+					let corr = correlation(n_samples, flips[j], flips[k], both[j * bits + k]);
+					if corr.abs() > *max_corr {
+						*max_corr = corr.abs();
+						if corr.abs() > worst.3.abs() {
+							worst = (i as u32, j as u32, k as u32, corr);
+						}
+					}
+				}
+			}
+		}
+		Self { max_corrs, worst }
+	}
+
+	/** For each input bit, the largest absolute correlation between the flips of two output bits. */
+	#[inline(always)]
+	pub fn max_corrs(&self) -> &[f32] { &self.max_corrs }
+
+	/** The input bit, the two output bits and the correlation of the pair with the largest absolute correlation. */
+	#[inline(always)]
+	pub fn worst_pair(&self) -> (u32, u32, u32, f32) { self.worst }
+}
+
+/** The phi coefficient of two output bits that flipped `n_j` and `n_k` times (and `n_jk` times together) out of
+   `n_samples`. If either of them always or never flipped, the correlation is undefined, which we count as the worst
+   case, i.e. 1. */
+fn correlation(n_samples: u32, n_j: u32, n_k: u32, n_jk: u32) -> f32 {
+	let (n, n_j, n_k, n_jk) = (n_samples as f64, n_j as f64, n_k as f64, n_jk as f64);
+	let var = n_j * (n - n_j) * n_k * (n - n_k);
+	if var == 0.0 {
+		return 1.0;
+	}
+	((n * n_jk - n_j * n_k) / var.sqrt()) as f32
+}
+
+
+/** Dispatches `BitIndependence::of` to the concrete type of the diffusion function. */
+struct BitIndependenceOf<'a>(&'a [u64]);
+
+impl<W: Word> DiffusionVisitor<W> for BitIndependenceOf<'_> {
+	type Output = BitIndependence;
+
+	#[inline(always)]
+	fn visit<F: DiffusionFunc<Word = W>>(self, f: &F) -> BitIndependence { BitIndependence::count(f, self.0) }
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::Rng;
+
+	use crate::{config::SearchConfig, diffusion::mrxsm::MRXSM};
+	use super::*;
+
+	#[test]
+	fn test_correlation() {
+		assert_eq!(1.0, correlation(100, 50, 50, 50));
+		assert_eq!(-1.0, correlation(100, 50, 50, 0));
+		assert_eq!(0.0, correlation(100, 50, 50, 25));
+		assert_eq!(1.0, correlation(100, 0, 50, 0));
+		assert_eq!(1.0, correlation(100, 50, 100, 50));
+	}
+
+	#[test]
+	fn test_bic() {
+		let mut rng = rand::thread_rng();
+		let samples: Vec<u64> = (0..10_000).map(|_| rng.gen()).collect();
+		let good = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let bic = BitIndependence::of(&good, &samples);
+		assert_eq!(64, bic.max_corrs().len());
+		let (i, j, k, corr) = bic.worst_pair();
+		assert!(corr.abs() < 0.1, "worst pair: {:?}", bic.worst_pair());
+		assert_eq!(corr.abs(), bic.max_corrs()[i as usize]);
+		assert!(j < k && k < 64);
+
+		// Flipping the lowest input bit always flips the lowest output bit.
+		let bad = MRXSM::new(0x3_u64, 0x5, 62, 63);
+		assert_eq!(1.0, BitIndependence::of(&bad, &samples).max_corrs()[0]);
+
+		let f = MRXSM::<u16>::random(&SearchConfig::default(), &mut rng);
+		assert_eq!(16, BitIndependence::of(&f, &samples).max_corrs().len());
+	}
+}
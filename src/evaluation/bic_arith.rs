@@ -0,0 +1,150 @@
+use std::fmt::{self, Display, Formatter};
+
+use rand::Rng;
+
+use crate::{
+	config::SearchConfig, diffusion::DiffusionFunc,
+	evaluation::{bic::BitIndependence, stats::{self, RoundStats}, wasserstein_arith::worst_index},
+	utils::word::Word,
+};
+
+use super::Evaluator;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+
+/** 'Evaluator' based on the Bit Independence Criterion (see `BitIndependence`). For each input bit, we take the largest
+   absolute correlation between the flips of two output bits and the loss is the maximum over all input bits. Like
+   `WassersteinArith`, we subdivide the samples and average the correlations for each input bit over multiple rounds
+   and, over the course of the genetic algorithm, over the generations that this 'Evaluator' lives (arithmetic mean).
+
+   This catches output bits that flip together, which neither the number of flipped bits nor the avalanche diagram
+   reveal, but is a lot more expensive than either. */
+pub struct BicArith<F: DiffusionFunc> {
+	/** One entry per input bit, i.e. as many as the function's word has bits. */
+	max_corrs: Vec<f32>,
+	/** Input bit, the two output bits and their correlation of the worst pair seen in the latest update. */
+	worst: (u32, u32, u32, f32),
+	/** The correlations of each round, for the standard error of the loss. */
+	#[serde(default)]
+	rounds: RoundStats,
+	loss: f32,
+	age: u32,
+	func: F
+}
+
+impl<F: DiffusionFunc> BicArith<F> {
+	/** Updates the stored correlations of this diffusion function and returns the updated maximum value. */
+	#[inline(always)]
+	fn update_corrs(&mut self, max_corrs: Vec<f32>) -> f32 {
+		self.age += 1;
+		if self.age == 1 {
+			self.max_corrs = max_corrs;
+		} else {
+			for (prev, new) in self.max_corrs.iter_mut().zip(max_corrs) {
+				*prev += new;
+			}
+		}
+		self.loss = self.max_corrs.iter().fold(0_f32, |m, &c| m.max(c)) / self.age as f32;
+		self.loss
+	}
+}
+
+impl<F: DiffusionFunc> Evaluator<F> for BicArith<F> {
+	fn new(func: F) -> Self {
+		let rounds = RoundStats::default();
+		Self { max_corrs: Vec::new(), worst: (0, 0, 0, 0.0), rounds, loss: f32::MAX, age: 0, func }
+	}
+
+	fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self {
+		Self::new(F::random(config, rng))
+	}
+
+	fn get_age(&self) -> u32 { self.age }
+
+	fn get_loss(&self) -> f32 { self.loss }
+
+	fn get_std_error(&self) -> f32 { self.rounds.std_error(worst_index(&self.max_corrs)) }
+
+	fn get_func(&self) -> &F { &self.func }
+
+	fn update(&mut self, samples: &[u64], config: &SearchConfig) -> f32 {
+		self.evaluate(samples, config, None);
+		self.loss
+	}
+
+	fn update_racing(&mut self, samples: &[u64], config: &SearchConfig, cutoff: f32) -> bool {
+		self.evaluate(samples, config, Some(cutoff))
+	}
+}
+
+impl<F: DiffusionFunc> BicArith<F> {
+	/** Evaluates all rounds or, racing against a `cutoff`, only until the loss is bound to exceed it. Returns whether
+	   all rounds were evaluated. */
+	fn evaluate(&mut self, samples: &[u64], config: &SearchConfig, cutoff: Option<f32>) -> bool {
+		debug_assert!(samples.len() == config.n_samples as usize);
+		let n_samples_per_round = config.n_samples_per_round() as usize;
+		let mut rounds = RoundStats::new(F::Word::BITS as usize);
+		let mut bound = None;
+		self.worst = (0, 0, 0, 0.0);
+		for samples in samples.chunks_exact(n_samples_per_round) {
+			let bic = BitIndependence::of(&self.func, samples);
+			if bic.worst_pair().3.abs() >= self.worst.3.abs() {
+				self.worst = bic.worst_pair();
+			}
+			rounds.add_round(bic.max_corrs());
+			bound = cutoff.and_then(|cutoff| stats::race_lost(&rounds, config, cutoff));
+			if bound.is_some() {
+				break;
+			}
+		}
+		let avg = rounds.means();
+		self.rounds.merge(rounds);
+		self.update_corrs(avg);
+		if let Some(bound) = bound {
+			self.loss = bound;
+		}
+		bound.is_none()
+	}
+}
+
+impl<F: DiffusionFunc> Display for BicArith<F> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "BICA{{")?;
+		write!(f, "loss:{}, ", self.get_loss())?;
+		write!(f, "age:{}, ", self.get_age())?;
+		let (i, j, k, corr) = self.worst;
+		write!(f, "worst:{}->{}/{}:{:.3}, ", i, j, k, corr)?;
+		write!(f, "func:{}", self.get_func())?;
+		write!(f, "}}")
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{diffusion::mrxsm::MRXSM, utils::sample_buffer::SampleBuffer};
+
+	#[test]
+	fn test_update_corrs() {
+		let mut e = BicArith::new(MRXSM::<u64>::default());
+		e.update_corrs(vec![0.5; 64]);
+		assert_eq!(0.5, e.get_loss());
+		let mut corrs = vec![0.1; 64];
+		corrs[3] = 0.3;
+		e.update_corrs(corrs);
+		assert_eq!(0.4, e.get_loss());
+		assert_eq!(2, e.get_age());
+	}
+
+	#[test]
+	fn test_update() {
+		let config = SearchConfig { n_samples: 4000, n_rounds: 2, ..Default::default() };
+		let samples = SampleBuffer::random(config.n_samples as usize, &mut rand::thread_rng());
+		let mut e = BicArith::new(MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59));
+		let loss = e.update(&samples, &config);
+		assert!(loss < 0.2, "{}", e);
+		assert!(e.worst.3.abs() >= loss);
+		assert!(e.to_string().starts_with(&format!("BICA{{loss:{}, age:1, worst:{}->", loss, e.worst.0)));
+	}
+}
@@ -1,37 +1,38 @@
 // use std::arch::x86_64::{_mm256_set1_epi64x, _mm256_xor_epi64};
 
-use aligned_array::{A32, Aligned};
-
-use crate::{diffusion::DiffusionFunc, globals::N_SAMPLES_PER_ROUND, utils::wasserstein};
+use crate::{
+	diffusion::{DiffusionFunc, DiffusionVisitor},
+	evaluation::differences::InputDifference,
+	utils::{distance::DistributionDistance, word::{Word, MAX_BITS}},
+};
 
+/** For each of the `W::BITS` input bits (or each input difference, see `of_differences`), a histogram of the number of
+   flipped output bits (0..=bits). The buffer is sized by the word width, so that counting doesn't allocate; only the
+   first `n_rows` rows are used. */
 #[derive(Debug, PartialEq, Eq)]
-// pub struct Bitflips(Aligned<A32, [[u32; 68]; 64]>);
-pub struct Bitflips([[u32; 65]; 64]);
-
-const BITS: Aligned<A32, [u64; 64]> = init_bits();
-const fn init_bits() -> Aligned<A32, [u64; 64]> {
-	let mut result = [1_u64; 64];
-	let mut shift = 0;
-	while shift < 64 {
-		result[shift] <<= shift;
-		shift += 1;
-	}
-	Aligned(result)
+pub struct Bitflips<W: Word> {
+	n_rows: usize,
+	counts: W::FlipCounts,
 }
 
-impl Bitflips {
+impl<W: Word> Bitflips<W> {
+	const ROW_LEN: usize = W::BITS as usize + 1;
 
-	pub fn of(f: &impl DiffusionFunc, samples: &[u64; N_SAMPLES_PER_ROUND as usize]) -> Self {
-		// debug_assert!(samples.len() == N_SAMPLES_PER_ROUND);
-		// let mut n_flips: Aligned<A32, _> = Aligned([[0_u32; 68]; 64]);
-		let mut n_flips = [[0_u32; 65]; 64];
-		for &x in samples {
-			let h = f.diffuse(x);
-			#[allow(clippy::needless_range_loop)]
-			for shift in 0..64 {
-				let diff = h ^ f.diffuse(x ^ BITS[shift]);
-				n_flips[shift][diff.count_ones() as usize] += 1;
+	/** For each input bit, counts how many output bits flip when that input bit is flipped. This is the hot loop of the
+	   evaluation. Words are built from `W::SAMPLE_LEN` consecutive samples. */
+	#[inline(always)]
+	pub fn of(f: &impl DiffusionFunc<Word = W>, samples: &[u64]) -> Self {
+		f.accept(BitflipsOf(samples))
+	}
 
+	fn count<F: DiffusionFunc<Word = W>>(f: &F, samples: &[u64]) -> Self {
+		let mut counts = W::ZERO_FLIP_COUNTS;
+		for sample in samples.chunks_exact(W::SAMPLE_LEN) {
+			let x = W::from_sample(sample);
+			let h = f.diffuse(x);
+			for (shift, row) in counts.as_mut().chunks_exact_mut(Self::ROW_LEN).enumerate() {
+				let diff = h ^ f.diffuse(x ^ (W::ONE << shift as u32));
+				row[diff.count_ones() as usize] += 1;
 			}
 
 			// let xs = unsafe { _mm256_set1_epi64x(x) };
@@ -46,38 +47,108 @@ impl Bitflips {
 
 			// }
 		}
-		Self(n_flips)
+		Self { n_rows: W::BITS as usize, counts }
 	}
 
-	pub fn w1s(&self) -> [f32; 64] {
-		let mut w1s = [0_f32; 64];
-		for (counts, w1) in self.0.iter().zip(w1s.iter_mut()) {
-			// *w1 = wasserstein::of_counts(&counts[..65].try_into().unwrap());
-			*w1 = wasserstein::of_counts(counts);
+	/** Like `of` but with one histogram per input difference instead of per input bit, for at most `W::BITS`
+	   differences. */
+	#[inline(always)]
+	pub fn of_differences(f: &impl DiffusionFunc<Word = W>, samples: &[u64], diffs: &[InputDifference]) -> Self {
+		f.accept(BitflipsOfDifferences(samples, diffs))
+	}
+
+	fn count_differences<F: DiffusionFunc<Word = W>>(f: &F, samples: &[u64], diffs: &[InputDifference]) -> Self {
+		debug_assert!(diffs.len() <= W::BITS as usize);
+		let mut words = [(false, W::ZERO); MAX_BITS];
+		for (word, diff) in words.iter_mut().zip(diffs) {
+			*word = diff.to_word();
+		}
+		let mut counts = W::ZERO_FLIP_COUNTS;
+		for sample in samples.chunks_exact(W::SAMPLE_LEN) {
+			let x = W::from_sample(sample);
+			let h = f.diffuse(x);
+			for (&(add, d), row) in words[..diffs.len()].iter().zip(counts.as_mut().chunks_exact_mut(Self::ROW_LEN)) {
+				let y = if add { x.wrapping_add(d) } else { x ^ d };
+				row[(h ^ f.diffuse(y)).count_ones() as usize] += 1;
+			}
+		}
+		Self { n_rows: diffs.len(), counts }
+	}
+
+	/** The histogram of flipped output bits for the given input bit (or difference). */
+	#[inline(always)]
+	pub fn row(&self, input_bit: usize) -> &[u32] {
+		debug_assert!(input_bit < self.n_rows);
+		&self.counts.as_ref()[input_bit * Self::ROW_LEN..][..Self::ROW_LEN]
+	}
+
+	/** The number of samples (i.e. words) these bit flips were counted on. */
+	#[inline(always)]
+	pub fn n_samples(&self) -> u32 { self.row(0).iter().sum() }
+
+	/** Writes one distance to Bin(bits, 0.5) per input bit (or difference) into `distances`, which must have room for
+	   exactly that many. */
+	pub fn distances(&self, distance: &impl DistributionDistance, distances: &mut [f32]) {
+		debug_assert!(distances.len() == self.n_rows);
+		let n_samples = self.n_samples();
+		for (row, d) in distances.iter_mut().enumerate() {
+			*d = distance.of_counts(self.row(row), n_samples);
 		}
-		w1s
+	}
+
+	/** One p-value per input bit (or difference), see `DistributionDistance::p_value`. */
+	pub fn p_values(&self, distance: &impl DistributionDistance) -> Option<Vec<f64>> {
+		let n_samples = self.n_samples();
+		(0..self.n_rows).map(|row| distance.p_value(self.row(row), n_samples)).collect()
 	}
 }
 
 
+/** Dispatches `Bitflips::of` to the concrete type of the diffusion function. */
+struct BitflipsOf<'a>(&'a [u64]);
+
+impl<W: Word> DiffusionVisitor<W> for BitflipsOf<'_> {
+	type Output = Bitflips<W>;
+
+	#[inline(always)]
+	fn visit<F: DiffusionFunc<Word = W>>(self, f: &F) -> Bitflips<W> { Bitflips::count(f, self.0) }
+}
+
+
+/** Dispatches `Bitflips::of_differences` to the concrete type of the diffusion function. */
+struct BitflipsOfDifferences<'a>(&'a [u64], &'a [InputDifference]);
+
+impl<W: Word> DiffusionVisitor<W> for BitflipsOfDifferences<'_> {
+	type Output = Bitflips<W>;
+
+	#[inline(always)]
+	fn visit<F: DiffusionFunc<Word = W>>(self, f: &F) -> Bitflips<W> { Bitflips::count_differences(f, self.0, self.1) }
+}
+
+
 #[cfg(test)]
 mod tests {
 	use rand::Rng;
 
-	use crate::diffusion::rxsm::RXSM;
+	use crate::{
+		config::SearchConfig, diffusion::rxsm::RXSM, evaluation::differences::DifferenceKind,
+		utils::distance::{DistanceKind, KullbackLeibler, Wasserstein1},
+	};
 	use super::*;
 
 	#[test]
 	fn test_rxsm_example() {
-		let f = RXSM::new(0xa4001226aaaaaab, 21, 59);
+		let f = RXSM::new(0xa4001226aaaaaab_u64, 21, 59);
 		let mut rng = rand::thread_rng();
-		let mut samples = [0_u64; N_SAMPLES_PER_ROUND as usize];
-		for i in 0..N_SAMPLES_PER_ROUND {
+		let n_samples_per_round = SearchConfig::default().n_samples_per_round();
+		let mut samples = vec![0_u64; n_samples_per_round as usize];
+		for i in 0..n_samples_per_round {
 			samples[i as usize] = rng.gen();
 		}
 		let n_flips = Bitflips::of(&f, &samples);
-		let w1s = n_flips.w1s();
-		dbg!("{}", w1s);
+		let mut w1s = [0_f32; 64];
+		n_flips.distances(&Wasserstein1, &mut w1s);
+		dbg!("{}", &w1s);
 		let mut max_idx = 0;
 		let mut max_w1 = 0.0;
 		for (i, &w1) in w1s.iter().enumerate() {
@@ -88,9 +159,50 @@ mod tests {
 		}
 		println!("max w1: {}, max idx: {}", max_w1, max_idx);
 		print!("[");
-		for f in n_flips.0[max_idx] {
+		for f in n_flips.row(max_idx) {
 			print!("{}, ", f);
 		}
 		println!("]");
 	}
+
+	#[test]
+	fn test_word_widths() {
+		let mut rng = rand::thread_rng();
+		let config = SearchConfig::default();
+		let samples: Vec<u64> = (0..1000).map(|_| rng.gen()).collect();
+		let n_flips = Bitflips::of(&RXSM::<u16>::random(&config, &mut rng), &samples);
+		assert_eq!(16 * 17, n_flips.counts.len());
+		assert_eq!(17, n_flips.row(15).len());
+		assert_eq!(1000, n_flips.n_samples());
+		let mut w1s = [0_f32; 16];
+		n_flips.distances(&Wasserstein1, &mut w1s);
+		assert!(w1s.iter().all(|&w1| w1 > 0.0));
+		let n_flips = Bitflips::of(&RXSM::<u128>::random(&config, &mut rng), &samples);
+		assert_eq!(128 * 129, n_flips.counts.len());
+		assert_eq!(129, n_flips.row(127).len());
+		assert_eq!(500, n_flips.n_samples());
+	}
+
+	#[test]
+	fn test_differences() {
+		let mut rng = rand::thread_rng();
+		let samples: Vec<u64> = (0..1000).map(|_| rng.gen()).collect();
+		let f = RXSM::new(0xa4001226aaaaaab_u64, 21, 59);
+		let config = SearchConfig { differences: vec![DifferenceKind::Bits], ..Default::default() };
+		let diffs = InputDifference::set::<u64>(&config);
+		assert_eq!(Bitflips::of(&f, &samples), Bitflips::of_differences(&f, &samples, &diffs));
+
+		let diffs = [InputDifference::Add(1), InputDifference::Xor(0xff)];
+		let n_flips = Bitflips::of_differences(&f, &samples, &diffs);
+		assert_eq!(1000, n_flips.n_samples());
+		assert_eq!(1000, n_flips.row(1).iter().sum::<u32>());
+		let mut kls = [0_f32; 2];
+		n_flips.distances(&DistanceKind::KullbackLeibler, &mut kls);
+		assert_eq!(KullbackLeibler.of_counts(n_flips.row(1), 1000), kls[1]);
+		assert_eq!(None, n_flips.p_values(&DistanceKind::KullbackLeibler));
+		let p_values = n_flips.p_values(&DistanceKind::ChiSquared).unwrap();
+		assert_eq!(2, p_values.len());
+		// Adding 1 flips a single bit half of the time.
+		assert!(p_values[0] < 1e-10, "{:?}", p_values);
+	}
 }
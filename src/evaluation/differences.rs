@@ -0,0 +1,160 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{config::SearchConfig, utils::word::Word};
+
+
+/** The kinds of input differences that make up the difference set of `SearchConfig::differences`. */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DifferenceKind {
+	/** Single bits, i.e. what `Bitflips::of` looks at. */
+	Bits,
+	/** All pairs of (distinct) bits. */
+	BitPairs,
+	/** Each aligned byte, i.e. `0xff << 8k`. */
+	Bytes,
+	/** Arithmetic differences `x + d` with `d = ±1, ±2, ..., ±2^(bits - 1)`. `x + 1` differs from `x` by a carry chain. */
+	Deltas,
+	/** `SearchConfig::sparse_masks` random masks with `SearchConfig::sparse_weight` bits set each. */
+	Sparse,
+}
+
+impl DifferenceKind {
+	pub const ALL: [DifferenceKind; 5] = [DifferenceKind::Bits, DifferenceKind::BitPairs, DifferenceKind::Bytes,
+		DifferenceKind::Deltas, DifferenceKind::Sparse];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			DifferenceKind::Bits => "bits",
+			DifferenceKind::BitPairs => "bit_pairs",
+			DifferenceKind::Bytes => "bytes",
+			DifferenceKind::Deltas => "deltas",
+			DifferenceKind::Sparse => "sparse",
+		}
+	}
+}
+
+impl Display for DifferenceKind {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for DifferenceKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		DifferenceKind::ALL.into_iter().find(|kind| kind.name() == s)
+			.ok_or_else(|| format!("unknown kind of input difference {}", s))
+	}
+}
+
+
+/** How a second input is derived from an input `x`. The values are stored as `u128`, so that the same set can be used
+   for any word width; they always fit into the word the set was built for. */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum InputDifference {
+	/** `x ^ mask` */
+	Xor(u128),
+	/** `x + delta` (wrapping) */
+	Add(u128),
+}
+
+impl InputDifference {
+	/** Builds the difference set for `W` made up of all kinds in `config.differences` (without duplicates). */
+	pub fn set<W: Word>(config: &SearchConfig) -> Vec<InputDifference> {
+		let bits = W::BITS;
+		let max = W::MAX.to_u128();
+		let mut set = Vec::new();
+		for kind in config.differences.iter() {
+			match kind {
+				DifferenceKind::Bits => set.extend((0..bits).map(|i| InputDifference::Xor(1 << i))),
+				DifferenceKind::BitPairs => {
+					for i in 0..bits {
+						set.extend((i + 1..bits).map(|j| InputDifference::Xor(1 << i | 1 << j)));
+					}
+				}
+				DifferenceKind::Bytes => set.extend((0..bits).step_by(8).map(|i| InputDifference::Xor(0xff << i))),
+				DifferenceKind::Deltas => {
+					for i in 0..bits {
+						let delta = 1_u128 << i;
+						set.push(InputDifference::Add(delta));
+						set.push(InputDifference::Add(delta.wrapping_neg() & max));
+					}
+				}
+				DifferenceKind::Sparse => {
+					let mut rng = StdRng::seed_from_u64(config.difference_seed);
+					let weight = config.sparse_weight.min(bits);
+					for _ in 0..config.sparse_masks {
+						let mut mask = 0_u128;
+						while mask.count_ones() < weight {
+							mask |= 1 << rng.gen_range(0..bits);
+						}
+						set.push(InputDifference::Xor(mask));
+					}
+				}
+			}
+		}
+		// -2^(bits - 1) = 2^(bits - 1) and single bits might come up again as sparse masks.
+		let mut seen = std::collections::HashSet::new();
+		set.retain(|diff| seen.insert(*diff));
+		set
+	}
+
+	/** The difference as applied to words of type `W`. */
+	#[inline(always)]
+	pub fn to_word<W: Word>(self) -> (bool, W) {
+		match self {
+			InputDifference::Xor(mask) => (false, W::from_u128(mask).expect("input difference doesn't fit")),
+			InputDifference::Add(delta) => (true, W::from_u128(delta).expect("input difference doesn't fit")),
+		}
+	}
+}
+
+impl Display for InputDifference {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			InputDifference::Xor(mask) => write!(f, "xor:{:#x}", mask),
+			InputDifference::Add(delta) => write!(f, "add:{:#x}", delta),
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_set() {
+		let config = SearchConfig { differences: vec![DifferenceKind::Bits], ..Default::default() };
+		assert_eq!((0..16).map(|i| InputDifference::Xor(1 << i)).collect::<Vec<_>>(), InputDifference::set::<u16>(&config));
+
+		let config = SearchConfig { differences: DifferenceKind::ALL.to_vec(), sparse_masks: 10, ..Default::default() };
+		let set = InputDifference::set::<u64>(&config);
+		assert!(set.contains(&InputDifference::Xor(0b11)));
+		assert!(set.contains(&InputDifference::Xor(1 << 63 | 1)));
+		assert!(set.contains(&InputDifference::Xor(0xff << 56)));
+		assert!(set.contains(&InputDifference::Add(1)));
+		assert!(set.contains(&InputDifference::Add(u64::MAX as u128)));
+		assert!(set.contains(&InputDifference::Add(1 << 63)));
+		let n_sparse = set.iter().filter(|diff| matches!(diff, InputDifference::Xor(m) if m.count_ones() == 3)).count();
+		assert_eq!(10, n_sparse);
+		assert_eq!(64 + 64 * 63 / 2 + 8 + 2 * 64 - 1 + 10, set.len());
+		// The sparse masks only depend on the seed.
+		assert_eq!(set, InputDifference::set::<u64>(&config));
+		let other = SearchConfig { difference_seed: 1, ..config.clone() };
+		assert_ne!(set, InputDifference::set::<u64>(&other));
+
+		for diff in InputDifference::set::<u16>(&config) {
+			let _: (bool, u16) = diff.to_word();
+		}
+		assert_eq!((true, 0xffff_u16), InputDifference::Add(0xffff).to_word());
+		assert_eq!("xor:0x3", InputDifference::Xor(3).to_string());
+	}
+}
@@ -1,25 +1,109 @@
-use std::fmt::Display;
+use std::{
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+};
 
 use rand::Rng;
 
-use crate::{diffusion::DiffusionFunc, globals::N_SAMPLES};
+use crate::{config::SearchConfig, diffusion::DiffusionFunc};
 
 pub mod wasserstein_arith;
 pub mod wasserstein_geom;
+pub mod avalanche_sse;
+pub mod bic_arith;
+pub mod wasserstein_worst;
+pub mod differences;
+pub mod stats;
 mod bitflips;
 mod avalanche;
+mod bic;
 
 pub trait Evaluator<F: DiffusionFunc>: Display {
 	fn new(func: F) -> Self;
 
-	fn random(rng: &mut impl Rng) -> Self;
+	fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self;
 
     fn get_age(&self) -> u32;
 
     fn get_loss(&self) -> f32;
 
+	/** The standard error of `get_loss`, estimated from how much the per-round losses vary over all rounds that this
+	   evaluator has seen. `NaN` as long as there are fewer than two. */
+	fn get_std_error(&self) -> f32;
+
+	/** The (approximate) 95% confidence interval of the loss. */
+	fn confidence_interval(&self) -> (f32, f32) {
+		let half_width = stats::Z_95 * self.get_std_error();
+		(self.get_loss() - half_width, self.get_loss() + half_width)
+	}
+
+	/** The p-value of `get_loss` under the null hypothesis that the function is an ideal random function, i.e. the
+	   probability that such a function would have a loss at least as high. `None` if the evaluator doesn't know how its
+	   loss is distributed then. */
+	fn get_p_value(&self, _config: &SearchConfig) -> Option<f64> { None }
+
 	fn get_func(&self) -> &F;
 
-    fn update(&mut self, samples: &[u64; (N_SAMPLES) as usize]) -> f32;
+	/** The losses of the individual cases that `get_loss` is the worst of (e.g. the distances of the input bits),
+	   averaged like it. Empty if the evaluator has no such cases. See `SelectionKind::Lexicase`. */
+	fn get_case_losses(&self) -> Vec<f32> { Vec::new() }
+
+    /** Evaluates the function on the given samples (`config.n_samples` many), folds the result into the running loss
+       and returns the updated loss. */
+    fn update(&mut self, samples: &[u64], config: &SearchConfig) -> f32;
+
+	/** Like `update`, but may give up after any round once the loss is bound to exceed `cutoff` (see
+	   `stats::race_lost`). The loss then only is that lower bound and `false` is returned. Candidates that make it
+	   through all rounds end up exactly as with `update`. Meant for functions that haven't been evaluated yet. */
+	fn update_racing(&mut self, samples: &[u64], config: &SearchConfig, cutoff: f32) -> bool;
+}
+
+
+/** The evaluation strategies that can be picked at runtime (see `SearchConfig::evaluator`). */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EvaluatorKind {
+	/** See `wasserstein_arith::WassersteinArith`. */
+	#[default]
+	WassersteinArith,
+	/** See `wasserstein_geom::WassersteinGeom`. */
+	WassersteinGeom,
+	/** See `avalanche_sse::AvalancheSse` (arithmetic mean). */
+	AvalancheSseArith,
+	/** See `avalanche_sse::AvalancheSse` (geometric mean). */
+	AvalancheSseGeom,
+	/** See `bic_arith::BicArith`. */
+	BicArith,
+	/** See `wasserstein_worst::WassersteinWorst`. */
+	WassersteinWorst,
+}
+
+impl EvaluatorKind {
+	pub const ALL: [EvaluatorKind; 6] = [EvaluatorKind::WassersteinArith, EvaluatorKind::WassersteinGeom,
+		EvaluatorKind::AvalancheSseArith, EvaluatorKind::AvalancheSseGeom, EvaluatorKind::BicArith,
+		EvaluatorKind::WassersteinWorst];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			EvaluatorKind::WassersteinArith => "wasserstein_arith",
+			EvaluatorKind::WassersteinGeom => "wasserstein_geom",
+			EvaluatorKind::AvalancheSseArith => "avalanche_sse_arith",
+			EvaluatorKind::AvalancheSseGeom => "avalanche_sse_geom",
+			EvaluatorKind::BicArith => "bic_arith",
+			EvaluatorKind::WassersteinWorst => "wasserstein_worst",
+		}
+	}
+}
+
+impl Display for EvaluatorKind {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for EvaluatorKind {
+	type Err = String;
 
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		EvaluatorKind::ALL.into_iter().find(|kind| kind.name() == s).ok_or_else(|| format!("unknown evaluator {}", s))
+	}
 }
@@ -0,0 +1,254 @@
+use std::{
+	collections::HashMap,
+	sync::{Mutex, OnceLock},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+	config::SearchConfig,
+	diffusion::DiffusionFunc,
+	evaluation::bitflips::Bitflips,
+	utils::distance::{chi_squared_sf, DistanceKind, DistributionDistance},
+};
+
+/** The quantile of the standard normal distribution for two-sided 95% confidence intervals. */
+pub const Z_95: f32 = 1.959964;
+
+/** The level below which `print_results` calls a difference significant. */
+pub const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+
+/** Running sums of a per-round quantity for each of several components (e.g. the distance of each input bit), over all
+   rounds an evaluator has seen. They tell how much of a loss is sampling noise. */
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct RoundStats {
+	sums: Vec<f64>,
+	sq_sums: Vec<f64>,
+	n_rounds: u32,
+}
+
+impl RoundStats {
+	pub fn new(len: usize) -> Self {
+		Self { sums: vec![0.0; len], sq_sums: vec![0.0; len], n_rounds: 0 }
+	}
+
+	/** Adds the values of a single round, one per component. */
+	#[inline(always)]
+	pub fn add_round(&mut self, values: &[f32]) {
+		debug_assert!(values.len() == self.sums.len());
+		for ((sum, sq_sum), &v) in self.sums.iter_mut().zip(self.sq_sums.iter_mut()).zip(values) {
+			*sum += v as f64;
+			*sq_sum += v as f64 * v as f64;
+		}
+		self.n_rounds += 1;
+	}
+
+	/** Adds all rounds of `other`. If the components don't match (e.g. `self` is still empty), `other` replaces
+	   `self`. */
+	pub fn merge(&mut self, other: RoundStats) {
+		if self.sums.len() != other.sums.len() {
+			*self = other;
+			return;
+		}
+		for (sum, s) in self.sums.iter_mut().zip(other.sums) {
+			*sum += s;
+		}
+		for (sq_sum, s) in self.sq_sums.iter_mut().zip(other.sq_sums) {
+			*sq_sum += s;
+		}
+		self.n_rounds += other.n_rounds;
+	}
+
+	#[inline(always)]
+	pub fn n_rounds(&self) -> u32 { self.n_rounds }
+
+	/** The mean of a component over all rounds. */
+	pub fn mean(&self, i: usize) -> f32 { (self.sums[i] / self.n_rounds as f64) as f32 }
+
+	/** The means of all components over all rounds. */
+	pub fn means(&self) -> Vec<f32> { (0..self.sums.len()).map(|i| self.mean(i)).collect() }
+
+	/** The index of the component with the largest mean. */
+	pub fn worst(&self) -> usize {
+		let mut worst = 0;
+		for (i, &sum) in self.sums.iter().enumerate() {
+			if sum > self.sums[worst] {
+				worst = i;
+			}
+		}
+		worst
+	}
+
+	/** The standard error of the mean of a component, i.e. the sample standard deviation over `sqrt(n_rounds)`. `NaN`
+	   for fewer than two rounds. */
+	pub fn std_error(&self, i: usize) -> f32 {
+		if self.n_rounds < 2 {
+			return f32::NAN;
+		}
+		let n = self.n_rounds as f64;
+		let mean = self.sums[i] / n;
+		let var = (self.sq_sums[i] / n - mean * mean).max(0.0) * n / (n - 1.0);
+		(var / n).sqrt() as f32
+	}
+}
+
+
+/** `P(Z > z)` for a standard normal `Z`. */
+pub fn normal_sf(z: f64) -> f64 {
+	let tail = chi_squared_sf(z * z, 1.0) / 2.0;
+	if z >= 0.0 { tail } else { 1.0 - tail }
+}
+
+/** The one-sided p-value of the hypothesis that a loss of `loss ± std_error` isn't actually lower than one of
+   `other ± other_std_error` (both estimated independently and approximately normal). */
+pub fn p_lower(loss: f32, std_error: f32, other: f32, other_std_error: f32) -> f64 {
+	let se = ((std_error as f64).powi(2) + (other_std_error as f64).powi(2)).sqrt();
+	normal_sf((other - loss) as f64 / se)
+}
+
+/** The p-value of a loss that is the worst of `n_components` means over `n_rounds` rounds each, under the hypothesis
+   that every round of every component follows a distribution with the given `mean` and `std_dev` (independently). The
+   means are taken to be normal and the maximum is accounted for with Šidák's correction. */
+pub fn p_worst_of(loss: f32, n_components: usize, n_rounds: u32, mean: f64, std_dev: f64) -> f64 {
+	let z = (loss as f64 - mean) / (std_dev / (n_rounds as f64).sqrt());
+	sidak(normal_sf(z), n_components)
+}
+
+/** Šidák's correction of the smallest of `n` independent p-values, i.e. the probability that at least one of them is
+   as small as `p`. */
+pub fn sidak(p: f64, n: usize) -> f64 {
+	-(n as f64 * (-p).ln_1p()).exp_m1()
+}
+
+/** The smallest p-value (see `DistributionDistance::p_value`) of the bit-flip histograms of `f`'s input bits on
+   `samples`, corrected for their number. `None` if `distance` doesn't come from a test. */
+pub fn worst_bit_p_value(f: &impl DiffusionFunc, samples: &[u64], distance: DistanceKind) -> Option<f64> {
+	let p_values = Bitflips::of(f, samples).p_values(&distance)?;
+	Some(sidak(p_values.iter().copied().fold(1.0, f64::min), p_values.len()))
+}
+
+/** Decides, while racing (see `Evaluator::update_racing`), whether a candidate can stop after the rounds so far: once
+   there are `config.racing_min_rounds` of them and even the lower end of a `config.racing_z` confidence bound of its
+   worst component exceeds `cutoff`, that bound is returned. */
+pub fn race_lost(rounds: &RoundStats, config: &SearchConfig, cutoff: f32) -> Option<f32> {
+	if rounds.n_rounds() < config.racing_min_rounds {
+		return None;
+	}
+	let worst = rounds.worst();
+	let bound = rounds.mean(worst) - config.racing_z * rounds.std_error(worst);
+	(bound > cutoff).then_some(bound)
+}
+
+
+/** Null moments by distance, word width and number of words. */
+type NullMoments = HashMap<(DistanceKind, u32, u32), (f64, f64)>;
+
+/** The mean and standard deviation of a `distance` of bit-flip histograms over `n_words` words of `bits` bits each, if
+   the number of flipped bits actually follows Bin(bits, 0.5). These are simulated (with a fixed seed) once per
+   combination and cached. */
+pub fn null_moments(distance: DistanceKind, bits: u32, n_words: u32) -> (f64, f64) {
+	const N_TRIALS: u32 = 500;
+	static CACHE: OnceLock<Mutex<NullMoments>> = OnceLock::new();
+	let key = (distance, bits, n_words);
+	if let Some(&moments) = CACHE.get_or_init(Default::default).lock().unwrap().get(&key) {
+		return moments;
+	}
+	let mut rng = StdRng::seed_from_u64(0);
+	let mask = u128::MAX >> (128 - bits);
+	let mut counts = vec![0_u32; bits as usize + 1];
+	let (mut sum, mut sq_sum) = (0_f64, 0_f64);
+	for _ in 0..N_TRIALS {
+		counts.fill(0);
+		for _ in 0..n_words {
+			counts[(rng.gen::<u128>() & mask).count_ones() as usize] += 1;
+		}
+		let d = distance.of_counts(&counts, n_words) as f64;
+		sum += d;
+		sq_sum += d * d;
+	}
+	let n = N_TRIALS as f64;
+	let mean = sum / n;
+	let moments = (mean, ((sq_sum / n - mean * mean).max(0.0) * n / (n - 1.0)).sqrt());
+	CACHE.get_or_init(Default::default).lock().unwrap().insert(key, moments);
+	moments
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{diffusion::{mrxsm::MRXSM, rxsm::RXSM}, utils::sample_buffer::SampleBuffer};
+
+	#[test]
+	fn test_round_stats() {
+		let mut stats = RoundStats::new(2);
+		stats.add_round(&[1.0, 2.0]);
+		assert!(stats.std_error(0).is_nan());
+		stats.add_round(&[3.0, 2.0]);
+		let mut other = RoundStats::new(2);
+		other.add_round(&[2.0, 2.0]);
+		stats.merge(other);
+		assert_eq!(3, stats.n_rounds());
+		assert_eq!(vec![2.0, 2.0], stats.means());
+		// Sample variance 1, over 3 rounds
+		assert!((stats.std_error(0) - (1.0_f32 / 3.0).sqrt()).abs() < 1e-6);
+		assert_eq!(0.0, stats.std_error(1));
+		let mut empty = RoundStats::default();
+		empty.merge(stats.clone());
+		assert_eq!(stats, empty);
+		stats.add_round(&[1.0, 4.0]);
+		assert_eq!(1, stats.worst());
+	}
+
+	#[test]
+	fn test_race_lost() {
+		let config = SearchConfig { racing_min_rounds: 3, racing_z: 2.0, ..Default::default() };
+		let mut rounds = RoundStats::new(2);
+		rounds.add_round(&[1.0, 9.0]);
+		rounds.add_round(&[1.0, 11.0]);
+		assert_eq!(None, race_lost(&rounds, &config, 0.0));
+		rounds.add_round(&[1.0, 10.0]);
+		// Mean 10 and standard error 1 / sqrt(3) in the second component
+		let bound = race_lost(&rounds, &config, 8.0).unwrap();
+		assert!((bound - (10.0 - 2.0 / 3_f32.sqrt())).abs() < 1e-5);
+		assert_eq!(None, race_lost(&rounds, &config, 9.0));
+	}
+
+	#[test]
+	fn test_p_values() {
+		assert!((normal_sf(1.959964) - 0.025).abs() < 1e-6);
+		assert!((normal_sf(-1.0) - 0.8413447).abs() < 1e-6);
+		assert_eq!(0.5, p_lower(1.0, 0.1, 1.0, 0.1));
+		assert!(p_lower(1.0, 0.1, 2.0, 0.1) < 1e-10);
+		assert!(p_lower(2.0, 0.1, 1.0, 0.1) > 1.0 - 1e-10);
+		// The worst of many components is bound to be high.
+		assert!(p_worst_of(1.0, 1, 100, 0.0, 5.0) < 0.05);
+		assert!(p_worst_of(1.0, 64, 100, 0.0, 5.0) > 0.05);
+		assert_eq!(0.05, sidak(0.05, 1));
+		assert!((sidak(0.01, 2) - 0.0199).abs() < 1e-12);
+	}
+
+	#[test]
+	fn test_worst_bit_p_value() {
+		let samples = SampleBuffer::random(10_000, &mut StdRng::seed_from_u64(1));
+		let good = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let bad = RXSM::new(1_u64, 1, 2);
+		assert_eq!(None, worst_bit_p_value(&good, &samples, DistanceKind::Wasserstein1));
+		for distance in [DistanceKind::ChiSquared, DistanceKind::GTest] {
+			assert!(worst_bit_p_value(&good, &samples, distance).unwrap() > 1e-3, "{}", distance);
+			assert!(worst_bit_p_value(&bad, &samples, distance).unwrap() < 1e-10, "{}", distance);
+		}
+	}
+
+	#[test]
+	fn test_null_moments() {
+		let (mean, std_dev) = null_moments(DistanceKind::Wasserstein1, 64, 1000);
+		assert_eq!((mean, std_dev), null_moments(DistanceKind::Wasserstein1, 64, 1000));
+		assert!(mean > 0.0 && std_dev > 0.0);
+		// The chi-squared statistic is on average its degrees of freedom, i.e. the number of (pooled) bins minus one.
+		// 0..=2 and 14..=16 are pooled for 10000 samples.
+		let (mean, _) = null_moments(DistanceKind::ChiSquared, 16, 10_000);
+		assert!((10.0..14.0).contains(&mean), "{}", mean);
+	}
+}
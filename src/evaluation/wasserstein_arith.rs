@@ -2,7 +2,11 @@ use std::fmt::{self, Display, Formatter};
 
 use rand::Rng;
 
-use crate::{diffusion::DiffusionFunc, evaluation::bitflips::Bitflips, globals::{N_ROUNDS, N_SAMPLES}, utils::wasserstein::normalise};
+use crate::{
+	config::SearchConfig, diffusion::DiffusionFunc,
+	evaluation::{bitflips::Bitflips, stats::{self, RoundStats}},
+	utils::word::{Word, MAX_BITS},
+};
 
 use super::Evaluator;
 
@@ -12,14 +16,18 @@ use super::Evaluator;
    function, for each input bit, the number of bit flips in the output when the input bit is flipped follows a binomial
    distribution and we measure the quality of our diffusion function by measuring the distance to that binomial
    distribution and taking the maximum over all input bits. To avoid outliers, we subdivide the samples and average the
-   calculated distances for each input bit over multiple rounds.
+   calculated distances for each input bit over multiple rounds. Other distances than the 1-Wasserstein distance can be
+   picked through `SearchConfig::distance` (see `utils::distance::DistanceKind`).
 
    Over the course of the genetic algorithm the results of this loss function are averaged over the generations that
    this 'Evaluator' lives by simply taking the arithmetic mean. This tends towards higher values and so, getting unlucky
    in one generation might disproportionately affect the averaged score.  */
 pub struct WassersteinArith<F: DiffusionFunc> {
-	#[serde(with="serde_arrays")]
-	w1s: [f32; 64],
+	/** One entry per input bit, i.e. as many as the function's word has bits. */
+	w1s: Vec<f32>,
+	/** The distances of each round, for the standard error of the loss. */
+	#[serde(default)]
+	rounds: RoundStats,
 	max_w1: f32,
 	age: u32,
 	func: F
@@ -28,24 +36,22 @@ pub struct WassersteinArith<F: DiffusionFunc> {
 impl<F: DiffusionFunc> WassersteinArith<F> {
 	/** Updates the stored Wasserstein distances of this diffusion function and returns the updated maximum value. */
 	#[inline(always)]
-	fn update_w1s(&mut self, w1s: [f32; 64]) -> f32 {
+	fn update_w1s(&mut self, w1s: Vec<f32>) -> f32 {
 		self.age += 1;
 		let mut loss = 0_f32;
 		if self.age == 1 {
 			// this is the first SSE calculated
 			self.w1s = w1s;
-			for w in self.w1s {
+			for &w in self.w1s.iter() {
 				if w > loss {
 					loss = w;
 				}
 			}
 		} else {
-			#[allow(clippy::needless_range_loop)]
-			for i in 0..64 {
-                let w1 = self.w1s[i] + w1s[i];
-				self.w1s[i] = w1;
-				if w1 > loss {
-					loss = w1;
+			for (prev, new) in self.w1s.iter_mut().zip(w1s) {
+				*prev += new;
+				if *prev > loss {
+					loss = *prev;
 				}
 			}
 			loss /= self.age as f32;
@@ -57,35 +63,81 @@ impl<F: DiffusionFunc> WassersteinArith<F> {
 
 impl<F: DiffusionFunc> Evaluator<F> for WassersteinArith<F> {
 	fn new(func: F) -> Self {
-		Self { w1s: [f32::MAX; 64], max_w1: f32::MAX, age: 0, func }
+		Self { w1s: Vec::new(), rounds: RoundStats::default(), max_w1: f32::MAX, age: 0, func }
 	}
 
-	fn random(rng: &mut impl Rng) -> Self {
-		Self::new(F::random(rng))
+	fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self {
+		Self::new(F::random(config, rng))
 	}
 
     fn get_age(&self) -> u32 { self.age }
 
     fn get_loss(&self) -> f32 { self.max_w1 }
 
+	fn get_std_error(&self) -> f32 { self.rounds.std_error(worst_index(&self.w1s)) }
+
+	fn get_p_value(&self, config: &SearchConfig) -> Option<f64> {
+		if self.age == 0 {
+			return None;
+		}
+		let n_words = config.n_samples_per_round() / F::Word::SAMPLE_LEN as u32;
+		let (mean, std_dev) = stats::null_moments(config.distance, F::Word::BITS, n_words);
+		Some(stats::p_worst_of(self.max_w1, self.w1s.len(), self.rounds.n_rounds(), mean, std_dev))
+	}
+
 	fn get_func(&self) -> &F { &self.func }
 
-    fn update(&mut self, samples: &[u64; N_SAMPLES as usize]) -> f32 {
-		const N_SAMPLES_PER_ROUND: u32 = N_SAMPLES/N_ROUNDS;
-        let mut avg = [0_f32; 64];
-		for round in 0..N_ROUNDS {
-			let l = (round * N_SAMPLES_PER_ROUND) as usize;
-			let samples = &samples[l..(l + N_SAMPLES_PER_ROUND as usize)];
-			let w1s = Bitflips::of(&self.func, samples.try_into().unwrap()).w1s();
-			for i in 0..64 {
-				avg[i] += w1s[i];
+	fn get_case_losses(&self) -> Vec<f32> { self.w1s.iter().map(|&w1| w1 / self.age.max(1) as f32).collect() }
+
+    fn update(&mut self, samples: &[u64], config: &SearchConfig) -> f32 {
+		self.evaluate(samples, config, None);
+		self.max_w1
+    }
+
+	fn update_racing(&mut self, samples: &[u64], config: &SearchConfig, cutoff: f32) -> bool {
+		self.evaluate(samples, config, Some(cutoff))
+	}
+}
+
+impl<F: DiffusionFunc> WassersteinArith<F> {
+	/** Evaluates all rounds or, racing against a `cutoff`, only until the loss is bound to exceed it. Returns whether
+	   all rounds were evaluated. */
+	fn evaluate(&mut self, samples: &[u64], config: &SearchConfig, cutoff: Option<f32>) -> bool {
+		debug_assert!(samples.len() == config.n_samples as usize);
+		let n_samples_per_round = config.n_samples_per_round();
+		let mut rounds = RoundStats::new(F::Word::BITS as usize);
+		let mut distances = [0_f32; MAX_BITS];
+		let distances = &mut distances[..F::Word::BITS as usize];
+		let mut bound = None;
+		for round in 0..config.n_rounds {
+			let l = (round * n_samples_per_round) as usize;
+			let samples = &samples[l..(l + n_samples_per_round as usize)];
+			Bitflips::of(&self.func, samples).distances(&config.distance, distances);
+			rounds.add_round(distances);
+			bound = cutoff.and_then(|cutoff| stats::race_lost(&rounds, config, cutoff));
+			if bound.is_some() {
+				break;
 			}
 		}
-		for a in avg.iter_mut() {
-			*a /= N_ROUNDS as f32;
+		let avg = rounds.means();
+		self.rounds.merge(rounds);
+		self.update_w1s(avg);
+		if let Some(bound) = bound {
+			self.max_w1 = bound;
 		}
-		self.update_w1s(avg)
-    }
+		bound.is_none()
+	}
+}
+
+/** The index of the largest of the given distances, i.e. of the one that makes up the loss. */
+pub(crate) fn worst_index(w1s: &[f32]) -> usize {
+	let mut worst = 0;
+	for (i, &w1) in w1s.iter().enumerate() {
+		if w1 > w1s[worst] {
+			worst = i;
+		}
+	}
+	worst
 }
 
 impl<F: DiffusionFunc> Display for WassersteinArith<F> {
@@ -102,15 +154,15 @@ impl<F: DiffusionFunc> Display for WassersteinArith<F> {
 #[cfg(test)]
 mod tests {
 	use super::*;
-    use crate::{diffusion::mrxsm::MRXSM};
+    use crate::{diffusion::mrxsm::MRXSM, utils::sample_buffer::SampleBuffer};
 
 	#[test]
 	fn test_update_w1s() {
-		let f = MRXSM::default();
+		let f = MRXSM::<u64>::default();
 		let mut e = WassersteinArith::new(f);
-		e.update_w1s([1.0; 64]);
+		e.update_w1s(vec![1.0; 64]);
 		for _ in 0..100 {
-			e.update_w1s([1.0; 64]);
+			e.update_w1s(vec![1.0; 64]);
 		}
 		assert_eq!(1.0, e.get_loss());
 
@@ -119,10 +171,28 @@ mod tests {
 			let mut prod = 1.0;
 			for _ in 0..10 {
 				let w: f32 = rng.gen_range(1.0..2.0);
-				e.update_w1s([w; 64]);
+				e.update_w1s(vec![w; 64]);
 				prod *= w as f64;
 			}
 			assert!((e.get_loss() as i64 - prod.powf(0.1).round() as i64).abs() <= 1);
 		}
 	}
+
+	#[test]
+	fn test_update_racing() {
+		let config = SearchConfig { n_samples: 20_000, n_rounds: 20, ..Default::default() };
+		let samples = SampleBuffer::random(config.n_samples as usize, &mut rand::thread_rng());
+		let good = WassersteinArith::new(MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59));
+		let mut raced = good.clone();
+		let mut full = good;
+		let loss = full.update(&samples, &config);
+		// Survivors end up exactly as without racing.
+		assert!(raced.update_racing(&samples, &config, loss + 1.0));
+		assert_eq!(full, raced);
+
+		let mut bad = WassersteinArith::new(MRXSM::new(0x3_u64, 0x5, 0, 63));
+		assert!(!bad.update_racing(&samples, &config, loss));
+		assert!(bad.get_loss() > loss);
+		assert_eq!(config.racing_min_rounds, bad.rounds.n_rounds());
+	}
 }
@@ -2,7 +2,11 @@ use std::fmt::{self, Display, Formatter};
 
 use rand::Rng;
 
-use crate::{diffusion::DiffusionFunc, evaluation::bitflips::Bitflips, globals::{N_ROUNDS, N_SAMPLES}, utils::wasserstein::normalise};
+use crate::{
+	config::SearchConfig, diffusion::DiffusionFunc,
+	evaluation::{bitflips::Bitflips, stats::{self, RoundStats}, wasserstein_arith::worst_index},
+	utils::word::{Word, MAX_BITS},
+};
 
 use super::Evaluator;
 
@@ -12,14 +16,18 @@ use super::Evaluator;
    function, for each input bit, the number of bit flips in the output when the input bit is flipped follows a binomial
    distribution and we measure the quality of our diffusion function by measuring the distance to that binomial
    distribution and taking the maximum over all input bits. To avoid outliers, we subdivide the samples and average the
-   calculated distances for each input bit over multiple rounds.
+   calculated distances for each input bit over multiple rounds. Other distances than the 1-Wasserstein distance can be
+   picked through `SearchConfig::distance` (see `utils::distance::DistanceKind`).
 
    Over the course of the genetic algorithm the results of this loss function are averaged over the generations that
    this 'Evaluator' lives by taking the geometric mean. This is always '<=' the usual, arithmetic mean (which favours
    higher values) and so, getting unlucky in one generation will have less of an effect on the averaged score. */
 pub struct WassersteinGeom<F: DiffusionFunc> {
-	#[serde(with="serde_arrays")]
-	w1s: [f32; 64],
+	/** One entry per input bit, i.e. as many as the function's word has bits. */
+	w1s: Vec<f32>,
+	/** The distances of each round, for the standard error of the loss. */
+	#[serde(default)]
+	rounds: RoundStats,
 	max_w1: f32,
 	age: u32,
 	func: F
@@ -28,13 +36,13 @@ pub struct WassersteinGeom<F: DiffusionFunc> {
 impl<F: DiffusionFunc> WassersteinGeom<F> {
 	/** Updates the stored Wasserstein distances of this diffusion function and returns the updated maximum value. */
 	#[inline(always)]
-	fn update_w1s(&mut self, w1s: [f32; 64]) -> f32 {
+	fn update_w1s(&mut self, w1s: Vec<f32>) -> f32 {
 		self.age += 1;
 		let mut m = 0_f32;
 		if self.age == 1 {
 			// this is the first SSE calculated
 			self.w1s = w1s;
-			for w in self.w1s {
+			for &w in self.w1s.iter() {
 				if w > m {
 					m = w;
 				}
@@ -63,42 +71,110 @@ impl<F: DiffusionFunc> WassersteinGeom<F> {
 
 impl<F: DiffusionFunc> Evaluator<F> for WassersteinGeom<F> {
 	fn new(func: F) -> Self {
-		Self { w1s: [f32::MAX; 64], max_w1: f32::MAX, age: 0, func }
+		Self { w1s: Vec::new(), rounds: RoundStats::default(), max_w1: f32::MAX, age: 0, func }
 	}
 
-	fn random(rng: &mut impl Rng) -> Self {
-		Self::new(F::random(rng))
+	fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self {
+		Self::new(F::random(config, rng))
 	}
 
     fn get_age(&self) -> u32 { self.age }
 
     fn get_loss(&self) -> f32 { self.max_w1 }
 
+	/** The standard error of the arithmetic mean over all rounds, which the geometric mean over generations doesn't
+	   exceed. */
+	fn get_std_error(&self) -> f32 { self.rounds.std_error(worst_index(&self.w1s)) }
+
+	/** Based on the arithmetic mean over all rounds (see `get_std_error`). */
+	fn get_p_value(&self, config: &SearchConfig) -> Option<f64> {
+		if self.age == 0 {
+			return None;
+		}
+		let n_words = config.n_samples_per_round() / F::Word::SAMPLE_LEN as u32;
+		let (mean, std_dev) = stats::null_moments(config.distance, F::Word::BITS, n_words);
+		let loss = self.rounds.mean(worst_index(&self.w1s));
+		Some(stats::p_worst_of(loss, self.w1s.len(), self.rounds.n_rounds(), mean, std_dev))
+	}
+
 	fn get_func(&self) -> &F { &self.func }
 
-    fn update(&mut self, samples: &[u64; (crate::globals::N_SAMPLES) as usize]) -> f32 {
-		const N_SAMPLES_PER_ROUND: u32 = N_SAMPLES/N_ROUNDS;
-        let mut avg = [0_f32; 64];
-		for round in 0..N_ROUNDS as usize {
-			let samples = &samples[round..(round + N_SAMPLES_PER_ROUND as usize)];
-			let w1s = Bitflips::of(&self.func, samples.try_into().unwrap()).w1s();
-			for i in 0..64 {
-				avg[i] += w1s[i];
+	fn get_case_losses(&self) -> Vec<f32> {
+		if self.age < 2 {
+			// Not on the log scale yet (see `update_w1s`)
+			return self.w1s.clone();
+		}
+		self.w1s.iter().map(|&w1| (w1 / self.age as f32).exp2() - 1.0).collect()
+	}
+
+    fn update(&mut self, samples: &[u64], config: &SearchConfig) -> f32 {
+		self.evaluate(samples, config, None);
+		self.max_w1
+    }
+
+	fn update_racing(&mut self, samples: &[u64], config: &SearchConfig, cutoff: f32) -> bool {
+		self.evaluate(samples, config, Some(cutoff))
+	}
+}
+
+impl<F: DiffusionFunc> WassersteinGeom<F> {
+	/** Evaluates all rounds or, racing against a `cutoff`, only until the loss is bound to exceed it. Returns whether
+	   all rounds were evaluated. */
+	fn evaluate(&mut self, samples: &[u64], config: &SearchConfig, cutoff: Option<f32>) -> bool {
+		debug_assert!(samples.len() == config.n_samples as usize);
+		let n_samples_per_round = config.n_samples_per_round() as usize;
+		let mut rounds = RoundStats::new(F::Word::BITS as usize);
+		let mut distances = [0_f32; MAX_BITS];
+		let distances = &mut distances[..F::Word::BITS as usize];
+		let mut bound = None;
+		for round in 0..config.n_rounds as usize {
+			let l = round * n_samples_per_round;
+			let samples = &samples[l..(l + n_samples_per_round)];
+			Bitflips::of(&self.func, samples).distances(&config.distance, distances);
+			rounds.add_round(distances);
+			bound = cutoff.and_then(|cutoff| stats::race_lost(&rounds, config, cutoff));
+			if bound.is_some() {
+				break;
 			}
 		}
-		for a in avg.iter_mut() {
-			*a /= N_ROUNDS as f32;
+		let avg = rounds.means();
+		self.rounds.merge(rounds);
+		self.update_w1s(avg);
+		if let Some(bound) = bound {
+			self.max_w1 = bound;
 		}
-		self.update_w1s(avg)
-    }
+		bound.is_none()
+	}
 }
 
 impl<F: DiffusionFunc> Display for WassersteinGeom<F> {
 	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
 		write!(f, "W1G{{")?;
 		write!(f, "loss:{}, ", self.get_loss())?;
-		write!(f, "age:{}", self.get_age())?;
+		write!(f, "age:{}, ", self.get_age())?;
 		write!(f, "func:{}", self.get_func())?;
 		write!(f, "}}")
 	}
 }
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		diffusion::mrxsm::MRXSM, evaluation::wasserstein_arith::WassersteinArith, utils::sample_buffer::SampleBuffer,
+	};
+
+	#[test]
+	fn test_std_error() {
+		let config = SearchConfig { n_samples: 20_000, n_rounds: 20, ..Default::default() };
+		let samples = SampleBuffer::random(config.n_samples as usize, &mut rand::thread_rng());
+		let f = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let mut arith = WassersteinArith::new(f.clone());
+		let mut geom = WassersteinGeom::new(f);
+		// After a single update, both have seen the same rounds and agree on the worst input bit.
+		assert_eq!(arith.update(&samples, &config), geom.update(&samples, &config));
+		assert!(arith.get_std_error() > 0.0);
+		assert_eq!(arith.get_std_error(), geom.get_std_error());
+	}
+}
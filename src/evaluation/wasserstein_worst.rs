@@ -0,0 +1,190 @@
+use std::fmt::{self, Display, Formatter};
+
+use rand::Rng;
+
+use crate::{
+	config::SearchConfig,
+	diffusion::DiffusionFunc,
+	evaluation::{
+		bitflips::Bitflips, differences::InputDifference, stats::{self, RoundStats}, wasserstein_arith::worst_index,
+	},
+	utils::word::Word,
+};
+
+use super::Evaluator;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+
+/** 'Evaluator' that, like `WassersteinArith`, uses the 1-Wasserstein distance to a Binom(bits, .5) but not only for
+   inputs that differ in a single bit. Instead, it looks at the whole set of input differences configured in
+   `SearchConfig::differences` (e.g. pairs of bits, bytes or carry chains `x + 1`) and the loss is the worst distance
+   over that set. As for `WassersteinArith`, the distances are averaged over multiple rounds and over the generations
+   that this 'Evaluator' lives (arithmetic mean).
+
+   Depending on the set, this can be a lot more expensive than `WassersteinArith`: all pairs of bits are 2016
+   differences for 64-bit words instead of 64. Like there, `SearchConfig::distance` can replace the 1-Wasserstein
+   distance. */
+pub struct WassersteinWorst<F: DiffusionFunc> {
+	/** One entry per input difference. */
+	w1s: Vec<f32>,
+	/** The input difference with the largest distance. */
+	worst: Option<InputDifference>,
+	/** The distances of each round, for the standard error of the loss. */
+	#[serde(default)]
+	rounds: RoundStats,
+	max_w1: f32,
+	age: u32,
+	func: F
+}
+
+impl<F: DiffusionFunc> WassersteinWorst<F> {
+	/** Updates the stored Wasserstein distances of this diffusion function and returns the updated maximum value. */
+	#[inline(always)]
+	fn update_w1s(&mut self, w1s: Vec<f32>, diffs: &[InputDifference]) -> f32 {
+		self.age += 1;
+		if self.age == 1 || self.w1s.len() != w1s.len() {
+			// the first distances calculated (or the difference set changed, e.g. when resuming with another config)
+			self.age = 1;
+			self.w1s = w1s;
+		} else {
+			for (prev, new) in self.w1s.iter_mut().zip(w1s) {
+				*prev += new;
+			}
+		}
+		let mut loss = 0_f32;
+		for (&w1, &diff) in self.w1s.iter().zip(diffs) {
+			if w1 > loss {
+				loss = w1;
+				self.worst = Some(diff);
+			}
+		}
+		self.max_w1 = loss / self.age as f32;
+		self.max_w1
+	}
+}
+
+impl<F: DiffusionFunc> Evaluator<F> for WassersteinWorst<F> {
+	fn new(func: F) -> Self {
+		Self { w1s: Vec::new(), worst: None, rounds: RoundStats::default(), max_w1: f32::MAX, age: 0, func }
+	}
+
+	fn random(config: &SearchConfig, rng: &mut impl Rng) -> Self {
+		Self::new(F::random(config, rng))
+	}
+
+	fn get_age(&self) -> u32 { self.age }
+
+	fn get_loss(&self) -> f32 { self.max_w1 }
+
+	fn get_std_error(&self) -> f32 { self.rounds.std_error(worst_index(&self.w1s)) }
+
+	fn get_p_value(&self, config: &SearchConfig) -> Option<f64> {
+		if self.age == 0 {
+			return None;
+		}
+		// Under the null hypothesis, every input difference gives Bin(bits, 0.5) just like single bits.
+		let n_words = config.n_samples_per_round() / F::Word::SAMPLE_LEN as u32;
+		let (mean, std_dev) = stats::null_moments(config.distance, F::Word::BITS, n_words);
+		Some(stats::p_worst_of(self.max_w1, self.w1s.len(), self.rounds.n_rounds(), mean, std_dev))
+	}
+
+	fn get_func(&self) -> &F { &self.func }
+
+	/** By input difference. */
+	fn get_case_losses(&self) -> Vec<f32> { self.w1s.iter().map(|&w1| w1 / self.age.max(1) as f32).collect() }
+
+	fn update(&mut self, samples: &[u64], config: &SearchConfig) -> f32 {
+		self.evaluate(samples, config, None);
+		self.max_w1
+	}
+
+	fn update_racing(&mut self, samples: &[u64], config: &SearchConfig, cutoff: f32) -> bool {
+		self.evaluate(samples, config, Some(cutoff))
+	}
+}
+
+impl<F: DiffusionFunc> WassersteinWorst<F> {
+	/** Evaluates all rounds or, racing against a `cutoff`, only until the loss is bound to exceed it. Returns whether
+	   all rounds were evaluated. */
+	fn evaluate(&mut self, samples: &[u64], config: &SearchConfig, cutoff: Option<f32>) -> bool {
+		debug_assert!(samples.len() == config.n_samples as usize);
+		let diffs = InputDifference::set::<F::Word>(config);
+		let n_samples_per_round = config.n_samples_per_round() as usize;
+		let mut rounds = RoundStats::new(diffs.len());
+		let mut distances = vec![0_f32; diffs.len()];
+		let bits = F::Word::BITS as usize;
+		let mut bound = None;
+		for samples in samples.chunks_exact(n_samples_per_round) {
+			for (diffs, distances) in diffs.chunks(bits).zip(distances.chunks_mut(bits)) {
+				Bitflips::of_differences(&self.func, samples, diffs).distances(&config.distance, distances);
+			}
+			rounds.add_round(&distances);
+			bound = cutoff.and_then(|cutoff| stats::race_lost(&rounds, config, cutoff));
+			if bound.is_some() {
+				break;
+			}
+		}
+		let avg = rounds.means();
+		self.rounds.merge(rounds);
+		self.update_w1s(avg, &diffs);
+		if let Some(bound) = bound {
+			self.max_w1 = bound;
+		}
+		bound.is_none()
+	}
+}
+
+impl<F: DiffusionFunc> Display for WassersteinWorst<F> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		write!(f, "W1W{{")?;
+		write!(f, "loss:{}, ", self.get_loss())?;
+		write!(f, "age:{}, ", self.get_age())?;
+		if let Some(worst) = self.worst {
+			write!(f, "worst:{}, ", worst)?;
+		}
+		write!(f, "func:{}", self.get_func())?;
+		write!(f, "}}")
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		diffusion::mrxsm::MRXSM,
+		evaluation::{differences::DifferenceKind, wasserstein_arith::WassersteinArith},
+		utils::sample_buffer::SampleBuffer,
+	};
+
+	#[test]
+	fn test_update_w1s() {
+		let mut e = WassersteinWorst::new(MRXSM::<u64>::default());
+		let diffs = [InputDifference::Xor(1), InputDifference::Add(1)];
+		e.update_w1s(vec![1.0, 2.0], &diffs);
+		assert_eq!(2.0, e.get_loss());
+		assert_eq!(Some(InputDifference::Add(1)), e.worst);
+		e.update_w1s(vec![4.0, 1.0], &diffs);
+		assert_eq!(2.5, e.get_loss());
+		assert_eq!(Some(InputDifference::Xor(1)), e.worst);
+	}
+
+	#[test]
+	fn test_update() {
+		let config = SearchConfig { n_samples: 10_000, n_rounds: 10, differences: vec![DifferenceKind::Bits],
+			..Default::default() };
+		let samples = SampleBuffer::random(config.n_samples as usize, &mut rand::thread_rng());
+		let f = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		// With single bits only, this is just `WassersteinArith`.
+		let mut arith = WassersteinArith::new(f.clone());
+		let mut worst = WassersteinWorst::new(f.clone());
+		assert_eq!(arith.update(&samples, &config), worst.update(&samples, &config));
+
+		// On the same samples, a larger set of differences can only be worse.
+		let config = SearchConfig { differences: vec![DifferenceKind::Bits, DifferenceKind::Deltas], ..config };
+		let mut worst = WassersteinWorst::new(f);
+		let loss = worst.update(&samples, &config);
+		assert!(loss >= arith.get_loss());
+		assert!(worst.to_string().contains("worst:"));
+	}
+}
@@ -0,0 +1,347 @@
+use std::{
+	error::Error,
+	fmt::{self, Display, Formatter},
+	fs::{self, File},
+	io::{self, BufReader, BufWriter, Read},
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::Arc,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+	config::SearchConfig, diffusion::inverse::InvertibleDiffusion, evaluation::Evaluator,
+	utils::sample_source::SampleSource,
+};
+
+use super::Evolution;
+
+/** The version of the checkpoint format. Bumped whenever a change to it (or to anything stored in it) keeps older
+   checkpoints from being read correctly. */
+pub const VERSION: u32 = 1;
+
+/** How checkpoints are encoded (see `SearchConfig::checkpoint_format`). Either way, a checkpoint is the format version,
+   followed by the configuration of the search and its `Evolution`, which includes the state of its random number
+   generators. */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckpointFormat {
+	/** Human-readable, in `<thread>.json`. */
+	#[default]
+	Json,
+	/** Compact and fast to read and write (bincode), in `<thread>.bin`. */
+	Binary,
+}
+
+impl CheckpointFormat {
+	pub const ALL: [CheckpointFormat; 2] = [CheckpointFormat::Json, CheckpointFormat::Binary];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			CheckpointFormat::Json => "json",
+			CheckpointFormat::Binary => "binary",
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			CheckpointFormat::Json => "json",
+			CheckpointFormat::Binary => "bin",
+		}
+	}
+}
+
+impl Display for CheckpointFormat {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for CheckpointFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		CheckpointFormat::ALL.into_iter().find(|format| format.name() == s)
+			.ok_or_else(|| format!("unknown checkpoint format {}", s))
+	}
+}
+
+
+/** The path of the checkpoint of thread `thread_num` in the given format. */
+pub fn path(state_dir: &Path, thread_num: u32, format: CheckpointFormat) -> PathBuf {
+	state_dir.join(format!("{}.{}", thread_num, format.extension()))
+}
+
+/** The checkpoint of thread `thread_num` in `state_dir`, in whichever format it was written. */
+pub fn find(state_dir: &Path, thread_num: u32) -> Option<(PathBuf, CheckpointFormat)> {
+	CheckpointFormat::ALL.into_iter().map(|format| (path(state_dir, thread_num, format), format))
+		.find(|(path, _)| path.exists())
+}
+
+
+/** A checkpoint as it is written. */
+#[derive(Serialize)]
+struct StoredRef<'a, F, E> {
+	version: u32,
+	config: &'a SearchConfig,
+	evolution: &'a Evolution<F, E>,
+}
+
+/** What is read of a JSON checkpoint before the evolution, which can only be read once these have been checked. */
+#[derive(Deserialize)]
+struct Header {
+	/** Missing in states written before checkpoints had a version. */
+	#[serde(default)]
+	version: u32,
+	config: Option<SearchConfig>,
+}
+
+/** A checkpoint read by `load`, which can be resumed. */
+#[derive(Debug, Deserialize)]
+pub struct Checkpoint<F, E> {
+	#[serde(skip)]
+	pub path: PathBuf,
+	/** The configuration of the search that wrote the checkpoint. */
+	pub config: SearchConfig,
+	pub evolution: Evolution<F, E>,
+}
+
+impl<F: InvertibleDiffusion, E: Evaluator<F> + Send> Checkpoint<F, E> {
+	/** Continues the search with `config`, which may differ from the stored configuration in anything but the size of
+	   a generation. The current samples are drawn again from `source`, so if it's configured as before, the search
+	   goes on exactly as it would have. */
+	pub fn resume(self, config: SearchConfig, source: Arc<dyn SampleSource>)
+		-> Result<Evolution<F, E>, CheckpointError> {
+		check_same(&self.path, "generation_size", self.config.generation_size, config.generation_size)?;
+		let stored_size = self.evolution.current_gen.len() as u32;
+		check_same(&self.path, "generation_size", stored_size, config.generation_size)?;
+		Ok(self.evolution.with_config(config, source))
+	}
+}
+
+
+/** Writes the checkpoint of `evolution` for thread `thread_num` in the configured format. It is written to a temporary
+   file first, which then replaces the previous checkpoint (in either format), so an interrupted write never leaves a
+   broken checkpoint behind. */
+pub fn store<F: Serialize, E: Serialize>(state_dir: &Path, thread_num: u32, evolution: &Evolution<F, E>)
+	-> Result<(), CheckpointError> {
+	let config = &evolution.config;
+	let format = config.checkpoint_format;
+	let path = path(state_dir, thread_num, format);
+	let tmp = path.with_extension(format!("{}.tmp", format.extension()));
+	let io_err = |e| CheckpointError::Io(path.clone(), e);
+	fs::create_dir_all(state_dir).map_err(io_err)?;
+	let mut writer = BufWriter::new(File::create(&tmp).map_err(io_err)?);
+	let stored = StoredRef { version: VERSION, config, evolution };
+	match format {
+		CheckpointFormat::Json => serde_json::to_writer(&mut writer, &stored).map_err(|e| e.to_string()),
+		CheckpointFormat::Binary => bincode::serialize_into(&mut writer, &stored).map_err(|e| e.to_string()),
+	}.map_err(|why| CheckpointError::Encoding(path.clone(), why))?;
+	let file = writer.into_inner().map_err(|e| io_err(e.into_error()))?;
+	file.sync_all().map_err(io_err)?;
+	fs::rename(&tmp, &path).map_err(io_err)?;
+	// A checkpoint in another format would be stale now.
+	for other in CheckpointFormat::ALL.into_iter().filter(|&other| other != format) {
+		match fs::remove_file(self::path(state_dir, thread_num, other)) {
+			Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(io_err(e)),
+			_ => {}
+		}
+	}
+	Ok(())
+}
+
+/** Reads the checkpoint of thread `thread_num` from `state_dir`, if there is one. Fails if it's broken, has another
+   format version or was written by a search of another family, word width or evaluator than `config`'s. */
+pub fn load<F: DeserializeOwned, E: DeserializeOwned>(state_dir: &Path, thread_num: u32, config: &SearchConfig)
+	-> Result<Option<Checkpoint<F, E>>, CheckpointError> {
+	let Some((path, format)) = find(state_dir, thread_num) else {
+		return Ok(None);
+	};
+	let encoding_err = |why: String| CheckpointError::Encoding(path.clone(), why);
+	let mut reader = BufReader::new(File::open(&path).map_err(|e| CheckpointError::Io(path.clone(), e))?);
+	let mut checkpoint: Checkpoint<F, E> = match format {
+		CheckpointFormat::Json => {
+			let mut s = String::new();
+			reader.read_to_string(&mut s).map_err(|e| CheckpointError::Io(path.clone(), e))?;
+			let header: Header = serde_json::from_str(&s).map_err(|e| encoding_err(e.to_string()))?;
+			check_header(&path, header.version, header.config.as_ref(), config)?;
+			serde_json::from_str(&s).map_err(|e| encoding_err(e.to_string()))?
+		}
+		CheckpointFormat::Binary => {
+			let version: u32 = bincode::deserialize_from(&mut reader).map_err(|e| encoding_err(e.to_string()))?;
+			check_header(&path, version, None, config)?;
+			let stored_config: SearchConfig =
+				bincode::deserialize_from(&mut reader).map_err(|e| encoding_err(e.to_string()))?;
+			check_header(&path, version, Some(&stored_config), config)?;
+			let evolution = bincode::deserialize_from(&mut reader).map_err(|e| encoding_err(e.to_string()))?;
+			Checkpoint { path: PathBuf::new(), config: stored_config, evolution }
+		}
+	};
+	checkpoint.path = path;
+	Ok(Some(checkpoint))
+}
+
+/** Checks the version and, if it's known already, the stored configuration of a checkpoint before the rest is read. */
+fn check_header(path: &Path, version: u32, stored: Option<&SearchConfig>, config: &SearchConfig)
+	-> Result<(), CheckpointError> {
+	if version != VERSION {
+		return Err(CheckpointError::Version(path.to_path_buf(), version));
+	}
+	let Some(stored) = stored else {
+		return Ok(());
+	};
+	check_same(path, "family", stored.family, config.family)?;
+	check_same(path, "word_width", stored.word_width, config.word_width)?;
+	check_same(path, "evaluator", stored.evaluator, config.evaluator)
+}
+
+fn check_same<T: PartialEq + Display>(path: &Path, field: &'static str, stored: T, given: T)
+	-> Result<(), CheckpointError> {
+	if stored == given {
+		Ok(())
+	} else {
+		let (stored, given) = (stored.to_string(), given.to_string());
+		Err(CheckpointError::Incompatible { path: path.to_path_buf(), field, stored, given })
+	}
+}
+
+
+#[derive(Debug)]
+pub enum CheckpointError {
+	Io(PathBuf, io::Error),
+	/** The checkpoint couldn't be encoded or decoded. */
+	Encoding(PathBuf, String),
+	/** The checkpoint has another format version than `VERSION` (0 for states written before there were versions). */
+	Version(PathBuf, u32),
+	/** The checkpoint was written by a search with another value of `field` than the given configuration. */
+	Incompatible { path: PathBuf, field: &'static str, stored: String, given: String },
+}
+
+impl Display for CheckpointError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			CheckpointError::Io(path, e) => write!(f, "couldn't access checkpoint {}: {}", path.display(), e),
+			CheckpointError::Encoding(path, why) => write!(f, "invalid checkpoint {}: {}", path.display(), why),
+			CheckpointError::Version(path, 0) => {
+				write!(f, "checkpoint {} predates versioned checkpoints and can't be read", path.display())
+			}
+			CheckpointError::Version(path, version) => write!(f, "checkpoint {} has format version {}, but only \
+				version {} can be read", path.display(), version, VERSION),
+			CheckpointError::Incompatible { path, field, stored, given } => write!(f, "checkpoint {} was written \
+				with {} {}, which can't be resumed with {}", path.display(), field, stored, given),
+		}
+	}
+}
+
+impl Error for CheckpointError {}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		diffusion::mrxsm::MRXSM,
+		evaluation::{wasserstein_arith::WassersteinArith, EvaluatorKind},
+		evolution::Racing,
+	};
+
+	type Ev = Evolution<MRXSM, WassersteinArith<MRXSM>>;
+
+	fn state_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("diffusion-checkpoint-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		dir
+	}
+
+	#[test]
+	fn test_store_and_load() {
+		for format in CheckpointFormat::ALL {
+			let dir = state_dir(format.name());
+			let config = SearchConfig {
+				seed: Some(3),
+				generation_size: 8,
+				elitism: 2,
+				n_samples: 1000,
+				n_rounds: 10,
+				checkpoint_format: format,
+				..Default::default()
+			};
+			let source = config.sample_source().unwrap();
+			let mut ev = Ev::random(config.clone(), source.clone(), 1);
+			ev.next_gen();
+			assert!(load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 1, &config).unwrap().is_none());
+			store(&dir, 1, &ev).unwrap();
+			let checkpoint = load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 1, &config).unwrap().unwrap();
+			assert_eq!(config, checkpoint.config);
+			let mut resumed = checkpoint.resume(config.clone(), source.clone()).unwrap();
+			assert_eq!(ev.samples, resumed.samples);
+			ev.next_gen();
+			resumed.next_gen();
+			assert_eq!(ev.current_gen.members, resumed.current_gen.members);
+			assert_eq!(vec![format!("1.{}", format.extension())],
+				fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect::<Vec<_>>());
+
+			let geom = SearchConfig { evaluator: EvaluatorKind::WassersteinGeom, ..config.clone() };
+			let err = load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 1, &geom).unwrap_err();
+			assert!(matches!(err, CheckpointError::Incompatible { field: "evaluator", .. }), "{}", err);
+			let larger = SearchConfig { generation_size: 16, ..config.clone() };
+			let checkpoint = load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 1, &larger).unwrap().unwrap();
+			let err = checkpoint.resume(larger.clone(), source.clone()).unwrap_err();
+			assert!(matches!(err, CheckpointError::Incompatible { .. }), "{}", err);
+			// The stored generation itself is checked too, not only the stored configuration.
+			let mut checkpoint = load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 1, &config).unwrap().unwrap();
+			checkpoint.config = larger.clone();
+			assert!(matches!(checkpoint.resume(larger, source), Err(CheckpointError::Incompatible { .. })));
+			fs::remove_dir_all(&dir).unwrap();
+		}
+	}
+
+	#[test]
+	fn test_exact_resume() {
+		// Stopped in the middle of the lifetime of the samples, with racing and on another island than the first.
+		let dir = state_dir("resume");
+		let config = SearchConfig {
+			seed: Some(11),
+			n_threads: 3,
+			generation_size: 16,
+			elitism: 4,
+			n_samples: 2000,
+			n_rounds: 10,
+			sample_lifetime: 4,
+			racing: Racing::Tournament,
+			racing_min_rounds: 2,
+			..Default::default()
+		};
+		let source = config.sample_source().unwrap();
+		let mut ev = Ev::random(config.clone(), source.clone(), 2);
+		for _ in 0..6 {
+			ev.next_gen();
+		}
+		store(&dir, 2, &ev).unwrap();
+		let checkpoint = load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 2, &config).unwrap().unwrap();
+		let mut resumed = checkpoint.resume(SearchConfig { seed: Some(12), ..config }, source).unwrap();
+		assert_eq!(11, resumed.seed());
+		for _ in 0..5 {
+			ev.next_gen();
+			resumed.next_gen();
+		}
+		assert_eq!(ev.generation_counter, resumed.generation_counter);
+		assert_eq!(ev.samples, resumed.samples);
+		assert_eq!(ev.current_gen.members, resumed.current_gen.members);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn test_invalid_checkpoints() {
+		let dir = state_dir("invalid");
+		let config = SearchConfig::default();
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(path(&dir, 0, CheckpointFormat::Json), r#"{"generation_counter": 3, "current_gen": []}"#).unwrap();
+		let err = load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 0, &config).unwrap_err();
+		assert!(matches!(err, CheckpointError::Version(_, 0)), "{}", err);
+		fs::write(path(&dir, 1, CheckpointFormat::Binary), [1, 0, 0, 0, 7]).unwrap();
+		let err = load::<MRXSM, WassersteinArith<MRXSM>>(&dir, 1, &config).unwrap_err();
+		assert!(matches!(err, CheckpointError::Encoding(..)), "{}", err);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,159 @@
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc,
+};
+
+use crate::{
+	config::{RngStream, SearchConfig},
+	diffusion::inverse::InvertibleDiffusion,
+	evaluation::{Evaluator, EvaluatorKind},
+	utils::{sample_buffer::SampleBuffer, sample_source::SampleSource},
+};
+
+use super::{evaluate_all, hall_of_fame::HallOfFame, Evolution};
+
+/** A function that takes the exam, with the lowest loss it had in the search. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate<F> {
+	pub func: F,
+	pub search_loss: f32,
+}
+
+/** How a candidate did in the exam with one evaluator. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+	pub loss: f32,
+	/** See `Evaluator::confidence_interval`. */
+	pub confidence_interval: (f32, f32),
+}
+
+/** The final re-evaluation of the best candidates of a search. The losses that the search reports are biased low, as
+   the candidates were selected on the same noisy samples they were scored on (the winner's curse). The exam scores
+   them again on `SearchConfig::exam_factor` batches of `n_samples` fresh samples, drawn from a random stream of their
+   own, with every evaluator that the configuration allows. All evaluators see the same batches. */
+pub struct Exam {
+	config: SearchConfig,
+	source: Arc<dyn SampleSource>,
+}
+
+impl Exam {
+	/** The search is over by then, so the exam puts the workers of all its threads to use. */
+	pub fn new(config: &SearchConfig, source: Arc<dyn SampleSource>) -> Self {
+		let n_workers = config.n_threads * config.n_workers;
+		Self { config: SearchConfig { n_workers, ..config.clone() }, source }
+	}
+
+	#[inline(always)]
+	pub fn config(&self) -> &SearchConfig { &self.config }
+
+	/** The distinct functions among the best `top` (evaluated) members of each of the `evolutions` and in
+	   `hall_of_fame`. */
+	pub fn candidates<'a, F, E>(evolutions: impl IntoIterator<Item = &'a Evolution<F, E>>,
+		hall_of_fame: &HallOfFame<E>, top: usize) -> Vec<Candidate<F>>
+		where F: InvertibleDiffusion + PartialEq + Clone + 'a, E: Evaluator<F> + 'a {
+		let mut best: Vec<&E> = Vec::new();
+		for evolution in evolutions {
+			let mut members: Vec<&E> = evolution.current_gen.members.iter().filter(|ev| ev.get_age() > 0).collect();
+			members.sort_by(|f, g| f.get_loss().total_cmp(&g.get_loss()));
+			best.extend(members.into_iter().take(top));
+		}
+		best.extend(hall_of_fame.entries().iter().map(|entry| &entry.ev));
+		let mut candidates: Vec<Candidate<F>> = Vec::with_capacity(best.len());
+		for ev in best {
+			match candidates.iter_mut().find(|candidate| &candidate.func == ev.get_func()) {
+				Some(candidate) => candidate.search_loss = candidate.search_loss.min(ev.get_loss()),
+				None => candidates.push(Candidate { func: ev.get_func().clone(), search_loss: ev.get_loss() }),
+			}
+		}
+		candidates
+	}
+
+	/** The evaluators that the exam uses, the one of the search first. Those that the configuration doesn't allow
+	   (e.g. avalanche evaluators with too many samples per round) are left out. */
+	pub fn evaluators(&self) -> Vec<EvaluatorKind> {
+		let mut kinds = vec![self.config.evaluator];
+		kinds.extend(EvaluatorKind::ALL.into_iter().filter(|&kind| kind != self.config.evaluator));
+		kinds.retain(|&kind| SearchConfig { evaluator: kind, ..self.config.clone() }.validate().is_ok());
+		kinds
+	}
+
+	/** Scores the candidates (in order) with `E`, which needn't be the evaluator of the search. `None` if `interrupt`
+	   is set before all batches are through. */
+	pub fn sit<F, E>(&self, candidates: &[Candidate<F>], interrupt: &AtomicBool) -> Option<Vec<Score>>
+		where F: InvertibleDiffusion + Clone, E: Evaluator<F> + Send {
+		let mut evs: Vec<E> = candidates.iter().map(|candidate| E::new(candidate.func.clone())).collect();
+		let mut rng = self.config.rng(0, RngStream::Exam);
+		for batch in 0..self.config.exam_factor {
+			if interrupt.load(Ordering::Acquire) {
+				return None;
+			}
+			let samples =
+				SampleBuffer::from_source(self.config.n_samples as usize, self.source.as_ref(), batch as u64, &mut rng);
+			evaluate_all(&mut evs, &samples, &self.config, None);
+		}
+		let score = |ev: &E| Score { loss: ev.get_loss(), confidence_interval: ev.confidence_interval() };
+		Some(evs.iter().map(score).collect())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		diffusion::mrxsm::MRXSM,
+		evaluation::{bic_arith::BicArith, wasserstein_arith::WassersteinArith},
+	};
+
+	#[test]
+	fn test_exam() {
+		let config = SearchConfig {
+			seed: Some(3),
+			n_threads: 2,
+			generation_size: 8,
+			elitism: 2,
+			n_samples: 1000,
+			n_rounds: 10,
+			exam_top: 3,
+			exam_factor: 4,
+			..Default::default()
+		};
+		let source = config.sample_source().unwrap();
+		let evs: Vec<Evolution<MRXSM, WassersteinArith<MRXSM>>> = (0..config.n_threads).map(|island| {
+			let mut ev = Evolution::random(config.clone(), Arc::clone(&source), island);
+			ev.next_gen();
+			ev.next_gen();
+			ev
+		}).collect();
+		let hall_of_fame =
+			HallOfFame::merge(evs.iter().map(|ev| ev.hall_of_fame()), config.hall_of_fame_size as usize);
+		let candidates = Exam::candidates(&evs, &hall_of_fame, 3);
+		assert!(candidates.len() >= 3 && candidates.len() <= 6 + hall_of_fame.len());
+		for (i, candidate) in candidates.iter().enumerate() {
+			assert!(candidates[i + 1..].iter().all(|other| other.func != candidate.func));
+		}
+		let best = evs.iter().map(|ev| ev.get_best()).min_by(|f, g| f.get_loss().total_cmp(&g.get_loss())).unwrap();
+		assert!(candidates.iter().any(|candidate| &candidate.func == best.get_func()));
+
+		let exam = Exam::new(&config, source);
+		assert_eq!(2, exam.config().n_workers);
+		let kinds = exam.evaluators();
+		assert_eq!(EvaluatorKind::WassersteinArith, kinds[0]);
+		assert_eq!(EvaluatorKind::ALL.len(), kinds.len());
+		let large_rounds = SearchConfig { n_samples: 5000, n_rounds: 1, ..config.clone() };
+		let kinds = Exam::new(&large_rounds, Arc::clone(&exam.source)).evaluators();
+		assert!(!kinds.contains(&EvaluatorKind::AvalancheSseArith), "too many samples per round");
+		let interrupt = AtomicBool::new(false);
+		let scores = exam.sit::<MRXSM, WassersteinArith<MRXSM>>(&candidates, &interrupt).unwrap();
+		assert_eq!(candidates.len(), scores.len());
+		for score in scores.iter() {
+			let (low, high) = score.confidence_interval;
+			assert!(low < score.loss && score.loss < high, "{:?}", score);
+		}
+		// Every exam sees the same samples.
+		assert_eq!(scores, exam.sit::<MRXSM, WassersteinArith<MRXSM>>(&candidates, &interrupt).unwrap());
+		assert_eq!(candidates.len(), exam.sit::<MRXSM, BicArith<MRXSM>>(&candidates, &interrupt).unwrap().len());
+		interrupt.store(true, Ordering::Release);
+		assert!(exam.sit::<MRXSM, WassersteinArith<MRXSM>>(&candidates, &interrupt).is_none());
+	}
+}
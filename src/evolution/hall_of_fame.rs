@@ -0,0 +1,128 @@
+use crate::{diffusion::DiffusionFunc, evaluation::Evaluator};
+
+/** A function in a `HallOfFame`. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry<E> {
+	/** The evaluator as of the evaluation of the function on the most samples so far, i.e. with its best estimate of
+	   the loss and the number of evaluations (its age). */
+	pub ev: E,
+	/** The generation in which the function made it into the hall of fame. */
+	pub since: u32,
+}
+
+/** The best distinct functions a search has seen, by loss, including those that have since been displaced from the
+   population (e.g. by a luckier child or after a change of samples). Bounded by `SearchConfig::hall_of_fame_size`. */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HallOfFame<E> {
+	/** By ascending loss */
+	entries: Vec<Entry<E>>,
+}
+
+impl<E> Default for HallOfFame<E> {
+	fn default() -> Self { Self { entries: Vec::new() } }
+}
+
+impl<E> HallOfFame<E> {
+	/** The entries, best first. */
+	#[inline(always)]
+	pub fn entries(&self) -> &[Entry<E>] { &self.entries }
+
+	#[inline(always)]
+	pub fn len(&self) -> usize { self.entries.len() }
+
+	#[inline(always)]
+	pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+	/** Takes in `ev`, evaluated in `generation`, if its function is among the best `size` distinct ones. If the
+	   function is in already, its entry is updated if `ev` has been evaluated at least as often. */
+	pub fn consider<F>(&mut self, ev: &E, generation: u32, size: usize)
+		where F: DiffusionFunc + PartialEq, E: Evaluator<F> + Clone {
+		if ev.get_age() == 0 || ev.get_loss().is_nan() {
+			return;
+		}
+		let known = self.entries.iter().any(|entry| entry.ev.get_func() == ev.get_func());
+		let worst = self.entries.last().filter(|_| self.entries.len() >= size).map(|entry| entry.ev.get_loss());
+		if !known && (size == 0 || worst.is_some_and(|worst| ev.get_loss() >= worst)) {
+			return;
+		}
+		self.insert(Entry { ev: ev.clone(), since: generation }, size);
+	}
+
+	/** Combines the halls of fame of several threads into the best `size` distinct functions of all of them. */
+	pub fn merge<'a, F>(halls: impl IntoIterator<Item = &'a HallOfFame<E>>, size: usize) -> Self
+		where F: DiffusionFunc + PartialEq, E: Evaluator<F> + Clone + 'a {
+		let mut merged = Self::default();
+		for entry in halls.into_iter().flat_map(|hall| hall.entries.iter()) {
+			merged.insert(entry.clone(), size);
+		}
+		merged
+	}
+
+	/** Adds `entry` or, if its function is in already, keeps whichever of both has been evaluated more often (the
+	   newer one on a tie), then drops all but the best `size`. */
+	fn insert<F>(&mut self, entry: Entry<E>, size: usize) where F: DiffusionFunc + PartialEq, E: Evaluator<F> {
+		match self.entries.iter_mut().find(|known| known.ev.get_func() == entry.ev.get_func()) {
+			Some(known) => {
+				if entry.ev.get_age() >= known.ev.get_age() {
+					known.ev = entry.ev;
+					known.since = known.since.min(entry.since);
+				}
+			}
+			None => self.entries.push(entry),
+		}
+		self.entries.sort_by(|e1, e2| e1.ev.get_loss().total_cmp(&e2.ev.get_loss()));
+		self.entries.truncate(size);
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		config::SearchConfig, diffusion::mrxsm::MRXSM, evaluation::wasserstein_arith::WassersteinArith,
+		utils::{sample_buffer::SampleBuffer, xoshiro::Xoshiro256PlusPlus},
+	};
+	use rand::SeedableRng;
+
+	#[test]
+	fn test_hall_of_fame() {
+		let config = SearchConfig { n_samples: 1000, n_rounds: 10, ..Default::default() };
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let samples = SampleBuffer::random(config.n_samples as usize, &mut rng);
+		let mut evs: Vec<WassersteinArith<MRXSM>> =
+			(0..6).map(|_| WassersteinArith::new(MRXSM::random(&config, &mut rng))).collect();
+		let mut hall = HallOfFame::default();
+		hall.consider(&evs[0], 0, 3);
+		assert!(hall.is_empty(), "not evaluated yet");
+		for ev in evs.iter_mut() {
+			ev.update(&samples, &config);
+		}
+		for (generation, ev) in evs.iter().enumerate() {
+			hall.consider(ev, generation as u32, 3);
+		}
+		evs.sort_by(|f, g| f.get_loss().total_cmp(&g.get_loss()));
+		let best: Vec<&WassersteinArith<MRXSM>> = hall.entries().iter().map(|entry| &entry.ev).collect();
+		assert_eq!(evs.iter().take(3).collect::<Vec<_>>(), best);
+
+		// Evaluated again, the entry of the best function is updated but keeps its generation.
+		let mut again = evs[0].clone();
+		again.update(&samples, &config);
+		let since = hall.entries()[0].since;
+		hall.consider(&again, 10, 3);
+		assert_eq!(3, hall.len());
+		assert_eq!(1, hall.entries().iter().filter(|entry| entry.ev.get_func() == again.get_func()).count());
+		let entry = hall.entries().iter().find(|entry| entry.ev.get_func() == again.get_func()).unwrap();
+		assert_eq!((2, since), (entry.ev.get_age(), entry.since));
+
+		let mut other = HallOfFame::default();
+		other.consider(&evs[3], 4, 3);
+		other.consider(&evs[0], 5, 3);
+		let merged = HallOfFame::merge([&hall, &other], 4);
+		assert_eq!(4, merged.len());
+		assert_eq!(&evs[3], &merged.entries()[3].ev);
+		let entry = merged.entries().iter().find(|entry| entry.ev.get_func() == again.get_func()).unwrap();
+		assert_eq!(2, entry.ev.get_age());
+	}
+}
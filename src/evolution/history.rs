@@ -0,0 +1,273 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	fs::{self, File},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	str::FromStr,
+	time::Duration,
+};
+
+use rand::SeedableRng;
+
+use crate::{
+	diffusion::DiffusionFunc,
+	evaluation::Evaluator,
+	utils::{word::Word, xoshiro::Xoshiro256PlusPlus},
+};
+
+/** Whether and how each thread logs statistics of every generation (see `History`). */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryFormat {
+	#[default]
+	Off,
+	/** One JSON object per generation, in `<thread>.history.jsonl`. */
+	JsonLines,
+	/** One row per generation after a header, in `<thread>.history.csv`. */
+	Csv,
+}
+
+impl HistoryFormat {
+	pub const ALL: [HistoryFormat; 3] = [HistoryFormat::Off, HistoryFormat::JsonLines, HistoryFormat::Csv];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			HistoryFormat::Off => "off",
+			HistoryFormat::JsonLines => "json_lines",
+			HistoryFormat::Csv => "csv",
+		}
+	}
+}
+
+impl Display for HistoryFormat {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for HistoryFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		HistoryFormat::ALL.into_iter().find(|format| format.name() == s)
+			.ok_or_else(|| format!("unknown history format {}", s))
+	}
+}
+
+
+/** The number of inputs `diversity` compares the outputs of functions on. */
+const DIVERSITY_INPUTS: usize = 16;
+
+/** Statistics of an evaluated generation. Losses are those of the evaluators (without any bijectivity penalty), so
+   those of children that racing gave up on are lower bounds. */
+#[derive(Debug, Clone, PartialEq, Serialize, Default)]
+pub struct GenerationStats {
+	/** The number of the generation, counting from 0. */
+	pub generation: u32,
+	pub best_loss: f32,
+	pub median_loss: f32,
+	pub worst_loss: f32,
+	/** The best function, as printed by the search. */
+	pub best: String,
+	/** The minimum, quartiles and maximum of the ages of the members. */
+	pub ages: [u32; 5],
+	/** See `diversity`. */
+	pub diversity: f32,
+}
+
+impl GenerationStats {
+	/** The statistics of the given (evaluated) members. */
+	pub fn of<F: DiffusionFunc, E: Evaluator<F>>(generation: u32, members: &[E]) -> Self {
+		if members.is_empty() {
+			return Self { generation, ..Default::default() };
+		}
+		let mut losses: Vec<f32> = members.iter().map(|ev| ev.get_loss()).collect();
+		losses.sort_unstable_by(f32::total_cmp);
+		let best = members.iter().min_by(|f, g| f.get_loss().total_cmp(&g.get_loss())).unwrap();
+		let mut ages: Vec<u32> = members.iter().map(|ev| ev.get_age()).collect();
+		ages.sort_unstable();
+		let quantile = |q: usize| ages[(ages.len() - 1) * q / 4];
+		Self {
+			generation,
+			best_loss: losses[0],
+			median_loss: losses[losses.len() / 2],
+			worst_loss: losses[losses.len() - 1],
+			best: best.get_func().to_string(),
+			ages: [0, 1, 2, 3, 4].map(quantile),
+			diversity: diversity(members.iter().map(|ev| ev.get_func())),
+		}
+	}
+}
+
+/** How differently the given functions behave: the mean Hamming distance between the outputs of two (different)
+   members on the same input, relative to the word width, over a fixed set of inputs. 0 if all functions are the same,
+   about 0.5 if they're unrelated. Unlike comparing parameters, this works for all families and doesn't count
+   functions as different that only differ in ineffective parameters. */
+pub fn diversity<'a, F: DiffusionFunc + 'a>(funcs: impl Iterator<Item = &'a F>) -> f32 {
+	let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+	let inputs: Vec<F::Word> = (0..DIVERSITY_INPUTS).map(|_| F::Word::random(&mut rng)).collect();
+	let bits = F::Word::BITS as usize;
+	// The number of functions for which each bit of the output on each input is set
+	let mut counts = vec![0_u64; DIVERSITY_INPUTS * bits];
+	let mut n = 0_u64;
+	for f in funcs {
+		for (&x, counts) in inputs.iter().zip(counts.chunks_exact_mut(bits)) {
+			let y = f.diffuse(x);
+			for (bit, count) in counts.iter_mut().enumerate() {
+				*count += ((y >> bit as u32) & F::Word::ONE != F::Word::ZERO) as u64;
+			}
+		}
+		n += 1;
+	}
+	if n < 2 {
+		return 0.0;
+	}
+	// Each bit differs between c * (n - c) of the n * (n - 1) / 2 pairs of functions.
+	let n_differing: u64 = counts.iter().map(|&c| c * (n - c)).sum();
+	(n_differing as f64 / (n * (n - 1) / 2) as f64 / counts.len() as f64) as f32
+}
+
+
+/** The statistics of every generation of a thread, written to a file in its state directory as they come in, so that
+   the progress of searches can be plotted and compared. */
+pub struct History {
+	file: File,
+	format: HistoryFormat,
+}
+
+/** A line of the JSON Lines format. */
+#[derive(Serialize)]
+struct Record<'a> {
+	#[serde(flatten)]
+	stats: &'a GenerationStats,
+	millis: f64,
+}
+
+const CSV_HEADER: &str =
+	"generation,best_loss,median_loss,worst_loss,min_age,q1_age,median_age,q3_age,max_age,diversity,millis,best";
+
+impl History {
+	/** The path of the history of thread `thread_num` in the given format. */
+	pub fn path(state_dir: &Path, thread_num: u32, format: HistoryFormat) -> PathBuf {
+		let extension = if format == HistoryFormat::Csv { "csv" } else { "jsonl" };
+		state_dir.join(format!("{}.history.{}", thread_num, extension))
+	}
+
+	/** Opens the history of thread `thread_num`, unless `format` is `Off`, for a thread that continues at generation
+	   `first_gen` (0 for a new one). The entries of earlier generations are kept. Those of later ones were logged
+	   after the checkpoint that the thread continues from, so they're dropped as the thread runs them again. */
+	pub fn open(state_dir: &Path, thread_num: u32, format: HistoryFormat, first_gen: u32) -> io::Result<Option<Self>> {
+		if format == HistoryFormat::Off {
+			return Ok(None);
+		}
+		fs::create_dir_all(state_dir)?;
+		let path = Self::path(state_dir, thread_num, format);
+		let previous = match fs::read_to_string(&path) {
+			Ok(previous) if first_gen > 0 => previous,
+			Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+			_ => String::new(),
+		};
+		let mut file = File::create(path)?;
+		if format == HistoryFormat::Csv {
+			writeln!(file, "{}", CSV_HEADER)?;
+		}
+		for line in previous.lines() {
+			if Self::generation(format, line).is_some_and(|generation| generation < first_gen) {
+				writeln!(file, "{}", line)?;
+			}
+		}
+		Ok(Some(Self { file, format }))
+	}
+
+	/** The generation of an entry of the history, `None` for the CSV header (or anything else that isn't an entry). */
+	fn generation(format: HistoryFormat, line: &str) -> Option<u32> {
+		match format {
+			HistoryFormat::Off => None,
+			HistoryFormat::JsonLines => serde_json::from_str::<serde_json::Value>(line).ok()?["generation"]
+				.as_u64().and_then(|generation| generation.try_into().ok()),
+			HistoryFormat::Csv => line.split(',').next()?.parse().ok(),
+		}
+	}
+
+	/** Appends a generation that took `elapsed`. */
+	pub fn write(&mut self, stats: &GenerationStats, elapsed: Duration) -> io::Result<()> {
+		let millis = elapsed.as_secs_f64() * 1000.0;
+		let line = match self.format {
+			HistoryFormat::Off => return Ok(()),
+			HistoryFormat::JsonLines => serde_json::to_string(&Record { stats, millis })?,
+			HistoryFormat::Csv => {
+				let [min, q1, median, q3, max] = stats.ages;
+				format!("{},{},{},{},{},{},{},{},{},{},{:.3},\"{}\"", stats.generation, stats.best_loss,
+					stats.median_loss, stats.worst_loss, min, q1, median, q3, max, stats.diversity, millis, stats.best)
+			}
+		};
+		writeln!(self.file, "{}", line)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		config::SearchConfig, diffusion::mrxsm::MRXSM, evaluation::wasserstein_arith::WassersteinArith,
+		evolution::Evolution,
+	};
+
+	#[test]
+	fn test_diversity() {
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(1);
+		let f = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		assert_eq!(0.0, diversity([f.clone(), f.clone(), f].iter()));
+		let random: Vec<MRXSM> = (0..50).map(|_| MRXSM::random(&SearchConfig::default(), &mut rng)).collect();
+		let d = diversity(random.iter());
+		assert!((0.45..0.55).contains(&d), "{}", d);
+	}
+
+	#[test]
+	fn test_history() {
+		let config = SearchConfig {
+			seed: Some(1),
+			generation_size: 8,
+			elitism: 2,
+			n_samples: 1000,
+			n_rounds: 10,
+			..Default::default()
+		};
+		let source = config.sample_source().unwrap();
+		let mut ev = Evolution::<MRXSM, WassersteinArith<MRXSM>>::random(config, source, 0);
+		ev.next_gen();
+		ev.next_gen();
+		let stats = ev.stats();
+		assert_eq!(1, stats.generation);
+		assert!(stats.best_loss <= stats.median_loss && stats.median_loss <= stats.worst_loss);
+		assert_eq!(ev.get_best().get_func().to_string(), stats.best);
+		assert!(stats.ages.windows(2).all(|w| w[0] <= w[1]));
+		assert_eq!(ev.get_longest_lived().get_age(), stats.ages[4]);
+
+		let dir = std::env::temp_dir().join(format!("diffusion-history-{}", std::process::id()));
+		for format in [HistoryFormat::JsonLines, HistoryFormat::Csv] {
+			for first_gen in [0, 2] {
+				let mut history = History::open(&dir, 3, format, first_gen).unwrap().unwrap();
+				history.write(stats, Duration::from_millis(5)).unwrap();
+			}
+			let s = fs::read_to_string(History::path(&dir, 3, format)).unwrap();
+			let lines: Vec<&str> = s.lines().collect();
+			if format == HistoryFormat::Csv {
+				assert_eq!(vec![CSV_HEADER, lines[1], lines[1]], lines);
+				assert!(lines[1].starts_with("1,") && lines[1].ends_with(&format!(",5.000,\"{}\"", stats.best)));
+			} else {
+				assert_eq!(2, lines.len());
+				let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+				assert_eq!(1, record["generation"]);
+				assert_eq!(5.0, record["millis"]);
+				assert_eq!(stats.best, record["best"]);
+			}
+			// Continuing from an earlier checkpoint drops the generations that are run again.
+			History::open(&dir, 3, format, 1).unwrap().unwrap();
+			let s = fs::read_to_string(History::path(&dir, 3, format)).unwrap();
+			assert_eq!(usize::from(format == HistoryFormat::Csv), s.lines().count());
+		}
+		assert!(History::open(&dir, 3, HistoryFormat::Off, 0).unwrap().is_none());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
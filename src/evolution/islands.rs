@@ -0,0 +1,257 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc::{self, Receiver, RecvTimeoutError, Sender},
+	},
+	time::Duration,
+};
+
+use rand::{Rng, SeedableRng};
+
+use crate::{
+	diffusion::inverse::InvertibleDiffusion,
+	evaluation::Evaluator,
+	utils::xoshiro::{splitmix64, Xoshiro256PlusPlus},
+};
+
+use super::Evolution;
+
+/** Which islands send their migrants to which (see `Island`). */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+	/** Island `i` sends to island `i + 1` and the last one to the first. */
+	#[default]
+	Ring,
+	/** Every island sends to all others. */
+	FullyConnected,
+	/** Every island sends to one other island, drawn anew for each migration. */
+	Random,
+}
+
+impl Topology {
+	pub const ALL: [Topology; 3] = [Topology::Ring, Topology::FullyConnected, Topology::Random];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Topology::Ring => "ring",
+			Topology::FullyConnected => "fully_connected",
+			Topology::Random => "random",
+		}
+	}
+
+	/** The islands that `island` (of `n_islands`) sends its migrants to after `generation` in a search with the given
+	   (master) seed. */
+	pub fn targets(&self, island: u32, n_islands: u32, generation: u32, seed: u64) -> Vec<u32> {
+		if n_islands < 2 {
+			return Vec::new();
+		}
+		match self {
+			Topology::Ring => vec![(island + 1) % n_islands],
+			Topology::FullyConnected => (0..n_islands).filter(|&i| i != island).collect(),
+			Topology::Random => {
+				// Seeded with what all islands know, so that they agree on the graph.
+				let mut state = seed;
+				let mut rng = Xoshiro256PlusPlus::seed_from_u64(splitmix64(&mut state) ^ generation as u64);
+				let offsets: Vec<u32> = (0..n_islands).map(|_| rng.gen_range(1..n_islands)).collect();
+				vec![(island + offsets[island as usize]) % n_islands]
+			}
+		}
+	}
+
+	/** The islands that send their migrants to `island` (of `n_islands`) after `generation`. */
+	pub fn sources(&self, island: u32, n_islands: u32, generation: u32, seed: u64) -> Vec<u32> {
+		(0..n_islands).filter(|&i| self.targets(i, n_islands, generation, seed).contains(&island)).collect()
+	}
+}
+
+impl Display for Topology {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for Topology {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		Topology::ALL.into_iter().find(|topology| topology.name() == s)
+			.ok_or_else(|| format!("unknown topology {}", s))
+	}
+}
+
+
+/** The functions an island sends after a generation. */
+struct Migrants<F> {
+	generation: u32,
+	funcs: Vec<F>,
+}
+
+/** One of the threads of a search, each of which runs its own `Evolution`, as seen by the others. Every
+   `SearchConfig::migration_interval` generations, each island sends its best `migration_size` functions to the
+   islands that `topology` connects it to, then waits for those sent to it and passes them on to
+   `Evolution::immigrate`. Islands only wait for others that are still running and stop waiting on an interrupt. */
+pub struct Island<F> {
+	index: u32,
+	/** Channels to all islands, by index (`None` for this one). */
+	outboxes: Vec<Option<Sender<Migrants<F>>>>,
+	/** Channels from all islands, by index (`None` for this one and those that have finished). */
+	inboxes: Vec<Option<Receiver<Migrants<F>>>>,
+	/** Migrants that arrived for a later migration than the one being waited for, by the island that sent them. */
+	early: Vec<Option<Migrants<F>>>,
+}
+
+impl<F> Island<F> {
+	/** Connects `n_islands` islands with each other. */
+	pub fn archipelago(n_islands: u32) -> Vec<Island<F>> {
+		let n = n_islands as usize;
+		let mut islands: Vec<Island<F>> = (0..n_islands).map(|index| Island {
+			index,
+			outboxes: (0..n).map(|_| None).collect(),
+			inboxes: (0..n).map(|_| None).collect(),
+			early: (0..n).map(|_| None).collect(),
+		}).collect();
+		for from in 0..n {
+			for to in (0..n).filter(|&to| to != from) {
+				let (sender, receiver) = mpsc::channel();
+				islands[from].outboxes[to] = Some(sender);
+				islands[to].inboxes[from] = Some(receiver);
+			}
+		}
+		islands
+	}
+
+	#[inline(always)]
+	pub fn index(&self) -> u32 { self.index }
+}
+
+impl<F: InvertibleDiffusion + Clone> Island<F> {
+	/** Exchanges migrants with the other islands if `evolution` just completed a generation after which that's due. */
+	pub fn migrate<E: Evaluator<F> + Send>(&mut self, evolution: &mut Evolution<F, E>, interrupt: &AtomicBool) {
+		let config = evolution.config();
+		let (interval, topology, seed) = (config.migration_interval, config.topology, config.seed.unwrap_or(0));
+		let generation = evolution.generation_counter;
+		if interval == 0 || !generation.is_multiple_of(interval) {
+			return;
+		}
+		let n_islands = self.outboxes.len() as u32;
+		let emigrants = evolution.emigrants();
+		for target in topology.targets(self.index, n_islands, generation, seed) {
+			if let Some(outbox) = &self.outboxes[target as usize] {
+				// Fails if the target has finished, which is fine.
+				let _ = outbox.send(Migrants { generation, funcs: emigrants.clone() });
+			}
+		}
+		let mut immigrants = Vec::new();
+		for source in topology.sources(self.index, n_islands, generation, seed) {
+			if let Some(migrants) = self.receive(source as usize, generation, interrupt) {
+				immigrants.extend(migrants.funcs);
+			}
+		}
+		evolution.immigrate(immigrants);
+	}
+
+	/** Waits for the migrants that island `source` sends after `generation`. Those of earlier generations (which can
+	   turn up after resuming from states of different generations) are dropped, those of later ones kept. */
+	fn receive(&mut self, source: usize, generation: u32, interrupt: &AtomicBool) -> Option<Migrants<F>> {
+		if let Some(migrants) = self.early[source].take() {
+			if migrants.generation >= generation {
+				return self.sort_in(source, migrants, generation);
+			}
+		}
+		loop {
+			match self.inboxes[source].as_ref()?.recv_timeout(Duration::from_millis(100)) {
+				Ok(migrants) if migrants.generation >= generation => return self.sort_in(source, migrants, generation),
+				Ok(_) => {}
+				Err(RecvTimeoutError::Timeout) => {
+					if interrupt.load(Ordering::Acquire) {
+						return None;
+					}
+				}
+				Err(RecvTimeoutError::Disconnected) => {
+					self.inboxes[source] = None;
+					return None;
+				}
+			}
+		}
+	}
+
+	/** Returns `migrants` if they're those of `generation` and keeps them for later otherwise. */
+	fn sort_in(&mut self, source: usize, migrants: Migrants<F>, generation: u32) -> Option<Migrants<F>> {
+		if migrants.generation == generation {
+			Some(migrants)
+		} else {
+			self.early[source] = Some(migrants);
+			None
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		config::SearchConfig, diffusion::mrxsm::MRXSM, evaluation::wasserstein_arith::WassersteinArith,
+	};
+	use std::{sync::Arc, thread};
+
+	#[test]
+	fn test_topology() {
+		assert_eq!(vec![0], Topology::Ring.targets(3, 4, 0, 0));
+		assert_eq!(vec![2], Topology::Ring.sources(3, 4, 0, 0));
+		assert_eq!(vec![0, 1, 3], Topology::FullyConnected.targets(2, 4, 0, 0));
+		assert!(Topology::Ring.targets(0, 1, 0, 0).is_empty());
+		for generation in 0..100 {
+			let targets: Vec<Vec<u32>> = (0..5).map(|i| Topology::Random.targets(i, 5, generation, 7)).collect();
+			assert!(targets.iter().enumerate().all(|(i, t)| t.len() == 1 && t[0] != i as u32));
+			let n_sources: usize = (0..5).map(|i| Topology::Random.sources(i, 5, generation, 7).len()).sum();
+			assert_eq!(5, n_sources);
+		}
+		assert_eq!(Ok(Topology::FullyConnected), "fully_connected".parse());
+	}
+
+	#[test]
+	fn test_migrate() {
+		let config = SearchConfig {
+			n_threads: 3,
+			generation_size: 8,
+			elitism: 2,
+			n_samples: 1000,
+			n_rounds: 10,
+			migration_interval: 2,
+			migration_size: 1,
+			topology: Topology::FullyConnected,
+			..Default::default()
+		};
+		let interrupt = Arc::new(AtomicBool::new(false));
+		let handles: Vec<_> = Island::archipelago(config.n_threads).into_iter().map(|mut island| {
+			let (config, interrupt) = (config.clone(), Arc::clone(&interrupt));
+			thread::spawn(move || {
+				let source = config.sample_source().unwrap();
+				let index = island.index();
+				let mut ev = Evolution::<MRXSM, WassersteinArith<MRXSM>>::random(config, source, index);
+				let mut n_received = Vec::new();
+				for _ in 0..4 {
+					ev.next_gen();
+					island.migrate(&mut ev, &interrupt);
+					n_received.push(ev.immigrants.len());
+				}
+				// The last island finishes early, so the others don't get anything from it after that.
+				if island.index() == 2 {
+					return n_received;
+				}
+				ev.next_gen();
+				ev.next_gen();
+				island.migrate(&mut ev, &interrupt);
+				n_received.push(ev.immigrants.len());
+				n_received
+			})
+		}).collect();
+		let n_received: Vec<Vec<usize>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+		assert_eq!(vec![0, 2, 0, 2, 1], n_received[0]);
+		assert_eq!(vec![0, 2, 0, 2, 1], n_received[1]);
+		assert_eq!(vec![0, 2, 0, 2], n_received[2]);
+	}
+}
@@ -1,54 +1,298 @@
-use std::mem::MaybeUninit;
+use std::{
+	fmt::{self, Display, Formatter},
+	mem,
+	str::FromStr,
+	sync::{Arc, Mutex},
+	thread,
+};
 
 use crate::{
-	diffusion::DiffusionFunc,
+	config::{RngStream, SearchConfig},
+	diffusion::inverse::InvertibleDiffusion,
 	evaluation::Evaluator,
-	globals::{ELITISM, GENERATION_SIZE, N_SAMPLES, SAMPLE_LIFETIME, TOURNAMENT_SIZE},
+	utils::{
+		sample_buffer::SampleBuffer,
+		sample_source::{SampleDistribution, SampleSource},
+		xoshiro::Xoshiro256PlusPlus,
+	},
 };
 use rand::Rng;
 
-use self::generation::Generation;
+use self::{
+	generation::Generation,
+	hall_of_fame::HallOfFame,
+	history::GenerationStats,
+	selection::{Population, Selection},
+};
 
+pub mod checkpoint;
+pub mod exam;
+pub mod hall_of_fame;
+pub mod history;
+pub mod islands;
+pub mod selection;
 mod generation;
 
+/** The number of children that are raced against the same cutoff (see `Evolution::eval_current_gen`). Fixed, so that
+   the outcome doesn't depend on `SearchConfig::n_workers`, which it also caps while racing. */
+const RACING_BATCH_SIZE: usize = 64;
+
+
+/** What the search does with candidates that aren't bijections (see `InvertibleDiffusion::check_bijective`). */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BijectivityPolicy {
+	/** Non-bijective candidates compete like any other. */
+	#[default]
+	Ignore,
+	/** Non-bijective candidates are never part of a generation. Random ones are drawn again and children are replaced
+	   by random (bijective) functions. */
+	Reject,
+	/** `SearchConfig::bijectivity_penalty` is added to the loss of non-bijective candidates during selection. */
+	Penalise,
+}
+
+impl BijectivityPolicy {
+	pub const ALL: [BijectivityPolicy; 3] =
+		[BijectivityPolicy::Ignore, BijectivityPolicy::Reject, BijectivityPolicy::Penalise];
 
-const SAMPLE_LIFETIME_MASK: u32 = SAMPLE_LIFETIME - 1;
+	pub fn name(&self) -> &'static str {
+		match self {
+			BijectivityPolicy::Ignore => "ignore",
+			BijectivityPolicy::Reject => "reject",
+			BijectivityPolicy::Penalise => "penalise",
+		}
+	}
+}
+
+impl Display for BijectivityPolicy {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for BijectivityPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		BijectivityPolicy::ALL.into_iter().find(|policy| policy.name() == s)
+			.ok_or_else(|| format!("unknown bijectivity policy {}", s))
+	}
+}
+
+
+/** Whether the search races children (see `Evaluator::update_racing`) and against which cutoff. Either way, children
+   that are evaluated on all samples end up with the same loss as without racing. */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Racing {
+	/** Every child is evaluated on all samples. */
+	#[default]
+	Off,
+	/** A child is given up on once it's bound to be worse than the worst elite, i.e. not to survive the generation. */
+	Elite,
+	/** A child is given up on once it's bound to be worse than half of the members evaluated before it and than the
+	   worst elite. It then only wins a tournament if all `tournament_size - 1` other competitors are worse still, i.e.
+	   rarely becomes a parent. */
+	Tournament,
+}
+
+impl Racing {
+	pub const ALL: [Racing; 3] = [Racing::Off, Racing::Elite, Racing::Tournament];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Racing::Off => "off",
+			Racing::Elite => "elite",
+			Racing::Tournament => "tournament",
+		}
+	}
+}
+
+impl Display for Racing {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for Racing {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		Racing::ALL.into_iter().find(|racing| racing.name() == s).ok_or_else(|| format!("unknown racing {}", s))
+	}
+}
 
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Evolution<F, E> {
-	#[serde(skip, default = "random_samples")]
-	samples: [u64; N_SAMPLES as usize],
+	#[serde(skip)]
+	config: SearchConfig,
+	/** Where fresh samples are drawn from (see `SearchConfig::sample_source`). */
+	#[serde(skip, default = "default_source")]
+	source: Arc<dyn SampleSource>,
+	#[serde(skip)]
+	samples: SampleBuffer,
 	pub generation_counter: u32,
 	pub current_gen: Generation<F, E>,
+	/** Functions sent by other islands (see `islands::Island`) that replace the worst members after the next
+	   evaluation. */
+	#[serde(default = "Vec::new")]
+	immigrants: Vec<F>,
+	/** The island (i.e. thread) this runs on, which its random numbers and samples depend on. */
+	#[serde(default)]
+	island: u32,
+	/** The master seed (see `SearchConfig::seed`) that the generators below were derived from. */
+	#[serde(default)]
+	seed: u64,
+	/** Random numbers for selection, crossover and mutation. */
+	#[serde(default)]
+	rng: Xoshiro256PlusPlus,
+	/** Random numbers for the samples. */
+	#[serde(default)]
+	sample_rng: Xoshiro256PlusPlus,
+	/** How often samples have been drawn so far. */
+	#[serde(default)]
+	n_draws: u64,
+	/** The number of the draw of the current samples (see `SampleSource::fill`) and the state of `sample_rng` before
+	   it, so that they can be drawn again after loading a checkpoint. */
+	#[serde(default)]
+	samples_draw: u64,
+	#[serde(default)]
+	samples_rng: Xoshiro256PlusPlus,
+	/** Those of the generation that was evaluated last. */
+	#[serde(skip)]
+	stats: GenerationStats,
+	/** The best functions this search has seen. */
+	#[serde(default = "HallOfFame::default")]
+	hall_of_fame: HallOfFame<E>,
 }
 
-fn random_samples() -> [u64; N_SAMPLES as usize] {
-	let mut rng = rand::thread_rng();
-	const LEN: usize = N_SAMPLES as usize;
-	let mut samples = [0_u64; LEN];
-	for sample in samples.iter_mut() {
-		*sample = rng.gen();
+fn default_source() -> Arc<dyn SampleSource> { Arc::new(SampleDistribution::default()) }
+
+
+/** What `Evolution::selection_loss` adds to the loss of `ev`. */
+#[inline(always)]
+fn penalty<F: InvertibleDiffusion, E: Evaluator<F>>(config: &SearchConfig, ev: &E) -> f32 {
+	if config.bijectivity == BijectivityPolicy::Penalise && !ev.get_func().is_bijective() {
+		config.bijectivity_penalty
+	} else {
+		0.0
+	}
+}
+
+/** Evaluates all of `evs` on `samples` (racing them against `cutoff` if given) on up to `config.n_workers` threads.
+   Every evaluation only depends on the member, the samples and the cutoff, so the outcome doesn't depend on how the
+   members are spread over the workers. */
+fn evaluate_all<F: InvertibleDiffusion, E: Evaluator<F> + Send>(evs: &mut [E], samples: &[u64], config: &SearchConfig,
+	cutoff: Option<f32>) {
+	let evaluate = |ev: &mut E| match cutoff {
+		Some(cutoff) => {
+			ev.update_racing(samples, config, cutoff - penalty(config, ev));
+		}
+		None => {
+			ev.update(samples, config);
+		}
+	};
+	let n_workers = (config.n_workers as usize).min(evs.len());
+	if n_workers <= 1 {
+		evs.iter_mut().for_each(evaluate);
+		return;
 	}
-	samples
+	// Members are handed out one at a time, as (especially when racing) they take very different amounts of time. The
+	// lock is only held to take the next one.
+	let members = Mutex::new(evs.iter_mut());
+	thread::scope(|scope| {
+		for _ in 0..n_workers {
+			scope.spawn(|| loop {
+				let Some(ev) = members.lock().unwrap().next() else { break };
+				evaluate(ev);
+			});
+		}
+	});
 }
 
+/** Returns a random evaluator whose function is admissible under `config.bijectivity`. */
+fn random_admissible<F: InvertibleDiffusion, E: Evaluator<F>>(config: &SearchConfig, rng: &mut impl Rng) -> E {
+	loop {
+		let ev = E::random(config, rng);
+		if config.bijectivity != BijectivityPolicy::Reject || ev.get_func().is_bijective() {
+			return ev;
+		}
+	}
+}
+
+
+impl<F: InvertibleDiffusion, E: Evaluator<F> + Send> Evolution<F, E> {
+	/** Starts from the given generation and samples (as the first draw of island 0). */
+	pub fn new(config: SearchConfig, source: Arc<dyn SampleSource>, samples: SampleBuffer,
+		starting_gen: Generation<F, E>) -> Self {
+		debug_assert!(samples.len() == config.n_samples as usize);
+		debug_assert!(starting_gen.len() == config.generation_size as usize);
+		let rng = config.rng(0, RngStream::Evolution);
+		Self { samples, n_draws: 1, ..Self::init(config, source, 0, starting_gen, rng) }
+	}
+
+	/** Starts from a random generation on the given island. It and everything else that's random only depends on
+	   `config.seed` and the island. */
+	pub fn random(config: SearchConfig, source: Arc<dyn SampleSource>, island: u32) -> Self {
+		let mut rng = config.rng(island, RngStream::Evolution);
+		let current_gen = Generation::random(&config, &mut rng);
+		let mut evolution = Self::init(config, source, island, current_gen, rng);
+		evolution.new_samples();
+		evolution
+	}
 
-impl<F: DiffusionFunc, E: Evaluator<F>> Evolution<F, E> {
-	pub fn new(samples: [u64; N_SAMPLES as usize], starting_gen: Generation<F, E>) -> Self {
-		Self { samples, generation_counter: 0, current_gen: starting_gen }
+	/** Everything but the samples, which are yet to be drawn. */
+	fn init(config: SearchConfig, source: Arc<dyn SampleSource>, island: u32, current_gen: Generation<F, E>,
+		rng: Xoshiro256PlusPlus) -> Self {
+		let (seed, sample_rng) = (config.seed.unwrap_or(0), config.rng(island, RngStream::Samples));
+		Self {
+			config,
+			source,
+			samples: SampleBuffer::default(),
+			generation_counter: 0,
+			current_gen,
+			immigrants: Vec::new(),
+			island,
+			seed,
+			rng,
+			sample_rng: sample_rng.clone(),
+			n_draws: 0,
+			samples_draw: 0,
+			samples_rng: sample_rng,
+			stats: GenerationStats::default(),
+			hall_of_fame: HallOfFame::default(),
+		}
 	}
 
-	pub fn random(rng: &mut impl Rng) -> Self {
-		let samples = random_samples();
-		let current_gen = Generation::random(rng);
-		Self { samples, generation_counter: 0, current_gen }
+	/** Neither the configuration nor the samples (or their source) are part of a stored state. This attaches the given
+	   configuration (keeping the stored seed) and source to a deserialised state and draws its samples again. Only
+	   `Checkpoint::resume` calls this, after checking that the configuration fits. */
+	fn with_config(mut self, mut config: SearchConfig, source: Arc<dyn SampleSource>) -> Self {
+		debug_assert_eq!(config.generation_size as usize, self.current_gen.len());
+		config.seed = Some(self.seed);
+		self.source = source;
+		self.config = config;
+		if self.n_draws == 0 {
+			self.new_samples();
+		} else {
+			self.draw_samples();
+		}
+		self
 	}
 
+	pub fn config(&self) -> &SearchConfig { &self.config }
+
+	/** The statistics of the latest generation, as evaluated by `next_gen` (before it was replaced by the next). */
+	pub fn stats(&self) -> &GenerationStats { &self.stats }
+
+	pub fn hall_of_fame(&self) -> &HallOfFame<E> { &self.hall_of_fame }
+
+	/** The master seed that this search started from. */
+	pub fn seed(&self) -> u64 { self.seed }
+
 	pub fn get_best(&self) -> &E {
-		const LAST: usize = (GENERATION_SIZE - 1) as usize;
 		// After next_gen returns, the best function will be at the end of the array.
-		&self.current_gen.members[LAST]
+		self.current_gen.members.last().unwrap()
 	}
 
 	pub fn get_longest_lived(&self) -> &E {
@@ -63,68 +307,179 @@ impl<F: DiffusionFunc, E: Evaluator<F>> Evolution<F, E> {
 		&self.current_gen.members[idx]
 	}
 
-	pub fn next_gen(&mut self, rng: &mut impl Rng) {
+	/** The best `migration_size` functions of the latest generation, to be sent to other islands. */
+	pub fn emigrants(&self) -> Vec<F> where F: Clone {
+		let members = self.current_gen.members.iter().rev();
+		members.take(self.config.migration_size as usize).map(|ev| ev.get_func().clone()).collect()
+	}
+
+	/** Queues functions sent by other islands. They replace the worst members once the next generation has been
+	   evaluated, but at most as many as there are children. */
+	pub fn immigrate(&mut self, funcs: Vec<F>) { self.immigrants.extend(funcs); }
+
+	pub fn next_gen(&mut self) where F: PartialEq, E: Clone {
 		self.eval_current_gen();
-		const N_CHILDREN: usize = (GENERATION_SIZE - ELITISM) as usize;
-		let mut next_gen: [MaybeUninit<E>; N_CHILDREN] = unsafe { MaybeUninit::uninit().assume_init() };
+		self.integrate_immigrants();
+		self.stats = GenerationStats::of(self.generation_counter, &self.current_gen.members);
+		self.enter_hall_of_fame();
+		let n_children = self.config.n_children() as usize;
+		let mut next_gen = Vec::with_capacity(n_children);
 
 		// Generate children
-		for i in (0..N_CHILDREN).step_by(2) {
-			let parent1 = self.tournament(rng).get_func();
-			let parent2 = self.tournament(rng).get_func();
-			let [child1, child2] = parent1.crossover(parent2, rng);
-			next_gen[i] = MaybeUninit::new(E::new(child1));
-			next_gen[i+1] = MaybeUninit::new(E::new(child2));
+		let population = self.population();
+		let select = |rng: &mut Xoshiro256PlusPlus| {
+			self.current_gen.members[self.config.selection.select(&population, &self.config, rng)].get_func()
+		};
+		let mut rng = mem::take(&mut self.rng);
+		for _ in (0..n_children).step_by(2) {
+			let parent1 = select(&mut rng);
+			let parent2 = select(&mut rng);
+			let [child1, child2] = parent1.crossover(parent2, &self.config, &mut rng);
+			for child in [child1, child2] {
+				if self.config.bijectivity == BijectivityPolicy::Reject && !child.is_bijective() {
+					next_gen.push(random_admissible(&self.config, &mut rng));
+				} else {
+					next_gen.push(E::new(child));
+				}
+			}
 		}
-		let ptr = &next_gen as *const _ as *const [E; N_CHILDREN];
-		let cast = unsafe { ptr.read() };
-		core::mem::forget(next_gen);
 
 		// Write back children, keeping elite at the end
-		for (i, f) in cast.into_iter().take(N_CHILDREN).enumerate() {
-			self.current_gen.members[i] = f;
+		for (member, f) in self.current_gen.members.iter_mut().zip(next_gen) {
+			*member = f;
 		}
 
+		self.rng = rng;
+
 		// Update generation counter and generate new samples for next round if necessary.
 		self.generation_counter += 1;
-		if self.generation_counter & SAMPLE_LIFETIME_MASK == 0 {
-			self.new_samples(rng);
+		if self.generation_counter & self.sample_lifetime_mask() == 0 {
+			self.new_samples();
 		}
 	}
 
+	/** The loss used for selection, i.e. including the penalty for non-bijective functions if requested. */
+	#[inline(always)]
+	fn selection_loss(&self, ev: &E) -> f32 { ev.get_loss() + penalty(&self.config, ev) }
+
+	/** The (selection) loss that children are raced against, given the ascending selection losses of the members
+	   evaluated so far. `None` if racing is off or there aren't enough of them yet. A tournament's median is never
+	   below the elite's cutoff, so that children that lose the race (and only have a bound for their loss) stay out of
+	   the elite and the hall of fame. */
+	fn racing_cutoff(&self, losses: &[f32]) -> Option<f32> {
+		let elitism = self.config.elitism as usize;
+		match self.config.racing {
+			Racing::Off => None,
+			Racing::Elite => losses.get(elitism.checked_sub(1)?).copied(),
+			Racing::Tournament if losses.len() >= elitism.max(1) =>
+				Some(losses[losses.len() / 2].max(losses[elitism.max(1) - 1])),
+			Racing::Tournament => None,
+		}
+	}
+
+	#[inline(always)]
+	fn sample_lifetime_mask(&self) -> u32 { self.config.sample_lifetime - 1 }
+
 	fn eval_current_gen(&mut self) {
-		// If we just switched samples, evaluate everyting. Otherwise, only children.
-		let i0 = if self.generation_counter & SAMPLE_LIFETIME_MASK == 0 { 0_usize } else { ELITISM as usize };
-		for i in i0..GENERATION_SIZE as usize {
-			// println!("Evaluating function {}", i);
-			let ev = &mut self.current_gen.members[i as usize];
-			ev.update(&self.samples);
-		}
-		self.current_gen.members.sort_unstable_by(|f, g| g.get_loss().partial_cmp(&f.get_loss()).unwrap());
-	}
-
-	/** Performs a deterministic tournament (i.e. fittest competitor always wins) of size `TOURNAMENT_SIZE`. */
-	fn tournament(&self, rng: &mut impl Rng) -> &E {
-		let mut best: &E = &self.current_gen.members[rng.gen_range(0..GENERATION_SIZE as usize)];
-		for _ in 1..TOURNAMENT_SIZE {
-			let cand = &self.current_gen.members[rng.gen_range(0..GENERATION_SIZE as usize)];
-			if cand.get_loss() < best.get_loss() {
-				best = cand;
+		// Children are at the front, the elite at the end (see `next_gen`). If we just switched samples, evaluate
+		// everything, the elite first so that children can be raced against it. Otherwise, only children.
+		let fresh_samples = self.generation_counter & self.sample_lifetime_mask() == 0;
+		let mut members = mem::take(&mut self.current_gen.members);
+		let (children, elite) = members.split_at_mut(self.config.n_children() as usize);
+		if fresh_samples {
+			evaluate_all(elite, &self.samples, &self.config, None);
+		}
+		let mut losses: Vec<f32> = elite.iter().map(|ev| self.selection_loss(ev)).collect();
+		losses.sort_unstable_by(f32::total_cmp);
+		// While racing, the cutoff is updated after each batch of children.
+		let batch_size = if self.config.racing == Racing::Off { children.len().max(1) } else { RACING_BATCH_SIZE };
+		for batch in children.chunks_mut(batch_size) {
+			evaluate_all(batch, &self.samples, &self.config, self.racing_cutoff(&losses));
+			if self.config.racing != Racing::Off {
+				for ev in batch.iter() {
+					let loss = self.selection_loss(ev);
+					losses.insert(losses.partition_point(|&l| l < loss), loss);
+				}
+			}
+		}
+		self.current_gen.members = members;
+		self.sort_members();
+	}
+
+	/** Replaces the worst members with the queued immigrants, evaluates them and sorts them in. */
+	fn integrate_immigrants(&mut self) {
+		if self.immigrants.is_empty() {
+			return;
+		}
+		let n = self.immigrants.len().min(self.config.n_children() as usize);
+		let members = &mut self.current_gen.members[..n];
+		for (member, func) in members.iter_mut().zip(self.immigrants.drain(..)) {
+			*member = E::new(func);
+		}
+		evaluate_all(members, &self.samples, &self.config, None);
+		self.sort_members();
+	}
+
+	/** Lets the elite of the (evaluated and sorted) current generation into the hall of fame. Functions that are
+	   penalised for not being bijections don't get in. */
+	fn enter_hall_of_fame(&mut self) where F: PartialEq, E: Clone {
+		let size = self.config.hall_of_fame_size as usize;
+		for ev in self.current_gen.members.iter().rev().take(self.config.elitism.max(1) as usize) {
+			if penalty(&self.config, ev) == 0.0 {
+				self.hall_of_fame.consider(ev, self.generation_counter, size);
 			}
 		}
-		best
 	}
 
-	fn new_samples(&mut self, rng: &mut impl Rng) {
+	/** Sorts the members by descending (selection) loss, i.e. with the best at the end. */
+	fn sort_members(&mut self) {
+		if self.config.bijectivity == BijectivityPolicy::Penalise {
+			let members = mem::take(&mut self.current_gen.members);
+			let mut members: Vec<(f32, E)> = members.into_iter().map(|ev| (self.selection_loss(&ev), ev)).collect();
+			members.sort_unstable_by(|(l1, _), (l2, _)| l2.partial_cmp(l1).unwrap());
+			self.current_gen.members = members.into_iter().map(|(_, ev)| ev).collect();
+		} else {
+			self.current_gen.members.sort_unstable_by(|f, g| g.get_loss().partial_cmp(&f.get_loss()).unwrap());
+		}
+	}
+
+	/** The (evaluated and sorted) current generation as `SearchConfig::selection` sees it. */
+	fn population(&self) -> Population {
+		let members = &self.current_gen.members;
+		let losses = members.iter().map(|ev| self.selection_loss(ev)).collect();
+		let cases = if self.config.selection.needs_cases() {
+			members.iter().map(|ev| {
+				let penalty = penalty(&self.config, ev);
+				ev.get_case_losses().into_iter().map(|loss| loss + penalty).collect()
+			}).collect()
+		} else {
+			Vec::new()
+		};
+		Population::new(losses, cases)
+	}
+
+	/** Draws fresh samples. Islands take turns in the numbering of draws (see `SampleSource::fill`). */
+	fn new_samples(&mut self) {
 		// let mut next_idx = u32::MAX;
 		// loop {
 		//     next_idx = next_idx.wrapping_add(1).wrapping_add(rng.gen::<u32>().trailing_zeros());
 		//     if next_idx as usize >= N_SAMPLES { break; }
 		//     self.samples[next_idx as usize] = rng.gen();
 		// }
-		for i in 0..N_SAMPLES as usize {
-			self.samples[i] = rng.gen();
+		self.samples_draw = self.n_draws * self.config.n_threads as u64 + self.island as u64;
+		self.samples_rng = self.sample_rng.clone();
+		self.n_draws += 1;
+		self.draw_samples();
+	}
+
+	/** Draws the samples of draw `samples_draw`, starting with `sample_rng` at `samples_rng`. */
+	fn draw_samples(&mut self) {
+		let n_samples = self.config.n_samples as usize;
+		if self.samples.len() != n_samples {
+			self.samples = SampleBuffer::new(n_samples);
 		}
+		self.sample_rng = self.samples_rng.clone();
+		self.source.fill(&mut self.samples, self.samples_draw, &mut self.sample_rng);
 	}
 }
 
@@ -133,20 +488,233 @@ impl<F: DiffusionFunc, E: Evaluator<F>> Evolution<F, E> {
 mod tests {
 
 	use super::*;
-	use crate::{diffusion::mrxsm::MRXSM, evaluation::wasserstein_arith::WassersteinArith};
+	use crate::{
+		diffusion::mrxsm::MRXSM,
+		evaluation::{wasserstein_arith::WassersteinArith, wasserstein_geom::WassersteinGeom},
+	};
+
+	fn test_samples(config: &SearchConfig, source: &dyn SampleSource) -> SampleBuffer {
+		SampleBuffer::from_source(config.n_samples as usize, source, 0, &mut rand::thread_rng())
+	}
 
 	#[test]
 	fn test_eval() {
-		let f = MRXSM::new(0xb520c891288cb35, 0xb018200835e0008d, 21, 59);
+		let f = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let config = SearchConfig::default();
 		let mut rng = rand::thread_rng();
-		let current_gen = Generation::random(&mut rng);
+		let current_gen = Generation::random(&config, &mut rng);
 		let mut members = current_gen.members;
 		members[0] = WassersteinArith::new(f);
 		let current_gen = Generation::new(members);
-		let mut ev = Evolution::new(random_samples(), current_gen);
+		let source = config.sample_source().unwrap();
+		let samples = test_samples(&config, source.as_ref());
+		let mut ev = Evolution::new(config.clone(), source, samples, current_gen);
 		ev.eval_current_gen();
 		println!("{}", ev.current_gen);
 		// let min_sse = AvalancheDiagram::of(&ev.best_func, &ev.samples).sse();
 		// assert_eq!(min_sse, ev.min_error);
 	}
+
+	#[test]
+	fn test_bijectivity_policy() {
+		let mut rng = rand::thread_rng();
+		let config =
+			SearchConfig { bijectivity: BijectivityPolicy::Reject, generation_size: 2000, ..Default::default() };
+		let gen = Generation::<MRXSM, WassersteinArith<MRXSM>>::random(&config, &mut rng);
+		assert!(gen.members.iter().all(|ev| ev.get_func().is_bijective()));
+
+		let config = SearchConfig {
+			bijectivity: BijectivityPolicy::Penalise,
+			bijectivity_penalty: 1000.0,
+			generation_size: 4,
+			elitism: 2,
+			n_samples: 1000,
+			n_rounds: 1,
+			..Default::default()
+		};
+		let non_bijective = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 0, 59);
+		// All other members are bijective.
+		let reject = SearchConfig { bijectivity: BijectivityPolicy::Reject, ..config.clone() };
+		let mut members: Vec<WassersteinArith<MRXSM>> = Generation::random(&reject, &mut rng).members;
+		members[3] = WassersteinArith::new(non_bijective.clone());
+		let source = config.sample_source().unwrap();
+		let samples = test_samples(&config, source.as_ref());
+		let mut ev = Evolution::new(config.clone(), source, samples, Generation::new(members));
+		ev.eval_current_gen();
+		assert_eq!(&non_bijective, ev.current_gen.members[0].get_func());
+		assert!(ev.selection_loss(&ev.current_gen.members[0]) >= 1000.0);
+	}
+
+	/** Races a generation of `E`s against the elite and checks that the elite ends up as without racing. */
+	fn check_racing<E: Evaluator<MRXSM> + Send + Clone + PartialEq + std::fmt::Debug>() {
+		let mut rng = rand::thread_rng();
+		let config = SearchConfig {
+			generation_size: 16,
+			elitism: 4,
+			n_samples: 10_000,
+			n_rounds: 20,
+			racing: Racing::Elite,
+			..Default::default()
+		};
+		let good = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let bad = MRXSM::new(0x3_u64, 0x5, 0, 63);
+		let mut members: Vec<E> = Generation::random(&config, &mut rng).members;
+		members[0] = E::new(bad.clone());
+		members[15] = E::new(good);
+		let source = config.sample_source().unwrap();
+		let samples = test_samples(&config, source.as_ref());
+		let off = SearchConfig { racing: Racing::Off, ..config.clone() };
+		let mut raced = Evolution::new(config, source.clone(), samples.clone(), Generation::new(members.clone()));
+		let mut full = Evolution::new(off, source, samples, Generation::new(members));
+		raced.eval_current_gen();
+		full.eval_current_gen();
+		// The elite is the same and the hopeless candidate was given up on (but is still worse than the elite).
+		let cutoff = raced.current_gen.members[12].get_loss();
+		assert_eq!(&full.current_gen.members[12..], &raced.current_gen.members[12..]);
+		let bad = raced.current_gen.members.iter().find(|ev| ev.get_func() == &bad).unwrap();
+		assert!(bad.get_loss() > cutoff);
+		assert!(raced.current_gen.members.iter().all(|ev| ev.get_age() == 1));
+	}
+
+	#[test]
+	fn test_racing() {
+		check_racing::<WassersteinArith<MRXSM>>();
+		check_racing::<WassersteinGeom<MRXSM>>();
+	}
+
+	#[test]
+	fn test_tournament_keeps_raced_out_children_from_the_elite() {
+		// With a large elite, the median is better than the worst elite member. Children that lose the race at the
+		// first chance only get a bound for their loss, which must not let them into the elite.
+		let config = SearchConfig {
+			generation_size: 16,
+			elitism: 12,
+			n_samples: 10_000,
+			n_rounds: 20,
+			racing: Racing::Tournament,
+			racing_min_rounds: 2,
+			..Default::default()
+		};
+		let good = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let mediocre = MRXSM::new(0x5555_u64, 0x5555, 8, 16);
+		let bad = MRXSM::new(0x3_u64, 0x5, 0, 63);
+		let worse = MRXSM::new(0x1_u64, 0x1, 1, 1);
+		let mut members = vec![WassersteinArith::new(mediocre.clone()), WassersteinArith::new(mediocre)];
+		members.extend(vec![WassersteinArith::new(worse.clone()); 2]);
+		members.extend(vec![WassersteinArith::new(bad); 5]);
+		members.extend(vec![WassersteinArith::new(good); 7]);
+		let source = config.sample_source().unwrap();
+		let samples = test_samples(&config, source.as_ref());
+		let off = SearchConfig { racing: Racing::Off, ..config.clone() };
+		let mut raced = Evolution::new(config, source.clone(), samples.clone(), Generation::new(members.clone()));
+		let mut full = Evolution::new(off, source, samples, Generation::new(members));
+		raced.eval_current_gen();
+		full.eval_current_gen();
+		assert_eq!(&full.current_gen.members[4..], &raced.current_gen.members[4..]);
+		assert!(raced.current_gen.members[4..].iter().all(|ev| ev.get_func() != &worse));
+	}
+
+	#[test]
+	fn test_parallel_evaluation() {
+		let mut rng = rand::thread_rng();
+		let config = SearchConfig {
+			generation_size: 200,
+			elitism: 20,
+			n_samples: 2000,
+			n_rounds: 10,
+			racing: Racing::Tournament,
+			racing_min_rounds: 2,
+			racing_z: 1.0,
+			..Default::default()
+		};
+		let members: Vec<WassersteinArith<MRXSM>> = Generation::random(&config, &mut rng).members;
+		let source = config.sample_source().unwrap();
+		let samples = test_samples(&config, source.as_ref());
+		let parallel = SearchConfig { n_workers: 8, ..config.clone() };
+		let mut serial = Evolution::new(config, source.clone(), samples.clone(), Generation::new(members.clone()));
+		let mut parallel = Evolution::new(parallel, source, samples, Generation::new(members));
+		serial.eval_current_gen();
+		parallel.eval_current_gen();
+		assert_eq!(serial.current_gen.members, parallel.current_gen.members);
+	}
+
+	#[test]
+	fn test_immigrate() {
+		let config =
+			SearchConfig { generation_size: 8, elitism: 6, n_samples: 10_000, n_rounds: 10, ..Default::default() };
+		let good = MRXSM::new(0xb520c891288cb35_u64, 0xb018200835e0008d, 21, 59);
+		let source = config.sample_source().unwrap();
+		let mut ev = Evolution::<MRXSM, WassersteinArith<MRXSM>>::random(config, source, 0);
+		ev.immigrate(vec![good.clone(), good.clone(), good.clone()]);
+		ev.next_gen();
+		// Only as many as there are children replace the worst members, then survive as elite.
+		assert!(ev.immigrants.is_empty());
+		let n_survivors = ev.current_gen.members[2..].iter().filter(|m| m.get_func() == &good).count();
+		assert_eq!(2, n_survivors);
+		assert_eq!(vec![ev.get_best().get_func().clone(); 2], ev.emigrants());
+	}
+
+	#[test]
+	fn test_reproducibility() {
+		let config = SearchConfig {
+			seed: Some(42),
+			n_threads: 2,
+			generation_size: 16,
+			elitism: 4,
+			n_samples: 1000,
+			n_rounds: 5,
+			sample_lifetime: 2,
+			..Default::default()
+		};
+		let source = config.sample_source().unwrap();
+		let run = |config: &SearchConfig, island: u32| {
+			let mut ev = Evolution::<MRXSM, WassersteinArith<MRXSM>>::random(config.clone(), source.clone(), island);
+			for _ in 0..4 {
+				ev.next_gen();
+			}
+			ev
+		};
+		let ev = run(&config, 1);
+		assert_eq!(42, ev.seed());
+		assert_eq!(ev.current_gen.members, run(&config, 1).current_gen.members);
+		// Other islands and seeds search elsewhere.
+		assert_ne!(ev.current_gen.members, run(&config, 0).current_gen.members);
+		let other_seed = SearchConfig { seed: Some(43), ..config.clone() };
+		assert_ne!(ev.current_gen.members, run(&other_seed, 1).current_gen.members);
+	}
+
+	#[test]
+	fn test_selection_kinds() {
+		for name in selection::SelectionKind::NAMES {
+			let config = SearchConfig {
+				selection: name.parse().unwrap(),
+				generation_size: 16,
+				elitism: 4,
+				n_samples: 1000,
+				n_rounds: 10,
+				..Default::default()
+			};
+			let source = config.sample_source().unwrap();
+			let mut ev = Evolution::<MRXSM, WassersteinArith<MRXSM>>::random(config, source, 0);
+			ev.next_gen();
+			ev.next_gen();
+			assert_eq!(16, ev.current_gen.len(), "{}", name);
+			assert!(ev.stats().best_loss < 1.0, "{}", name);
+		}
+	}
+
+	#[test]
+	fn test_large_state_on_small_stack() {
+		// Stored states used to be deserialised into arrays on the stack, which required raising its size.
+		const GENERATION_SIZE: u32 = 50_000;
+		let config = SearchConfig { generation_size: GENERATION_SIZE, ..Default::default() };
+		let mut rng = rand::thread_rng();
+		let gen = Generation::<MRXSM, WassersteinArith<MRXSM>>::random(&config, &mut rng);
+		let serialised = serde_json::to_string(&gen).unwrap();
+		let handle = std::thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+			let gen: Generation<MRXSM, WassersteinArith<MRXSM>> = serde_json::from_str(&serialised).unwrap();
+			gen.len()
+		});
+		assert_eq!(GENERATION_SIZE as usize, handle.unwrap().join().unwrap());
+	}
 }
@@ -0,0 +1,303 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+};
+
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+use crate::config::SearchConfig;
+
+/** How the parents of the children of a generation are picked among its members. */
+pub trait Selection {
+	/** Picks a parent and returns its index in `population`. */
+	fn select(&self, population: &Population, config: &SearchConfig, rng: &mut dyn RngCore) -> usize;
+}
+
+/** The (evaluated) members of a generation as selection sees them: by descending selection loss, i.e. with the best
+   last, like in a `Generation`. */
+#[derive(Debug, Clone, Default)]
+pub struct Population {
+	losses: Vec<f32>,
+	/** The losses of each member on the individual cases (see `Evaluator::get_case_losses`), if the selection needs
+	   them. */
+	cases: Vec<Vec<f32>>,
+	/** By case, how much worse than the best member on it another member may be and still pass a case in lexicase
+	   selection: the median absolute deviation of the case's losses. */
+	epsilons: Vec<f32>,
+}
+
+impl Population {
+	/** `cases` may be empty unless the selection is `Lexicase`. */
+	pub fn new(losses: Vec<f32>, cases: Vec<Vec<f32>>) -> Self {
+		debug_assert!(losses.windows(2).all(|w| w[0] >= w[1]));
+		debug_assert!(cases.is_empty() || cases.len() == losses.len());
+		let n_cases = cases.first().map_or(0, Vec::len);
+		let epsilons = (0..n_cases).map(|case| {
+			let mut losses: Vec<f32> = cases.iter().map(|member| member[case]).collect();
+			let center = median(&mut losses);
+			let mut deviations: Vec<f32> = losses.iter().map(|&loss| (loss - center).abs()).collect();
+			median(&mut deviations)
+		}).collect();
+		Self { losses, cases, epsilons }
+	}
+
+	#[inline(always)]
+	pub fn len(&self) -> usize { self.losses.len() }
+
+	#[inline(always)]
+	pub fn is_empty(&self) -> bool { self.losses.is_empty() }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+	values.sort_unstable_by(f32::total_cmp);
+	values[values.len() / 2]
+}
+
+/** Picks an index with a probability proportional to its (non-negative) weight. */
+fn roulette(weights: impl Iterator<Item = f64> + Clone, rng: &mut dyn RngCore) -> usize {
+	let total: f64 = weights.clone().sum();
+	let mut r = rng.gen::<f64>() * total;
+	let mut last = 0;
+	for (i, weight) in weights.enumerate() {
+		if r < weight {
+			return i;
+		}
+		r -= weight;
+		last = i;
+	}
+	// Only reached through rounding errors.
+	last
+}
+
+
+/** The selection strategies that can be picked through `SearchConfig::selection`. Like `SampleDistribution`, their
+   textual form is the name, optionally followed by `:<parameter>`, e.g. `linear_rank:1.8`. */
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SelectionKind {
+	/** The best of `SearchConfig::tournament_size` random members wins. */
+	#[default]
+	Tournament,
+	/** Of `SearchConfig::tournament_size` random members, the best wins with probability `p`, otherwise the second
+	   best with probability `p`, and so on. */
+	ProbabilisticTournament { p: f64 },
+	/** The probability of a member falls linearly with its rank, from `pressure / n` for the best to
+	   `(2 - pressure) / n` for the worst of `n`. `pressure` is in [1, 2]. */
+	LinearRank { pressure: f64 },
+	/** The probability of a member falls exponentially with its rank: each is `base` times as likely as the next
+	   better one. */
+	ExponentialRank { base: f64 },
+	/** The probability of a member is proportional to its fitness, the loss transformed linearly so that it's 1 for the
+	   best and 0 for the worst member. */
+	Proportional,
+	/** A uniformly random member of the best `fraction` of the generation. */
+	Truncation { fraction: f64 },
+	/** Epsilon-lexicase selection over the case losses (see `Evaluator::get_case_losses`), i.e. for the Wasserstein
+	   evaluators over the distances of the individual input bits (or differences). The cases are shuffled and, case by
+	   case, only the members are kept that are within the median absolute deviation of the best remaining one on it,
+	   until one is left or the cases run out. Favours specialists that are excellent on some bits over members that
+	   are mediocre on all of them. */
+	Lexicase,
+}
+
+impl SelectionKind {
+	pub const NAMES: [&'static str; 7] =
+		["tournament", "probabilistic_tournament", "linear_rank", "exponential_rank", "proportional", "truncation",
+			"lexicase"];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			SelectionKind::Tournament => "tournament",
+			SelectionKind::ProbabilisticTournament { .. } => "probabilistic_tournament",
+			SelectionKind::LinearRank { .. } => "linear_rank",
+			SelectionKind::ExponentialRank { .. } => "exponential_rank",
+			SelectionKind::Proportional => "proportional",
+			SelectionKind::Truncation { .. } => "truncation",
+			SelectionKind::Lexicase => "lexicase",
+		}
+	}
+
+	/** Whether `Population` has to hold the case losses of the members. */
+	pub fn needs_cases(&self) -> bool { *self == SelectionKind::Lexicase }
+}
+
+impl Selection for SelectionKind {
+	fn select(&self, population: &Population, config: &SearchConfig, rng: &mut dyn RngCore) -> usize {
+		let n = population.len();
+		let losses = &population.losses;
+		match *self {
+			SelectionKind::Tournament => {
+				let mut best = rng.gen_range(0..n);
+				for _ in 1..config.tournament_size {
+					let candidate = rng.gen_range(0..n);
+					if losses[candidate] < losses[best] {
+						best = candidate;
+					}
+				}
+				best
+			}
+			SelectionKind::ProbabilisticTournament { p } => {
+				let mut competitors: Vec<usize> = (0..config.tournament_size).map(|_| rng.gen_range(0..n)).collect();
+				competitors.sort_by(|&i, &j| losses[i].total_cmp(&losses[j]));
+				let last = competitors.len() - 1;
+				competitors[..last].iter().copied().find(|_| rng.gen_bool(p)).unwrap_or(competitors[last])
+			}
+			SelectionKind::LinearRank { pressure } => {
+				// Members are sorted by descending loss, so the index is the rank counted from the worst.
+				let slope = if n > 1 { 2.0 * (pressure - 1.0) / (n - 1) as f64 } else { 0.0 };
+				roulette((0..n).map(|i| 2.0 - pressure + slope * i as f64), rng)
+			}
+			SelectionKind::ExponentialRank { base } => {
+				roulette((0..n).map(|i| base.powi((n - 1 - i) as i32)), rng)
+			}
+			SelectionKind::Proportional => {
+				let (worst, best) = (losses[0] as f64, losses[n - 1] as f64);
+				if !(worst - best).is_normal() {
+					return rng.gen_range(0..n);
+				}
+				roulette(losses.iter().map(|&loss| (worst - loss as f64) / (worst - best)), rng)
+			}
+			SelectionKind::Truncation { fraction } => {
+				let k = ((fraction * n as f64).ceil() as usize).clamp(1, n);
+				n - 1 - rng.gen_range(0..k)
+			}
+			SelectionKind::Lexicase => {
+				let mut order: Vec<usize> = (0..population.epsilons.len()).collect();
+				order.shuffle(rng);
+				let mut candidates: Vec<usize> = (0..n).collect();
+				for case in order {
+					if candidates.len() == 1 {
+						break;
+					}
+					let loss = |i: usize| population.cases[i][case];
+					let best = candidates.iter().map(|&i| loss(i)).min_by(f32::total_cmp).unwrap();
+					candidates.retain(|&i| loss(i) <= best + population.epsilons[case]);
+				}
+				candidates[rng.gen_range(0..candidates.len())]
+			}
+		}
+	}
+}
+
+impl Display for SelectionKind {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			SelectionKind::ProbabilisticTournament { p } => write!(f, "{}:{}", self.name(), p),
+			SelectionKind::LinearRank { pressure } => write!(f, "{}:{}", self.name(), pressure),
+			SelectionKind::ExponentialRank { base } => write!(f, "{}:{}", self.name(), base),
+			SelectionKind::Truncation { fraction } => write!(f, "{}:{}", self.name(), fraction),
+			_ => write!(f, "{}", self.name()),
+		}
+	}
+}
+
+impl FromStr for SelectionKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		let (name, param) = match s.split_once(':') {
+			Some((name, param)) => (name.trim(), Some(param.trim())),
+			None => (s, None),
+		};
+		// The parameter must lie in `low..=high`, or `low<..=high` if `open` (as probabilities of 0 make no sense).
+		let param_in = |default: f64, low: f64, high: f64, open: bool| -> Result<f64, String> {
+			let value = param.map(str::parse).transpose().map_err(|_| format!("invalid parameter in {}", s))?;
+			let value: f64 = value.unwrap_or(default);
+			if !(value <= high && (value > low || !open && value == low)) {
+				let range = if open { format!("({}, {}]", low, high) } else { format!("[{}, {}]", low, high) };
+				return Err(format!("parameter of {} must be in {}", name, range));
+			}
+			Ok(value)
+		};
+		let no_param = |kind: SelectionKind| match param {
+			None => Ok(kind),
+			Some(_) => Err(format!("{} doesn't take a parameter", name)),
+		};
+		match name {
+			"tournament" => no_param(SelectionKind::Tournament),
+			"probabilistic_tournament" => {
+				Ok(SelectionKind::ProbabilisticTournament { p: param_in(0.75, 0.0, 1.0, true)? })
+			}
+			"linear_rank" => Ok(SelectionKind::LinearRank { pressure: param_in(1.5, 1.0, 2.0, false)? }),
+			"exponential_rank" => Ok(SelectionKind::ExponentialRank { base: param_in(0.99, 0.0, 1.0, true)? }),
+			"proportional" => no_param(SelectionKind::Proportional),
+			"truncation" => Ok(SelectionKind::Truncation { fraction: param_in(0.25, 0.0, 1.0, true)? }),
+			"lexicase" => no_param(SelectionKind::Lexicase),
+			_ => Err(format!("unknown selection {}", name)),
+		}
+	}
+}
+
+impl TryFrom<String> for SelectionKind {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> { s.parse() }
+}
+
+impl From<SelectionKind> for String {
+	fn from(kind: SelectionKind) -> Self { kind.to_string() }
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::xoshiro::Xoshiro256PlusPlus;
+	use rand::SeedableRng;
+
+	/** How often each member of `population` is picked in 10000 selections. */
+	fn counts(kind: &str, population: &Population, config: &SearchConfig) -> Vec<u32> {
+		let kind: SelectionKind = kind.parse().unwrap();
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(0);
+		let mut counts = vec![0; population.len()];
+		for _ in 0..10_000 {
+			counts[kind.select(population, config, &mut rng)] += 1;
+		}
+		counts
+	}
+
+	#[test]
+	fn test_selection() {
+		let config = SearchConfig { tournament_size: 2, ..Default::default() };
+		let population = Population::new((0..10).rev().map(|i| i as f32).collect(), Vec::new());
+		for kind in ["tournament", "probabilistic_tournament:0.9", "linear_rank:2", "exponential_rank:0.7",
+			"proportional", "truncation:0.5"] {
+			let counts = counts(kind, &population, &config);
+			// Better members are picked more often, or at least as often.
+			assert!(counts.windows(2).all(|w| w[0] <= w[1] + 300), "{}: {:?}", kind, counts);
+			assert!(counts[9] > counts[0] + 500, "{}: {:?}", kind, counts);
+		}
+		// The worst never gets picked where it has no chance.
+		for kind in ["linear_rank:2", "proportional", "truncation:0.5"] {
+			assert_eq!(0, counts(kind, &population, &config)[0], "{}", kind);
+		}
+		assert!(counts("truncation:0.5", &population, &config)[..5].iter().all(|&count| count == 0));
+		let uniform = counts("linear_rank:1", &population, &config);
+		assert!(uniform.iter().all(|&count| (800..1200).contains(&count)), "{:?}", uniform);
+
+		// The first member is the best on case 0 by far, the second on case 1, the last is decent on both and has
+		// the lowest (maximum) loss, the rest are bad on both.
+		let cases = vec![vec![0.0, 1.0], vec![1.0, 0.0], vec![0.9, 0.9], vec![0.8, 0.9], vec![0.5, 0.5]];
+		let losses = cases.iter().map(|case: &Vec<f32>| case.iter().copied().fold(0.0, f32::max)).collect();
+		let lexicase = Population::new(losses, cases);
+		let counts = counts("lexicase", &lexicase, &config);
+		assert!(counts[0] > 4000 && counts[1] > 4000 && counts[2] + counts[3] + counts[4] == 0, "{:?}", counts);
+	}
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(Ok(SelectionKind::LinearRank { pressure: 1.8 }), "linear_rank:1.8".parse());
+		assert_eq!(Ok(SelectionKind::ExponentialRank { base: 0.99 }), "exponential_rank".parse());
+		for name in SelectionKind::NAMES {
+			let kind: SelectionKind = name.parse().unwrap();
+			assert_eq!(Ok(kind), kind.to_string().parse());
+		}
+		for invalid in ["linear_rank:2.5", "probabilistic_tournament:0", "truncation:x", "lexicase:3", "roulette"] {
+			assert!(invalid.parse::<SelectionKind>().is_err(), "{}", invalid);
+		}
+		let json = serde_json::to_string(&SelectionKind::Truncation { fraction: 0.5 }).unwrap();
+		assert_eq!("\"truncation:0.5\"", json);
+		assert_eq!(SelectionKind::Truncation { fraction: 0.5 }, serde_json::from_str(&json).unwrap());
+	}
+}
@@ -1,12 +1,18 @@
 #![forbid(non_ascii_idents)]
+#![allow(clippy::tabs_in_doc_comments)]
 #![allow(dead_code)]
 #![allow(unused_imports)]
 extern crate serde_json;
 use std::{
 	any::Any,
+	collections::BTreeSet,
+	env,
+	fmt::Display,
 	fs::{self, File},
 	io::{Read, Write},
-	path::Path,
+	path::{Path, PathBuf},
+	process,
+	str::FromStr,
 	sync::{
 		atomic::{AtomicBool, Ordering},
 		Arc,
@@ -15,22 +21,212 @@ use std::{
 	time::{Duration, Instant},
 };
 
-use diffusion::{evaluation::Evaluator, evolution::Evolution, globals::*};
+use diffusion::{
+	config::{RngStream, SearchConfig},
+	diffusion::{any_diffusion::AnyDiffusion, DiffusionFunc},
+	evaluation::{
+		avalanche_sse::AvalancheSse, bic_arith::BicArith, stats, wasserstein_arith::WassersteinArith,
+		wasserstein_geom::WassersteinGeom, wasserstein_worst::WassersteinWorst, Evaluator, EvaluatorKind,
+	},
+	evolution::{
+		checkpoint::{self, CheckpointFormat},
+		exam::{Candidate, Exam, Score},
+		hall_of_fame::HallOfFame,
+		history::History,
+		islands::Island,
+		Evolution,
+	},
+	utils::{sample_buffer::SampleBuffer, sample_source::SampleSource, word::Word},
+};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rand::{rngs::OsRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+
+/** The family of diffusion functions is picked at runtime through `SearchConfig::family`, the word width through
+   `SearchConfig::word_width`. */
+type F<W> = AnyDiffusion<W>;
+
+/** Everything we need of an evaluator. The concrete type is picked once at startup through `SearchConfig::evaluator`
+   (and `SearchConfig::word_width`), so that the rest of the program is monomorphised. */
+trait SearchEvaluator<W: Word>: Evaluator<F<W>> + Clone + Serialize + DeserializeOwned + Send + 'static {}
+
+impl<W: Word, E: Evaluator<F<W>> + Clone + Serialize + DeserializeOwned + Send + 'static> SearchEvaluator<W> for E {}
+
+const USAGE: &str = "\
+usage: diffusion [search] [<options>]
+       diffusion evaluate <func> [<options>]
+       diffusion compare <func> <func>... [<options>]
+       diffusion resume <state dir> [<options>]
+       diffusion export [<state dir>] [--top <n>] [<options>]
+
+<func> is a diffusion function as printed by the search, e.g. \"MRXSM{m1:0x..., m2:0x..., s1:21, s2:59}\".
+<options> are `--config <file>` and any parameter of the search as `--<name> <value>` (see README).";
+
+/** Directory used by `search` to store (and pick up) the state of each thread. */
+const STATE_DIR: &str = "state";
+
+enum Command {
+	/** Runs the genetic search, continuing from any state found in `STATE_DIR`. */
+	Search,
+	/** Scores a single function. */
+	Evaluate(String),
+	/** Ranks several functions evaluated on the same samples. */
+	Compare(Vec<String>),
+	/** Continues the search from the states stored in the given directory. */
+	Resume(PathBuf),
+	/** Prints the best (up to the given number of) candidates of the states stored in the given directory. */
+	Export(PathBuf, usize),
+}
+
+fn exit_with_usage(msg: impl Display) -> ! {
+	eprintln!("{}\n\n{}", msg, USAGE);
+	process::exit(2);
+}
+
+/** Splits the command line into a command and the configuration given by the remaining flags. */
+fn parse_args() -> (Command, SearchConfig) {
+	let mut args: Vec<String> = env::args().skip(1).collect();
+	let name = match args.first() {
+		Some(arg) if !arg.starts_with("--") => args.remove(0),
+		_ => String::from("search"),
+	};
+	let n_positional = args.iter().take_while(|arg| !arg.starts_with("--")).count();
+	let mut positional: Vec<String> = args.drain(..n_positional).collect();
+	let command = match (name.as_str(), positional.len()) {
+		("search", 0) => Command::Search,
+		("evaluate", 1) => Command::Evaluate(positional.remove(0)),
+		("compare", n) if n > 1 => Command::Compare(positional),
+		("resume", 1) => Command::Resume(PathBuf::from(positional.remove(0))),
+		("export", n) if n < 2 => {
+			let dir = positional.pop().unwrap_or_else(|| String::from(STATE_DIR));
+			let top = match args.iter().position(|arg| arg == "--top") {
+				Some(i) => {
+					let top = args.get(i + 1).and_then(|n| n.parse().ok());
+					let top = top.unwrap_or_else(|| exit_with_usage("--top expects a number"));
+					args.drain(i..i + 2);
+					top
+				}
+				None => 10,
+			};
+			Command::Export(PathBuf::from(dir), top)
+		}
+		("search" | "evaluate" | "compare" | "resume" | "export", _) => {
+			exit_with_usage(format!("wrong number of arguments for {}", name))
+		}
+		_ => exit_with_usage(format!("unknown command {}", name)),
+	};
+	match SearchConfig::from_args(args) {
+		Ok(config) => (command, config),
+		Err(why) => exit_with_usage(why),
+	}
+}
+
+/** Calls `$f::<W, E>($args)` with the word type `W` and evaluator `E` picked by `$config`. */
+macro_rules! dispatch {
+	($config:expr, $f:ident($($args:expr),*)) => {
+		match $config.word_width {
+			16 => dispatch!(@evaluator $config, u16, $f($($args),*)),
+			32 => dispatch!(@evaluator $config, u32, $f($($args),*)),
+			64 => dispatch!(@evaluator $config, u64, $f($($args),*)),
+			128 => dispatch!(@evaluator $config, u128, $f($($args),*)),
+			width => unreachable!("unsupported word width {}", width),
+		}
+	};
+	(@evaluator $config:expr, $w:ty, $f:ident($($args:expr),*)) => {
+		match $config.evaluator {
+			EvaluatorKind::WassersteinArith => $f::<$w, WassersteinArith<F<$w>>>($($args),*),
+			EvaluatorKind::WassersteinGeom => $f::<$w, WassersteinGeom<F<$w>>>($($args),*),
+			EvaluatorKind::AvalancheSseArith => $f::<$w, AvalancheSse<F<$w>, false>>($($args),*),
+			EvaluatorKind::AvalancheSseGeom => $f::<$w, AvalancheSse<F<$w>, true>>($($args),*),
+			EvaluatorKind::BicArith => $f::<$w, BicArith<F<$w>>>($($args),*),
+			EvaluatorKind::WassersteinWorst => $f::<$w, WassersteinWorst<F<$w>>>($($args),*),
+		}
+	};
+}
 
 #[cfg(feature = "profile")]
 fn main() {
-	let mut rng = rand::thread_rng();
-	let mut ev = Evolution::<F, E>::random(&mut rng);
+	let (_, config) = parse_args();
+	let config = with_seed(config);
+	dispatch!(config, profile(config))
+}
+
+#[cfg(feature = "profile")]
+fn profile<W: Word, E: SearchEvaluator<W>>(config: SearchConfig) {
+	let source = config.sample_source().unwrap_or_else(|why| exit_with_usage(why));
+	let mut ev = Evolution::<F<W>, E>::random(config.clone(), source, 0);
 	let start = Instant::now();
-	ev.next_gen(&mut rng);
+	ev.next_gen();
 	let elapsed = start.elapsed();
-	println!("Evaluation took {} ms (generation size: {}, samples: {})", elapsed.as_millis(), GENERATION_SIZE,
-		N_SAMPLES);
+	println!("Evaluation took {} ms (generation size: {}, samples: {})", elapsed.as_millis(), config.generation_size,
+		config.n_samples);
 }
 
 #[cfg(not(feature = "profile"))]
 fn main() {
+	let (command, config) = parse_args();
+	let config = with_seed(config);
+	let result = dispatch!(config, run(command, config));
+	if let Err(why) = result {
+		eprintln!("{}", why);
+		process::exit(1);
+	}
+}
+
+/** Draws a master seed if none was given, so that every run can be repeated. */
+fn with_seed(mut config: SearchConfig) -> SearchConfig {
+	config.seed.get_or_insert_with(|| OsRng.next_u64());
+	config
+}
+
+
+fn run<W: Word, E: SearchEvaluator<W>>(command: Command, config: SearchConfig) -> Result<(), String> {
+	let source = || config.sample_source().map_err(|why| why.to_string());
+	match command {
+		Command::Search => {
+			let source = source()?;
+			search::<W, E>(config, source, Path::new(STATE_DIR))
+		}
+		Command::Evaluate(func) => compare::<W, E>(&config, source()?.as_ref(), &[func]),
+		Command::Compare(funcs) => compare::<W, E>(&config, source()?.as_ref(), &funcs),
+		Command::Resume(dir) => {
+			let missing: Vec<u32> = (0..config.n_threads).filter(|&n| checkpoint::find(&dir, n).is_none()).collect();
+			if missing.is_empty() {
+				let source = source()?;
+				search::<W, E>(config, source, &dir)
+			} else {
+				Err(format!("no stored state in {} for thread(s) {:?}", dir.display(), missing))
+			}
+		}
+		Command::Export(dir, top) => export::<W, E>(&config, &dir, top),
+	}
+}
+
+
+/** Runs the search, continuing from the checkpoints in `state_dir` of those threads that have one. */
+#[allow(clippy::type_complexity)]
+fn search<W: Word, E: SearchEvaluator<W>>(config: SearchConfig, source: Arc<dyn SampleSource>, state_dir: &Path)
+	-> Result<(), String> {
+	let mut evs = Vec::with_capacity(config.n_threads as usize);
+	let mut histories = Vec::with_capacity(config.n_threads as usize);
+	let mut n_resumed = 0;
+	for thread_num in 0..config.n_threads {
+		let ev = match checkpoint::load::<F<W>, E>(state_dir, thread_num, &config).map_err(|why| why.to_string())? {
+			Some(checkpoint) => {
+				let ev = checkpoint.resume(config.clone(), Arc::clone(&source)).map_err(|why| why.to_string())?;
+				println!("Continuing from previous state {} (gen: {}, seed: {})", thread_num, ev.generation_counter,
+					ev.seed());
+				n_resumed += 1;
+				ev
+			}
+			None => Evolution::random(config.clone(), Arc::clone(&source), thread_num),
+		};
+		let history = History::open(state_dir, thread_num, config.history, ev.generation_counter)
+			.map_err(|why| format!("couldn't open the history of thread {}: {}", thread_num, why))?;
+		evs.push(ev);
+		histories.push(history);
+	}
+
 	// Start evolution threads
 	let mp = MultiProgress::new();
 	let interrupt = Arc::new(AtomicBool::new(false));
@@ -38,95 +234,155 @@ fn main() {
 		let interrupt = Arc::clone(&interrupt);
 		ctrlc::set_handler(move || interrupt.store(true, Ordering::Release)).unwrap();
 	}
-	let mut handles = Vec::with_capacity(N_THREADS as usize);
+	// Resumed threads go on with their stored seed.
+	if n_resumed < config.n_threads {
+		println!("Seed: {} (pass --seed {} to repeat this search)", config.seed.unwrap(), config.seed.unwrap());
+	}
+	let first_gens: Vec<u32> = evs.iter().map(|ev| ev.generation_counter).collect();
+	// Threads that continue from a checkpoint run `n_generations` more, unless `until_generation` says otherwise.
+	let last_gens: Vec<u32> =
+		first_gens.iter().map(|&gen| config.until_generation.unwrap_or(gen + config.n_generations)).collect();
+	let mut handles = Vec::with_capacity(config.n_threads as usize);
 	let start = Instant::now();
-	for n in 0..N_THREADS {
-		handles.push(spawn_thread(n, Arc::clone(&interrupt), &mp));
+	let threads = Island::archipelago(config.n_threads).into_iter().zip(evs).zip(histories).zip(&last_gens);
+	for (((island, ev), history), &last_gen) in threads {
+		let state_dir = state_dir.to_path_buf();
+		handles.push(spawn_thread(island, ev, last_gen, history, state_dir, Arc::clone(&interrupt), &mp));
 	}
 	println!();
 	let _ = mp.join();
-	let results: Vec<Result<Evolution<F, E>, Box<dyn Any + Send>>> = handles.into_iter().map(|h| h.join()).collect();
-	print_results(&results, &start.elapsed());
+	let results: Vec<Result<Evolution<F<W>, E>, Box<dyn Any + Send>>> = handles.into_iter().map(|h| h.join()).collect();
+	let interrupted = interrupt.load(Ordering::Acquire);
+	if interrupted {
+		println!("\nInterrupted. Continue with `resume {} --until-generation {}` and the same options.",
+			state_dir.display(), last_gens.iter().max().unwrap());
+	}
+	// The threads run side by side, so the one that ran the most generations took the time.
+	let n_generations = results.iter().zip(first_gens)
+		.filter_map(|(r, first_gen)| r.as_ref().ok().map(|ev| ev.generation_counter - first_gen)).max().unwrap_or(0);
+	print_results(&config, &results, n_generations, &start.elapsed());
+	if !interrupted && config.exam_top > 0 {
+		final_exam(&config, source, &results, &interrupt);
+	}
+	Ok(())
 }
 
 
-fn spawn_thread(thread_num: u32, interrupt: Arc<AtomicBool>, mp: &MultiProgress) -> JoinHandle<Evolution<F, E>> {
-	let pb = mp.add(ProgressBar::new(N_GENERATIONS as u64));
+/** Evaluates all given functions (of any family) on the same (fresh) samples and prints them ordered by loss. */
+fn compare<W: Word, E: SearchEvaluator<W>>(config: &SearchConfig, source: &dyn SampleSource, funcs: &[String])
+	-> Result<(), String> {
+	let mut evs = Vec::with_capacity(funcs.len());
+	for func in funcs {
+		evs.push(E::new(F::<W>::from_str(func).map_err(|why| why.to_string())?));
+	}
+	let mut rng = config.rng(0, RngStream::Samples);
+	let samples = SampleBuffer::from_source(config.n_samples as usize, source, 0, &mut rng);
+	for ev in evs.iter_mut() {
+		ev.update(&samples, config);
+	}
+	evs.sort_by(|f, g| f.get_loss().partial_cmp(&g.get_loss()).unwrap());
+	for (i, ev) in evs.iter().enumerate() {
+		println!("{:>3}. {}", i + 1, ev);
+		println!("     {}", confidence::<W, E>(ev, config));
+	}
+	Ok(())
+}
+
+
+/** Prints the `top` candidates with the lowest loss across all states stored in `state_dir` as tab-separated lines of
+   loss, age and function (so that the latter can be passed to `evaluate` and `compare`). */
+fn export<W: Word, E: SearchEvaluator<W>>(config: &SearchConfig, state_dir: &Path, top: usize) -> Result<(), String> {
+	let entries = fs::read_dir(state_dir).map_err(|why| format!("couldn't read {}: {}", state_dir.display(), why))?;
+	let mut thread_nums = BTreeSet::new();
+	for entry in entries.flatten() {
+		let path = entry.path();
+		let thread_num = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<u32>().ok());
+		let extension = path.extension().and_then(|e| e.to_str());
+		let is_checkpoint = CheckpointFormat::ALL.iter().any(|format| extension == Some(format.extension()));
+		if let (Some(thread_num), true) = (thread_num, is_checkpoint) {
+			thread_nums.insert(thread_num);
+		}
+	}
+	let mut states = Vec::new();
+	for thread_num in thread_nums {
+		let checkpoint = checkpoint::load::<F<W>, E>(state_dir, thread_num, config).map_err(|why| why.to_string())?;
+		states.extend(checkpoint.map(|checkpoint| checkpoint.evolution));
+	}
+	if states.is_empty() {
+		return Err(format!("no stored state in {}", state_dir.display()));
+	}
+	// Members that have never been evaluated don't have a meaningful loss.
+	let mut candidates: Vec<&E> =
+		states.iter().flat_map(|ev| ev.current_gen.members.iter()).filter(|f| f.get_age() > 0).collect();
+	candidates.sort_by(|f, g| f.get_loss().partial_cmp(&g.get_loss()).unwrap());
+	for f in candidates.into_iter().take(top) {
+		println!("{}\t{}\t{}", f.get_loss(), f.get_age(), f.get_func());
+	}
+	Ok(())
+}
+
+
+fn spawn_thread<W: Word, E: SearchEvaluator<W>>(mut island: Island<F<W>>, mut ev: Evolution<F<W>, E>, last_gen: u32,
+	mut history: Option<History>, state_dir: PathBuf, interrupt: Arc<AtomicBool>, mp: &MultiProgress)
+	-> JoinHandle<Evolution<F<W>, E>> {
+	let pb = mp.add(ProgressBar::new(last_gen as u64));
+	// A resumed search picks up at the generation it stopped at.
+	pb.set_position(ev.generation_counter as u64);
 	let sty = ProgressStyle::default_bar()
 		.template("[{spinner:.yellow} {elapsed_precise}/{eta_precise}] [{bar:40}] {pos:>6}/{len:6} {msg}")
 		.progress_chars("#>-");
 	pb.set_style(sty);
 	thread::spawn(move || {
-		let mut rng = rand::thread_rng();
-		let mut ev = match try_load_state(thread_num) {
-			Some(ev) => {
-				println!("Continuing from previous state {} (gen: {})", thread_num, ev.generation_counter);
-				ev
-			}
-			None => Evolution::random(&mut rng),
-		};
-		for i in 0..N_GENERATIONS {
+		let thread_num = island.index();
+		while ev.generation_counter < last_gen {
 			if interrupt.load(Ordering::Acquire) {
 				break;
 			}
-			ev.next_gen(&mut rng);
+			let start = Instant::now();
+			ev.next_gen();
+			// Waiting for the neighbours to migrate isn't part of the generation.
+			let elapsed = start.elapsed();
+			island.migrate(&mut ev, &interrupt);
+			if let Some(history) = history.as_mut() {
+				if let Err(why) = history.write(ev.stats(), elapsed) {
+					pb.println(format!("couldn't write the history of thread {}: {}", thread_num, why));
+				}
+			}
 			if ev.generation_counter & 31 == 0 {
-				store_state(thread_num, &ev);
+				if let Err(why) = checkpoint::store(&state_dir, thread_num, &ev) {
+					pb.println(why.to_string());
+				}
 			}
-			pb.set_position(i as u64);
+			pb.set_position(ev.generation_counter as u64);
 			let best = ev.get_best();
 			pb.set_message(format!("{}", best));
 		}
 		pb.finish();
-		// store_state(thread_num, &ev);
+		// Whether the search is done or was interrupted, this is where `resume` picks up.
+		if let Err(why) = checkpoint::store(&state_dir, thread_num, &ev) {
+			eprintln!("{}", why);
+		}
 		ev
 	})
 }
 
 
-fn try_load_state(thread_num: u32) -> Option<Evolution<F, E>> {
-	let path = format!("state/{}.json", thread_num);
-	let path = Path::new(path.as_str());
-	let display = path.display();
-	let mut file = match File::open(path) {
-		Ok(f) => f,
-		Err(_) => return Option::None,
-	};
-	let mut s = String::new();
-	if let Err(why) = file.read_to_string(&mut s) {
-		panic!("couldn't read {}: {}", display, why)
-	}
-	let ev: Evolution<F, E> = match serde_json::from_str(&s) {
-		Ok(e) => e,
-		Err(why) => panic!("invalid state {}: {}", thread_num, why),
-	};
-	Option::Some(ev)
-}
-
-
-fn store_state(thread_num: u32, ev: &Evolution<F, E>) {
-	fs::create_dir_all("state").unwrap();
-	let mut file = File::create(format!("state/{}.json", thread_num)).unwrap();
-	let serialised = serde_json::to_string(ev).unwrap();
-	file.write_all(serialised.as_bytes()).unwrap();
-}
-
-
 #[allow(clippy::type_complexity)]
-fn print_results(results: &[Result<Evolution<F, E>, Box<dyn Any + Send>>], elapsed: &Duration) {
+fn print_results<W: Word, E: SearchEvaluator<W>>(config: &SearchConfig, results: &[Result<Evolution<F<W>, E>, Box<dyn Any + Send>>],
+	n_generations: u32, elapsed: &Duration) {
 	println!(
 		"\nEvolution took {} s (generations: {}, threads: {}, time/gen: {:.2} ms)",
 		elapsed.as_secs(),
-		N_GENERATIONS,
-		N_THREADS,
-		elapsed.as_millis() as f64 / N_GENERATIONS as f64
+		n_generations,
+		config.n_threads,
+		elapsed.as_millis() as f64 / n_generations.max(1) as f64
 	);
 	let mut best: Option<&E> = Option::None;
 	let mut oldest: Option<&E> = Option::None;
 	for (i, r) in results.iter().enumerate() {
-		println!("\nResults for thread {}:", i);
 		match r {
 			Ok(ev) => {
+				println!("\nResults for thread {} (seed: {}):", i, ev.seed());
 				// println!("{}\n", serde_json::to_string(&ev).unwrap());
 				println!("Best current: {}", ev.get_best());
 				let thread_oldest = ev.get_longest_lived();
@@ -142,15 +398,124 @@ fn print_results(results: &[Result<Evolution<F, E>, Box<dyn Any + Send>>], elaps
 			Err(_) => panic!("Failed printing summary!"),
 		}
 	}
-	println!("\nAbsolute lowest loss: {}", best.unwrap());
+	let best = best.unwrap();
+	println!("\nAbsolute lowest loss: {}", best);
+	println!("  {}", confidence::<W, E>(best, config));
+	// The best distinct function other than the best one (elites may appear in several threads).
+	let best_func = best.get_func().to_string();
+	let runner_up = results.iter().flatten().flat_map(|ev| ev.current_gen.members.iter())
+		.filter(|f| f.get_age() > 0 && f.get_func().to_string() != best_func)
+		.min_by(|f, g| f.get_loss().partial_cmp(&g.get_loss()).unwrap());
+	if let Some(runner_up) = runner_up {
+		println!("Runner-up: {}", runner_up);
+		println!("  {}", confidence::<W, E>(runner_up, config));
+		let p = stats::p_lower(best.get_loss(), best.get_std_error(), runner_up.get_loss(), runner_up.get_std_error());
+		let verdict = if p < stats::SIGNIFICANCE_LEVEL { "significantly" } else { "not significantly" };
+		println!("The best function is {} better than the runner-up (p = {:.3e})", verdict, p);
+	}
 	println!("Absolute oldest: {}", oldest.unwrap());
+	let hall_of_fame = HallOfFame::merge(results.iter().flatten().map(|ev| ev.hall_of_fame()),
+		config.hall_of_fame_size as usize);
+	if !hall_of_fame.is_empty() {
+		println!("\nHall of fame (age is the number of evaluations):");
+		for (i, entry) in hall_of_fame.entries().iter().enumerate() {
+			println!("{:>3}. {} (since generation {})", i + 1, entry.ev, entry.since);
+		}
+	}
 	// println!();
 	// print_by_age(results, oldest.unwrap().get_age());
 }
 
 
+/** Re-scores the best candidates of all threads and the hall of fame on fresh samples with every evaluator (see
+   `Exam`) and prints them ranked by the loss of the search's evaluator. */
+#[allow(clippy::type_complexity)]
+fn final_exam<W: Word, E: SearchEvaluator<W>>(config: &SearchConfig, source: Arc<dyn SampleSource>,
+	results: &[Result<Evolution<F<W>, E>, Box<dyn Any + Send>>], interrupt: &AtomicBool) {
+	let evolutions: Vec<&Evolution<F<W>, E>> = results.iter().flatten().collect();
+	let hall_of_fame = HallOfFame::merge(evolutions.iter().map(|ev| ev.hall_of_fame()),
+		config.hall_of_fame_size as usize);
+	let candidates = Exam::candidates(evolutions, &hall_of_fame, config.exam_top as usize);
+	if candidates.is_empty() {
+		return;
+	}
+	let exam = Exam::new(config, source);
+	println!("\nFinal exam of {} candidates on {} x {} fresh samples (Ctrl-C to skip)...", candidates.len(),
+		config.exam_factor, config.n_samples);
+	let mut columns: Vec<(EvaluatorKind, Vec<Score>)> = Vec::new();
+	for kind in exam.evaluators() {
+		let config = SearchConfig { evaluator: kind, ..exam.config().clone() };
+		match dispatch!(@evaluator config, W, sit(&exam, &candidates, interrupt)) {
+			Some(scores) => columns.push((kind, scores)),
+			None => break,
+		}
+	}
+	if columns.is_empty() {
+		println!("Interrupted.");
+		return;
+	}
+	let mut ranking: Vec<usize> = (0..candidates.len()).collect();
+	ranking.sort_by(|&i, &j| columns[0].1[i].loss.total_cmp(&columns[0].1[j].loss));
+	// The cells of each column, by candidate
+	let cells: Vec<Vec<String>> = columns.iter().map(|(_, scores)| scores.iter().map(|score| {
+		let (low, high) = score.confidence_interval;
+		format!("{:.6} ± {:.6}", score.loss, (high - low) / 2.0)
+	}).collect()).collect();
+	let widths: Vec<usize> = columns.iter().zip(&cells).map(|((kind, _), cells)| {
+		cells.iter().map(|cell| cell.chars().count()).chain([kind.name().len()]).max().unwrap()
+	}).collect();
+	let mut header = format!("{:>3}  {:>11}", "#", "search loss");
+	for ((kind, _), width) in columns.iter().zip(&widths) {
+		header += &format!("  {:>w$}", kind.name(), w = width);
+	}
+	println!("Ranked by {} (loss ± half the width of the 95% CI):", columns[0].0);
+	println!("{}  function", header);
+	for (rank, &i) in ranking.iter().enumerate() {
+		let mut row = format!("{:>3}  {:>11.6}", rank + 1, candidates[i].search_loss);
+		for (cells, width) in cells.iter().zip(&widths) {
+			row += &format!("  {:>w$}", cells[i], w = width);
+		}
+		println!("{}  {}", row, candidates[i].func);
+	}
+	if interrupt.load(Ordering::Acquire) {
+		println!("Interrupted, so not all evaluators are shown.");
+	}
+}
+
+/** Scores the candidates of the exam with the evaluator `E` (see `Exam::sit`). */
+fn sit<W: Word, E: SearchEvaluator<W>>(exam: &Exam, candidates: &[Candidate<F<W>>], interrupt: &AtomicBool)
+	-> Option<Vec<Score>> {
+	exam.sit::<F<W>, E>(candidates, interrupt)
+}
+
+
+/** The 95% confidence interval of the loss and, if the evaluator knows it, its p-value against a random function.
+   Distances that come from a test also report the p-value of the worst input bit (see `worst_bit_p_value`). */
+fn confidence<W: Word, E: SearchEvaluator<W>>(ev: &E, config: &SearchConfig) -> String {
+	let (low, high) = ev.confidence_interval();
+	let mut s = format!("95% CI: [{}, {}]", low, high);
+	if let Some(p) = ev.get_p_value(config) {
+		s += &format!(", p-value (random function): {:.3e}", p);
+	}
+	if let Some(p) = worst_bit_p_value(ev.get_func(), config) {
+		s += &format!(", {} p-value (worst input bit): {:.3e}", config.distance, p);
+	}
+	s
+}
+
+/** `stats::worst_bit_p_value` on `n_samples` fresh uniform samples, the same for every function. `None` if the configured
+   distance doesn't come from a test. */
+fn worst_bit_p_value(f: &impl DiffusionFunc, config: &SearchConfig) -> Option<f64> {
+	if !config.distance.has_p_value() {
+		return None;
+	}
+	let samples = SampleBuffer::random(config.n_samples as usize, &mut config.rng(0, RngStream::Report));
+	stats::worst_bit_p_value(f, &samples, config.distance)
+}
+
+
 #[allow(clippy::type_complexity)]
-fn print_by_age(results: &[Result<Evolution<F, E>, Box<dyn Any + Send>>], oldest: u32) {
+fn print_by_age<W: Word, E: SearchEvaluator<W>>(results: &[Result<Evolution<F<W>, E>, Box<dyn Any + Send>>], oldest: u32) {
 	let mut best = vec![Option::None; oldest as usize + 1];
 	let mut min_loss = vec![f32::MAX; oldest as usize + 1];
 	for r in results.iter() {
@@ -170,9 +535,9 @@ fn print_by_age(results: &[Result<Evolution<F, E>, Box<dyn Any + Send>>], oldest
 	}
 	println!("By age:");
 	for (i, &f) in best.iter().enumerate() {
-		if f.is_some() {
-			// we might not have a function of that age
-			println!("Age {:02}: {}", i, f.unwrap());
+		// we might not have a function of that age
+		if let Some(f) = f {
+			println!("Age {:02}: {}", i, f);
 		}
 	}
 }
@@ -0,0 +1,214 @@
+use std::{
+	collections::HashSet,
+	error::Error,
+	fmt::{self, Display, Formatter},
+	fs, io,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use rand::{seq::index, RngCore};
+
+use super::sample_source::SampleSource;
+
+
+/** How the samples of a generation are drawn from a `Corpus`. */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CorpusMode {
+	/** Consecutive windows of the corpus, one per draw (see `SampleSource::fill`), wrapping around at its end. Every
+	   key is used before any is used again. */
+	#[default]
+	Rotate,
+	/** A random subset (without replacement) of the corpus. */
+	Subsample,
+}
+
+impl CorpusMode {
+	pub const ALL: [CorpusMode; 2] = [CorpusMode::Rotate, CorpusMode::Subsample];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			CorpusMode::Rotate => "rotate",
+			CorpusMode::Subsample => "subsample",
+		}
+	}
+}
+
+impl Display for CorpusMode {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for CorpusMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		CorpusMode::ALL.into_iter().find(|mode| mode.name() == s).ok_or_else(|| format!("unknown corpus mode {}", s))
+	}
+}
+
+
+/** Real keys (e.g. dumped from production) to evaluate functions on instead of synthetic samples. */
+#[derive(Debug)]
+pub struct Corpus {
+	keys: Vec<u64>,
+	mode: CorpusMode,
+}
+
+impl Corpus {
+	/** Wraps the given keys. Fails if there are fewer than `min_distinct` distinct ones. */
+	pub fn new(keys: Vec<u64>, mode: CorpusMode, min_distinct: usize) -> Result<Self, CorpusError> {
+		let distinct = keys.iter().collect::<HashSet<_>>().len();
+		if distinct < min_distinct {
+			return Err(CorpusError::TooFewKeys { distinct, needed: min_distinct });
+		}
+		Ok(Self { keys, mode })
+	}
+
+	/** Loads a corpus from a file. Files with the extension `bin` hold little-endian `u64`s back to back. Anything else
+	   is read as text with one key per line, either in decimal or in hexadecimal (with a `0x` prefix); empty lines and
+	   lines starting with `#` are skipped. */
+	pub fn load(path: &Path, mode: CorpusMode, min_distinct: usize) -> Result<Self, CorpusError> {
+		let err = |kind| CorpusError::File(path.to_path_buf(), kind);
+		let keys = if path.extension().is_some_and(|ext| ext == "bin") {
+			let bytes = fs::read(path).map_err(|e| err(FileError::Io(e)))?;
+			if bytes.len() % 8 != 0 {
+				return Err(err(FileError::Length(bytes.len())));
+			}
+			bytes.chunks_exact(8).map(|b| u64::from_le_bytes(b.try_into().unwrap())).collect()
+		} else {
+			let text = fs::read_to_string(path).map_err(|e| err(FileError::Io(e)))?;
+			let mut keys = Vec::new();
+			for (i, line) in text.lines().enumerate() {
+				let line = line.trim();
+				if line.is_empty() || line.starts_with('#') {
+					continue;
+				}
+				let key = match line.strip_prefix("0x") {
+					Some(hex) => u64::from_str_radix(hex, 16),
+					None => line.parse(),
+				};
+				keys.push(key.map_err(|_| err(FileError::Parse(i + 1, line.to_string())))?);
+			}
+			keys
+		};
+		Self::new(keys, mode, min_distinct)
+	}
+
+	pub fn len(&self) -> usize { self.keys.len() }
+
+	pub fn is_empty(&self) -> bool { self.keys.is_empty() }
+}
+
+impl SampleSource for Corpus {
+	fn fill(&self, samples: &mut [u64], draw: u64, rng: &mut dyn RngCore) {
+		let n = self.keys.len();
+		match self.mode {
+			CorpusMode::Rotate => {
+				let start = ((draw as u128 * samples.len() as u128) % n as u128) as usize;
+				for (i, sample) in samples.iter_mut().enumerate() {
+					*sample = self.keys[(start + i) % n];
+				}
+			}
+			CorpusMode::Subsample => {
+				// Without replacement as long as the corpus is large enough.
+				for chunk in samples.chunks_mut(n) {
+					let indices = index::sample(rng, n, chunk.len());
+					for (sample, i) in chunk.iter_mut().zip(indices) {
+						*sample = self.keys[i];
+					}
+				}
+			}
+		}
+	}
+}
+
+
+#[derive(Debug)]
+pub enum CorpusError {
+	File(PathBuf, FileError),
+	TooFewKeys { distinct: usize, needed: usize },
+}
+
+#[derive(Debug)]
+pub enum FileError {
+	Io(io::Error),
+	/** The length (in bytes) of a binary corpus isn't a multiple of 8. */
+	Length(usize),
+	/** A line (1-based) of a text corpus isn't a number. */
+	Parse(usize, String),
+}
+
+impl Display for CorpusError {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			CorpusError::File(path, FileError::Io(e)) => write!(f, "couldn't read corpus {}: {}", path.display(), e),
+			CorpusError::File(path, FileError::Length(len)) => {
+				write!(f, "corpus {} has {} bytes, which isn't a multiple of 8", path.display(), len)
+			}
+			CorpusError::File(path, FileError::Parse(line, s)) => {
+				write!(f, "invalid key in corpus {}, line {}: {}", path.display(), line, s)
+			}
+			CorpusError::TooFewKeys { distinct, needed } => {
+				write!(f, "corpus has only {} distinct keys but needs at least {} (n_samples)", distinct, needed)
+			}
+		}
+	}
+}
+
+impl Error for CorpusError {}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_fill() {
+		let mut rng = rand::thread_rng();
+		let corpus = Corpus::new((0..10).collect(), CorpusMode::Rotate, 10).unwrap();
+		let mut samples = [0_u64; 4];
+		corpus.fill(&mut samples, 0, &mut rng);
+		assert_eq!([0, 1, 2, 3], samples);
+		corpus.fill(&mut samples, 1, &mut rng);
+		assert_eq!([4, 5, 6, 7], samples);
+		corpus.fill(&mut samples, 2, &mut rng);
+		assert_eq!([8, 9, 0, 1], samples);
+
+		let corpus = Corpus::new((0..10).collect(), CorpusMode::Subsample, 10).unwrap();
+		let mut samples = [0_u64; 10];
+		corpus.fill(&mut samples, 0, &mut rng);
+		samples.sort();
+		assert_eq!((0..10).collect::<Vec<_>>(), samples);
+		let mut samples = [0_u64; 25];
+		corpus.fill(&mut samples, 0, &mut rng);
+		assert!(samples.iter().all(|&x| x < 10));
+
+		assert!(matches!(Corpus::new(vec![1, 2, 2, 3], CorpusMode::Rotate, 4),
+			Err(CorpusError::TooFewKeys { distinct: 3, needed: 4 })));
+	}
+
+	#[test]
+	fn test_load() {
+		let dir = std::env::temp_dir().join(format!("diffusion-corpus-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let text = dir.join("keys.txt");
+		fs::write(&text, "# keys\n1\n0x10\n\n18446744073709551615\n").unwrap();
+		let corpus = Corpus::load(&text, CorpusMode::Rotate, 3).unwrap();
+		assert_eq!(vec![1, 16, u64::MAX], corpus.keys);
+		fs::write(&text, "1\nfoo\n").unwrap();
+		assert!(matches!(Corpus::load(&text, CorpusMode::Rotate, 1),
+			Err(CorpusError::File(_, FileError::Parse(2, _)))));
+
+		let bin = dir.join("keys.bin");
+		let bytes: Vec<u8> = [7_u64, 1 << 40].iter().flat_map(|k| k.to_le_bytes()).collect();
+		fs::write(&bin, &bytes).unwrap();
+		assert_eq!(vec![7, 1 << 40], Corpus::load(&bin, CorpusMode::Rotate, 2).unwrap().keys);
+		fs::write(&bin, &bytes[..12]).unwrap();
+		assert!(matches!(Corpus::load(&bin, CorpusMode::Rotate, 1), Err(CorpusError::File(_, FileError::Length(12)))));
+		assert!(matches!(Corpus::load(&dir.join("missing.txt"), CorpusMode::Rotate, 1),
+			Err(CorpusError::File(_, FileError::Io(_)))));
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
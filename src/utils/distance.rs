@@ -0,0 +1,396 @@
+use std::{
+	fmt::{self, Display, Formatter},
+	str::FromStr,
+};
+
+use super::wasserstein::{self, binom_pmf};
+
+/** A measure of how far a histogram of bit flips is from Bin(bits, 0.5), i.e. from what a perfectly random function
+   would produce. Like `wasserstein::of_counts`, implementations take the raw counts on 0..=bits (with
+   `bits = counts.len() - 1`) and the number of samples they sum up to. Lower is better and 0 is a perfect match. */
+pub trait DistributionDistance {
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32;
+
+	/** The p-value of the counts under the hypothesis that they were drawn from Bin(bits, 0.5), for distances that
+	   come from a test with a known distribution. */
+	fn p_value(&self, _counts: &[u32], _n_samples: u32) -> Option<f64> { None }
+}
+
+
+/** The 1-Wasserstein (earth mover's) distance, see `wasserstein::of_counts`. */
+pub struct Wasserstein1;
+
+impl DistributionDistance for Wasserstein1 {
+	#[inline(always)]
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 { wasserstein::of_counts(counts, n_samples) }
+}
+
+
+/** The 2-Wasserstein distance, i.e. the root mean square difference of the quantile functions. Compared to
+   `Wasserstein1`, mass that is moved far is penalised more heavily. */
+pub struct Wasserstein2;
+
+impl DistributionDistance for Wasserstein2 {
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 {
+		let pmf = binom_pmf(counts.len() as u32 - 1);
+		let n = n_samples as f64;
+		// Walks both cdfs at once. Between two consecutive steps of either cdf, both quantiles are constant.
+		let (mut i, mut j) = (0, 0);
+		let (mut p, mut q) = (counts[0] as f64 / n, pmf[0]);
+		let (mut u, mut d) = (0_f64, 0_f64);
+		while i < counts.len() && j < pmf.len() {
+			let next = p.min(q);
+			d += (next - u) * (i as f64 - j as f64).powi(2);
+			u = next;
+			if p <= next {
+				i += 1;
+				p += counts.get(i).map_or(0.0, |&c| c as f64 / n);
+			}
+			if q <= next {
+				j += 1;
+				q += pmf.get(j).copied().unwrap_or(0.0);
+			}
+		}
+		d.sqrt() as f32
+	}
+}
+
+
+/** Pearson's chi-squared statistic. Bins in the tails with an expected count below 5 are pooled, as the test isn't
+   meaningful for them (the extremes of Bin(64, 0.5) have probabilities around 5e-20). */
+pub struct ChiSquared;
+
+impl DistributionDistance for ChiSquared {
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 {
+		let mut x = 0_f64;
+		for_each_pooled(counts, n_samples, |observed, expected| x += (observed - expected).powi(2) / expected);
+		x as f32
+	}
+
+	fn p_value(&self, counts: &[u32], n_samples: u32) -> Option<f64> {
+		let n_bins = for_each_pooled(counts, n_samples, |_, _| ());
+		Some(chi_squared_sf(self.of_counts(counts, n_samples) as f64, (n_bins - 1) as f64))
+	}
+}
+
+
+/** The G-test (likelihood-ratio) statistic `2 Σ O ln(O / E)` on the same pooled bins as `ChiSquared`. It also follows
+   a chi-squared distribution, but is less dominated by single bins with a small expected count. */
+pub struct GTest;
+
+impl DistributionDistance for GTest {
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 {
+		let mut g = 0_f64;
+		for_each_pooled(counts, n_samples, |observed, expected| {
+			if observed > 0.0 {
+				g += observed * (observed / expected).ln();
+			}
+		});
+		(2.0 * g) as f32
+	}
+
+	fn p_value(&self, counts: &[u32], n_samples: u32) -> Option<f64> {
+		let n_bins = for_each_pooled(counts, n_samples, |_, _| ());
+		Some(chi_squared_sf(self.of_counts(counts, n_samples) as f64, (n_bins - 1) as f64))
+	}
+}
+
+
+/** The Kolmogorov–Smirnov statistic, i.e. the largest absolute difference of the cdfs. */
+pub struct KolmogorovSmirnov;
+
+impl DistributionDistance for KolmogorovSmirnov {
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 {
+		let n = n_samples as f64;
+		let mut d = 0_f64;
+		let (mut p, mut q) = (0_f64, 0_f64);
+		for (&c, b) in counts.iter().zip(binom_pmf(counts.len() as u32 - 1)) {
+			p += c as f64 / n;
+			q += b;
+			d = d.max((p - q).abs());
+		}
+		d as f32
+	}
+}
+
+
+/** The total variation distance, i.e. half the L1 distance of the pmfs. */
+pub struct TotalVariation;
+
+impl DistributionDistance for TotalVariation {
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 {
+		let n = n_samples as f64;
+		let pmf = binom_pmf(counts.len() as u32 - 1);
+		let d: f64 = counts.iter().zip(pmf).map(|(&c, b)| (c as f64 / n - b).abs()).sum();
+		(d / 2.0) as f32
+	}
+}
+
+
+/** The Kullback–Leibler divergence of Bin(bits, 0.5) from the observed distribution (in nats). Unlike the G-test, no
+   bins are pooled, so a single count far out in a tail weighs a lot. */
+pub struct KullbackLeibler;
+
+impl DistributionDistance for KullbackLeibler {
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 {
+		let n = n_samples as f64;
+		let mut d = 0_f64;
+		for (&c, b) in counts.iter().zip(binom_pmf(counts.len() as u32 - 1)) {
+			if c > 0 {
+				let p = c as f64 / n;
+				d += p * (p / b).ln();
+			}
+		}
+		d as f32
+	}
+}
+
+
+/** Calls `f(observed, expected)` for each bin after pooling the tails of Bin(bits, 0.5), such that every bin has an
+   expected count of at least 5 (as far as `n_samples` allows). Returns the number of bins. */
+fn for_each_pooled(counts: &[u32], n_samples: u32, mut f: impl FnMut(f64, f64)) -> usize {
+	let bits = counts.len() - 1;
+	let pmf = binom_pmf(bits as u32);
+	let n = n_samples as f64;
+	// Bin(bits, 0.5) is symmetric, so we pool 0..=lo and bits - lo..=bits. The middle bin is never pooled.
+	let mut lo = 0;
+	let mut tail = pmf[0];
+	while lo + 1 < bits / 2 && (n * tail < 5.0 || n * pmf[lo + 1] < 5.0) {
+		lo += 1;
+		tail += pmf[lo];
+	}
+	let low: u32 = counts[..=lo].iter().sum();
+	let high: u32 = counts[bits - lo..].iter().sum();
+	f(low as f64, n * tail);
+	for k in lo + 1..bits - lo {
+		f(counts[k] as f64, n * pmf[k]);
+	}
+	f(high as f64, n * tail);
+	bits - 2 * lo + 1
+}
+
+
+/** The survival function `P(X > x)` of the chi-squared distribution with `df` degrees of freedom. */
+pub fn chi_squared_sf(x: f64, df: f64) -> f64 {
+	if x <= 0.0 {
+		return 1.0;
+	}
+	gamma_q(df / 2.0, x / 2.0)
+}
+
+/** The regularised upper incomplete gamma function `Q(a, x)`, by its series for small `x` and by its continued
+   fraction otherwise (see Numerical Recipes, 6.2). */
+fn gamma_q(a: f64, x: f64) -> f64 {
+	const EPS: f64 = 1e-14;
+	const MAX_ITER: usize = 1000;
+	let ln_prefix = a * x.ln() - x - ln_gamma(a);
+	if x < a + 1.0 {
+		let (mut term, mut sum, mut ap) = (1.0 / a, 1.0 / a, a);
+		for _ in 0..MAX_ITER {
+			ap += 1.0;
+			term *= x / ap;
+			sum += term;
+			if term.abs() < sum.abs() * EPS {
+				break;
+			}
+		}
+		1.0 - sum * ln_prefix.exp()
+	} else {
+		// Modified Lentz's method
+		let tiny = f64::MIN_POSITIVE / EPS;
+		let mut b = x + 1.0 - a;
+		let mut c = 1.0 / tiny;
+		let mut d = 1.0 / b;
+		let mut h = d;
+		for i in 1..MAX_ITER {
+			let an = -(i as f64) * (i as f64 - a);
+			b += 2.0;
+			d = an * d + b;
+			if d.abs() < tiny {
+				d = tiny;
+			}
+			c = b + an / c;
+			if c.abs() < tiny {
+				c = tiny;
+			}
+			d = 1.0 / d;
+			let delta = d * c;
+			h *= delta;
+			if (delta - 1.0).abs() < EPS {
+				break;
+			}
+		}
+		ln_prefix.exp() * h
+	}
+}
+
+/** The natural logarithm of the gamma function for `x > 0` (Lanczos approximation, g = 7). */
+fn ln_gamma(x: f64) -> f64 {
+	const COEFFS: [f64; 9] = [0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8,
+		771.323_428_777_653_1, -176.615_029_162_140_6, 12.507_343_278_686_905, -0.138_571_095_265_720_12,
+		9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7];
+	if x < 0.5 {
+		// Reflection formula
+		let pi = std::f64::consts::PI;
+		return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+	}
+	let x = x - 1.0;
+	let mut sum = COEFFS[0];
+	for (i, c) in COEFFS.iter().enumerate().skip(1) {
+		sum += c / (x + i as f64);
+	}
+	let t = x + 7.5;
+	0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+
+/** The distances that can be picked at runtime (see `SearchConfig::distance`). */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceKind {
+	/** See `Wasserstein1`. */
+	#[default]
+	Wasserstein1,
+	/** See `Wasserstein2`. */
+	Wasserstein2,
+	/** See `ChiSquared`. */
+	ChiSquared,
+	/** See `GTest`. */
+	GTest,
+	/** See `KolmogorovSmirnov`. */
+	KolmogorovSmirnov,
+	/** See `TotalVariation`. */
+	TotalVariation,
+	/** See `KullbackLeibler`. */
+	KullbackLeibler,
+}
+
+impl DistanceKind {
+	pub const ALL: [DistanceKind; 7] = [DistanceKind::Wasserstein1, DistanceKind::Wasserstein2,
+		DistanceKind::ChiSquared, DistanceKind::GTest, DistanceKind::KolmogorovSmirnov, DistanceKind::TotalVariation,
+		DistanceKind::KullbackLeibler];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			DistanceKind::Wasserstein1 => "wasserstein1",
+			DistanceKind::Wasserstein2 => "wasserstein2",
+			DistanceKind::ChiSquared => "chi_squared",
+			DistanceKind::GTest => "g_test",
+			DistanceKind::KolmogorovSmirnov => "kolmogorov_smirnov",
+			DistanceKind::TotalVariation => "total_variation",
+			DistanceKind::KullbackLeibler => "kullback_leibler",
+		}
+	}
+
+	/** Whether the distance is the statistic of a test with a known distribution, i.e. has a `p_value`. */
+	pub fn has_p_value(&self) -> bool { matches!(self, DistanceKind::ChiSquared | DistanceKind::GTest) }
+}
+
+impl DistributionDistance for DistanceKind {
+	#[inline(always)]
+	fn of_counts(&self, counts: &[u32], n_samples: u32) -> f32 {
+		match self {
+			DistanceKind::Wasserstein1 => Wasserstein1.of_counts(counts, n_samples),
+			DistanceKind::Wasserstein2 => Wasserstein2.of_counts(counts, n_samples),
+			DistanceKind::ChiSquared => ChiSquared.of_counts(counts, n_samples),
+			DistanceKind::GTest => GTest.of_counts(counts, n_samples),
+			DistanceKind::KolmogorovSmirnov => KolmogorovSmirnov.of_counts(counts, n_samples),
+			DistanceKind::TotalVariation => TotalVariation.of_counts(counts, n_samples),
+			DistanceKind::KullbackLeibler => KullbackLeibler.of_counts(counts, n_samples),
+		}
+	}
+
+	fn p_value(&self, counts: &[u32], n_samples: u32) -> Option<f64> {
+		match self {
+			DistanceKind::ChiSquared => ChiSquared.p_value(counts, n_samples),
+			DistanceKind::GTest => GTest.p_value(counts, n_samples),
+			_ => None,
+		}
+	}
+}
+
+impl Display for DistanceKind {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+impl FromStr for DistanceKind {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		DistanceKind::ALL.into_iter().find(|kind| kind.name() == s).ok_or_else(|| format!("unknown distance {}", s))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::Rng;
+
+	use super::*;
+
+	fn random_counts(bits: u32, n_samples: u32) -> Vec<u32> {
+		let mut rng = rand::thread_rng();
+		let mut counts = vec![0_u32; bits as usize + 1];
+		for _ in 0..n_samples {
+			counts[(rng.gen::<u128>() & (u128::MAX >> (128 - bits))).count_ones() as usize] += 1;
+		}
+		counts
+	}
+
+	#[test]
+	fn test_distances() {
+		// The expected counts themselves (as far as integers allow) are close to Bin(64, 0.5).
+		let n = 1 << 20;
+		let expected: Vec<u32> = binom_pmf(64).iter().map(|p| (p * n as f64).round() as u32).collect();
+		let n = expected.iter().sum();
+		// All mass in a single bin is far from it.
+		let mut skewed = vec![0_u32; 65];
+		skewed[40] = n;
+		for kind in DistanceKind::ALL {
+			let d = kind.of_counts(&expected, n);
+			assert!(d >= 0.0 && d < 1e-3 * kind.of_counts(&skewed, n), "{}: {}", kind, d);
+		}
+		// E[(40 - X)^2] = (40 - 32)^2 + Var(X)
+		assert!((Wasserstein2.of_counts(&skewed, n) - 80_f32.sqrt()).abs() < 1e-4);
+		assert!((TotalVariation.of_counts(&skewed, n) - (1.0 - binom_pmf(64)[40] as f32)).abs() < 1e-6);
+		// The reference cdf just below 40, about 0.96
+		assert!(KolmogorovSmirnov.of_counts(&skewed, n) > 0.9);
+		assert!(ChiSquared.p_value(&skewed, n).unwrap() < 1e-10);
+		assert_eq!(None, DistanceKind::TotalVariation.p_value(&skewed, n));
+		for kind in DistanceKind::ALL {
+			assert_eq!(kind.has_p_value(), kind.p_value(&skewed, n).is_some(), "{}", kind);
+		}
+	}
+
+	#[test]
+	fn test_p_values() {
+		// Under the null hypothesis, p-values are uniformly distributed.
+		for bits in [16, 64, 128] {
+			let p_values: Vec<f64> = (0..200).map(|_| ChiSquared.p_value(&random_counts(bits, 1000), 1000).unwrap())
+				.collect();
+			let mean = p_values.iter().sum::<f64>() / p_values.len() as f64;
+			assert!((0.4..0.6).contains(&mean), "{}: {}", bits, mean);
+			assert!(GTest.p_value(&random_counts(bits, 1000), 1000).unwrap() > 1e-6);
+		}
+	}
+
+	#[test]
+	fn test_chi_squared_sf() {
+		// For even degrees of freedom 2k, the survival function is exp(-x/2) Σ_{i<k} (x/2)^i / i!.
+		assert!((chi_squared_sf(3.84145882, 1.0) - 0.05).abs() < 1e-8);
+		assert!((chi_squared_sf(10.0, 10.0) - 0.44049329).abs() < 1e-8);
+		assert!((chi_squared_sf(100.0, 64.0) - 0.00268628).abs() < 1e-8);
+		assert_eq!(1.0, chi_squared_sf(0.0, 3.0));
+		assert!((ln_gamma(10.0) - 362880_f64.ln()).abs() < 1e-12);
+	}
+
+	#[test]
+	fn test_parse() {
+		for kind in DistanceKind::ALL {
+			assert_eq!(Ok(kind), kind.name().parse());
+			assert_eq!(format!("\"{}\"", kind), serde_json::to_string(&kind).unwrap());
+		}
+		assert!("w1".parse::<DistanceKind>().is_err());
+	}
+}
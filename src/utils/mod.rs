@@ -6,7 +6,13 @@ use num_integer::{div_rem};
 use rand::Rng;
 
 pub mod bitset;
+pub mod corpus;
+pub mod distance;
+pub mod sample_buffer;
+pub mod sample_source;
 pub mod wasserstein;
+pub mod word;
+pub mod xoshiro;
 
 /** For some reason _mm256_load intrinsics are painfully slow and using transmute is significantly faster.
  * However, you need to make sure yourself that the pointer is 32-byte aligned. */
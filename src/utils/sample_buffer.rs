@@ -0,0 +1,80 @@
+use std::{
+	ops::{Deref, DerefMut},
+	slice,
+};
+
+use aligned_array::{Aligned, A32};
+use rand::{Rng, RngCore};
+
+use super::sample_source::SampleSource;
+
+type Chunk = Aligned<A32, [u64; 4]>;
+
+/** A heap-allocated buffer of samples whose size is only known at runtime. Like the `Aligned<A32, _>` arrays we use on
+   the stack, the start of the buffer is guaranteed to be 32-byte aligned, so every chunk of four samples can be read
+   with `utils::read_m256i`. Dereferences to a plain `[u64]`. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleBuffer {
+	chunks: Vec<Chunk>,
+	len: usize,
+}
+
+impl SampleBuffer {
+	/** Returns a buffer of `len` zeros. */
+	pub fn new(len: usize) -> Self {
+		let chunks = vec![Aligned([0_u64; 4]); (len + 3) >> 2];
+		Self { chunks, len }
+	}
+
+	pub fn random(len: usize, rng: &mut impl Rng) -> Self {
+		let mut buffer = Self::new(len);
+		for sample in buffer.iter_mut() {
+			*sample = rng.gen();
+		}
+		buffer
+	}
+
+	/** Returns a buffer of `len` samples drawn from `source` (see `SampleSource::fill` for `draw`). */
+	pub fn from_source(len: usize, source: &dyn SampleSource, draw: u64, rng: &mut dyn RngCore) -> Self {
+		let mut buffer = Self::new(len);
+		source.fill(&mut buffer, draw, rng);
+		buffer
+	}
+}
+
+impl Default for SampleBuffer {
+	fn default() -> Self { Self::new(0) }
+}
+
+impl Deref for SampleBuffer {
+	type Target = [u64];
+
+	#[inline(always)]
+	fn deref(&self) -> &[u64] {
+		// Safe because each chunk consists of exactly four u64s without any padding in between.
+		unsafe { slice::from_raw_parts(self.chunks.as_ptr() as *const u64, self.len) }
+	}
+}
+
+impl DerefMut for SampleBuffer {
+	#[inline(always)]
+	fn deref_mut(&mut self) -> &mut [u64] {
+		unsafe { slice::from_raw_parts_mut(self.chunks.as_mut_ptr() as *mut u64, self.len) }
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_alignment() {
+		let mut rng = rand::thread_rng();
+		for len in [0_usize, 1, 3, 4, 5, 1000, 100_001] {
+			let buffer = SampleBuffer::random(len, &mut rng);
+			assert_eq!(len, buffer.len());
+			assert_eq!(0, buffer.as_ptr() as usize & 31);
+		}
+	}
+}
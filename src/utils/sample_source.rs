@@ -0,0 +1,227 @@
+use std::{
+	fmt::{self, Debug, Display, Formatter},
+	str::FromStr,
+};
+
+use rand::{Rng, RngCore};
+
+/** Where the inputs of an evaluation come from. Samples are always 64 bits wide; narrower words keep the low bits of
+   a sample and 128-bit words are made up of two consecutive samples (see `Word::from_sample`). */
+pub trait SampleSource: Debug + Send + Sync {
+	/** Overwrites all of `samples` with fresh ones. `draw` numbers the fills of a search across all threads (thread `t`
+	   of `n` makes draws `t`, `t + n`, `t + 2n`, ...), so that sources that go through their samples in order (like
+	   `CorpusMode::Rotate`) don't depend on how the threads are scheduled. */
+	fn fill(&self, samples: &mut [u64], draw: u64, rng: &mut dyn RngCore);
+}
+
+
+/** The distributions of inputs that can be picked through `SearchConfig::samples`. Their textual form (used both on
+   the command line and in JSON) is the name, optionally followed by `:<parameter>`, e.g. `counter:8`. A mixture is
+   written as `<weight>*<distribution>+...`, e.g. `0.5*uniform+0.5*pointer:4`. */
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SampleDistribution {
+	/** Uniformly random samples. */
+	#[default]
+	Uniform,
+	/** `start, start + stride, start + 2 * stride, ...` (wrapping) from a random `start`. */
+	Counter { stride: u64 },
+	/** Between 1 and `max_weight` random bits set. */
+	Sparse { max_weight: u32 },
+	/** The Gray code of a counter from a random start, i.e. consecutive samples differ in exactly one bit. */
+	Gray,
+	/** Like addresses in a 48-bit address space that are aligned to `2^align_bits`, i.e. the low `align_bits` and the
+	   high 16 bits are zero. */
+	Pointer { align_bits: u32 },
+	/** Consecutive blocks of the buffer are filled by the components, each in proportion to its weight. This keeps
+	   counters and Gray codes sequential. */
+	Mixture(Vec<(f64, SampleDistribution)>),
+}
+
+/** The number of bits of a `SampleDistribution::Pointer`. */
+const ADDRESS_BITS: u32 = 48;
+
+impl SampleSource for SampleDistribution {
+	/** Distributions don't depend on the draw, only on `rng`. */
+	fn fill(&self, samples: &mut [u64], _draw: u64, rng: &mut dyn RngCore) {
+		match self {
+			SampleDistribution::Uniform => {
+				for sample in samples.iter_mut() {
+					*sample = rng.gen();
+				}
+			}
+			SampleDistribution::Counter { stride } => {
+				let mut x: u64 = rng.gen();
+				for sample in samples.iter_mut() {
+					*sample = x;
+					x = x.wrapping_add(*stride);
+				}
+			}
+			SampleDistribution::Sparse { max_weight } => {
+				for sample in samples.iter_mut() {
+					let weight = rng.gen_range(1..=*max_weight);
+					let mut x = 0_u64;
+					while x.count_ones() < weight {
+						x |= 1 << rng.gen_range(0..64);
+					}
+					*sample = x;
+				}
+			}
+			SampleDistribution::Gray => {
+				let mut i: u64 = rng.gen();
+				for sample in samples.iter_mut() {
+					*sample = i ^ (i >> 1);
+					i = i.wrapping_add(1);
+				}
+			}
+			SampleDistribution::Pointer { align_bits } => {
+				let mask = ((1_u64 << ADDRESS_BITS) - 1) & (u64::MAX << align_bits);
+				for sample in samples.iter_mut() {
+					*sample = rng.gen::<u64>() & mask;
+				}
+			}
+			SampleDistribution::Mixture(components) => {
+				let total: f64 = components.iter().map(|(weight, _)| weight).sum();
+				let mut start = 0_usize;
+				let mut cumulative = 0_f64;
+				for (i, (weight, dist)) in components.iter().enumerate() {
+					cumulative += weight;
+					let end = if i + 1 == components.len() {
+						samples.len()
+					} else {
+						((cumulative / total * samples.len() as f64).round() as usize).min(samples.len())
+					};
+					dist.fill(&mut samples[start..end.max(start)], 0, rng);
+					start = end.max(start);
+				}
+			}
+		}
+	}
+}
+
+impl Display for SampleDistribution {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			SampleDistribution::Uniform => write!(f, "uniform"),
+			SampleDistribution::Counter { stride } => write!(f, "counter:{}", stride),
+			SampleDistribution::Sparse { max_weight } => write!(f, "sparse:{}", max_weight),
+			SampleDistribution::Gray => write!(f, "gray"),
+			SampleDistribution::Pointer { align_bits } => write!(f, "pointer:{}", align_bits),
+			SampleDistribution::Mixture(components) => {
+				for (i, (weight, dist)) in components.iter().enumerate() {
+					if i > 0 {
+						write!(f, "+")?;
+					}
+					write!(f, "{}*{}", weight, dist)?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl FromStr for SampleDistribution {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		if s.contains('+') || s.contains('*') {
+			let mut components = Vec::new();
+			for component in s.split('+') {
+				let (weight, dist) = match component.split_once('*') {
+					Some((weight, dist)) => {
+						(weight.trim().parse::<f64>().map_err(|_| format!("invalid weight in {}", component))?, dist)
+					}
+					None => (1.0, component),
+				};
+				if !(weight.is_finite() && weight > 0.0) {
+					return Err(format!("weights must be positive, got {}", weight));
+				}
+				match dist.parse()? {
+					SampleDistribution::Mixture(_) => return Err(format!("mixtures can't be nested: {}", component)),
+					dist => components.push((weight, dist)),
+				}
+			}
+			return Ok(SampleDistribution::Mixture(components));
+		}
+		let (name, param) = match s.split_once(':') {
+			Some((name, param)) => (name.trim(), Some(param.trim())),
+			None => (s, None),
+		};
+		let param_in = |default: u64, max: u64| -> Result<u64, String> {
+			let value = param.map(str::parse).transpose().map_err(|_| format!("invalid parameter in {}", s))?;
+			let value = value.unwrap_or(default);
+			if value == 0 || value > max {
+				return Err(format!("parameter of {} must be in 1..={}", name, max));
+			}
+			Ok(value)
+		};
+		let no_param = |dist: SampleDistribution| match param {
+			None => Ok(dist),
+			Some(_) => Err(format!("{} doesn't take a parameter", name)),
+		};
+		match name {
+			"uniform" => no_param(SampleDistribution::Uniform),
+			"counter" => Ok(SampleDistribution::Counter { stride: param_in(1, u64::MAX)? }),
+			"sparse" => Ok(SampleDistribution::Sparse { max_weight: param_in(4, 64)? as u32 }),
+			"gray" => no_param(SampleDistribution::Gray),
+			"pointer" => Ok(SampleDistribution::Pointer { align_bits: param_in(3, ADDRESS_BITS as u64 - 1)? as u32 }),
+			_ => Err(format!("unknown sample distribution {}", name)),
+		}
+	}
+}
+
+impl TryFrom<String> for SampleDistribution {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> { s.parse() }
+}
+
+impl From<SampleDistribution> for String {
+	fn from(dist: SampleDistribution) -> Self { dist.to_string() }
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sample(dist: &str, len: usize) -> Vec<u64> {
+		let mut samples = vec![0_u64; len];
+		dist.parse::<SampleDistribution>().unwrap().fill(&mut samples, 0, &mut rand::thread_rng());
+		samples
+	}
+
+	#[test]
+	fn test_distributions() {
+		let samples = sample("counter:8", 100);
+		assert!(samples.windows(2).all(|w| w[1] == w[0].wrapping_add(8)));
+		assert!(sample("sparse:3", 1000).iter().all(|x| (1..=3).contains(&x.count_ones())));
+		assert!(sample("gray", 1000).windows(2).all(|w| (w[0] ^ w[1]).count_ones() == 1));
+		assert!(sample("pointer:4", 1000).iter().all(|x| x & 0xf == 0 && x >> 48 == 0));
+		let samples = sample("0.75*counter+0.25*pointer:12", 1000);
+		assert!(samples[..750].windows(2).all(|w| w[1] == w[0].wrapping_add(1)));
+		assert!(samples[750..].iter().all(|x| x & 0xfff == 0));
+	}
+
+	#[test]
+	fn test_parse() {
+		assert_eq!(Ok(SampleDistribution::Counter { stride: 1 }), "counter".parse());
+		assert_eq!(Ok(SampleDistribution::Pointer { align_bits: 3 }), " pointer ".parse());
+		let mixture = SampleDistribution::Mixture(vec![(1.0, SampleDistribution::Uniform),
+			(0.5, SampleDistribution::Sparse { max_weight: 2 })]);
+		assert_eq!(Ok(mixture.clone()), "uniform + 0.5*sparse:2".parse());
+		for dist in [SampleDistribution::Gray, SampleDistribution::Counter { stride: 3 }, mixture] {
+			assert_eq!(Ok(dist.clone()), dist.to_string().parse());
+			let json = serde_json::to_string(&dist).unwrap();
+			assert_eq!(dist, serde_json::from_str(&json).unwrap());
+		}
+		assert!("gray:2".parse::<SampleDistribution>().is_err());
+		assert!("sparse:65".parse::<SampleDistribution>().is_err());
+		assert!("counter:x".parse::<SampleDistribution>().is_err());
+		assert!("0*uniform+gray".parse::<SampleDistribution>().is_err());
+		assert!("normal".parse::<SampleDistribution>().is_err());
+		assert_eq!(Err("mixtures can't be nested: 0.5*0.3*uniform".to_string()),
+			"0.5*0.3*uniform".parse::<SampleDistribution>());
+	}
+}
@@ -1,5 +1,3 @@
-use crate::globals::N_SAMPLES_PER_ROUND;
-
 // pub const BINOM64_PMF: [f32; 65] = [5.421010862427522e-20, 3.469446951953597e-18, 1.0928757898653858e-16,
 //     2.258609965721796e-15, 3.4443801977257506e-14, 4.1332562372709e-13, 4.064368633316391e-12, 3.367619724747855e-11,
 //     2.39942905388286e-10, 1.4929780779715472e-09, 8.211379428843538e-09, 4.031040810523176e-08,
@@ -15,87 +13,115 @@ use crate::globals::N_SAMPLES_PER_ROUND;
 // 4.0310408105231766e-08, 8.211379428843538e-09, 1.4929780779715472e-09, 2.39942905388286e-10,
 //     3.367619724747855e-11, 4.0643686333163915e-12, 4.1332562372708997e-13, 3.4443801977257506e-14,
 //     2.258609965721796e-15, 1.092875789865386e-16, 3.469446951953596e-18, 5.421010862427522e-20];
-const BINOM64_PMF: [f32; 65] = init_binom64_pmf();
-
-const fn init_binom64_pmf() -> [f32; 65] {
-	let mut pmf = [0_f32; 65];
-	let mut binom_coeffs = [[0_u64; 65]; 65];
-	let mut n = 0;
-	while n <= 64 {
-		binom_coeffs[n][0] = 1;
-		let mut k = 1;
-		while k <= n {
-			binom_coeffs[n][k] = binom_coeffs[n - 1][(k - 1)] + binom_coeffs[n - 1][k];
-			k += 1;
+const BINOM16_PMF: [f64; 17] = init_binom_pmf();
+const BINOM32_PMF: [f64; 33] = init_binom_pmf();
+const BINOM64_PMF: [f64; 65] = init_binom_pmf();
+const BINOM128_PMF: [f64; 129] = init_binom_pmf();
+
+/** The pmf of Bin(N - 1, 0.5). The binomial coefficients are built up one row of Pascal's triangle at a time, which
+   fits into a `u128` for all supported word widths. */
+const fn init_binom_pmf<const N: usize>() -> [f64; N] {
+	let mut pmf = [0_f64; N];
+	let mut binom_coeffs = [0_u128; N];
+	binom_coeffs[0] = 1;
+	let mut n = 1;
+	while n < N {
+		let mut k = n;
+		while k > 0 {
+			binom_coeffs[k] += binom_coeffs[k - 1];
+			k -= 1;
 		}
 		n += 1;
 	}
+	// 2^(N - 1) in floating point, avoiding an overflow for N - 1 = 128.
+	let mut total = 1_f64;
+	let mut i = 1;
+	while i < N {
+		total *= 2.0;
+		i += 1;
+	}
 	let mut k = 0;
-	while k <= 64 {
-		let p = binom_coeffs[64][k] as f64 / 18446744073709551616.0;
-		pmf[k] = p as f32;
+	while k < N {
+		pmf[k] = binom_coeffs[k] as f64 / total;
 		k += 1;
 	}
-
 	pmf
 }
 
-const BINOM64_CDF_SCALED: [f32; 65] = init_binom64_cdf_scaled();
-
-const fn init_binom64_cdf_scaled() -> [f32; 65] {
-	let mut cdf = [0_f32; 65];
-	let mut binom_coeffs = [[0_u64; 65]; 65];
-	let mut n = 0;
-	while n <= 64 {
-		binom_coeffs[n][0] = 1;
-		let mut k = 1;
-		while k <= n {
-			binom_coeffs[n][k] = binom_coeffs[n - 1][(k - 1)] + binom_coeffs[n - 1][k];
-			k += 1;
-		}
-		n += 1;
-	}
+const BINOM16_CDF: [f32; 17] = init_binom_cdf(&BINOM16_PMF);
+const BINOM32_CDF: [f32; 33] = init_binom_cdf(&BINOM32_PMF);
+const BINOM64_CDF: [f32; 65] = init_binom_cdf(&BINOM64_PMF);
+const BINOM128_CDF: [f32; 129] = init_binom_cdf(&BINOM128_PMF);
+
+/** The cdf of Bin(N - 1, 0.5), given its pmf. */
+const fn init_binom_cdf<const N: usize>(pmf: &[f64; N]) -> [f32; N] {
+	let mut cdf = [0_f32; N];
 	let mut p = 0_f64;
 	let mut k = 0;
-	while k <= 64 {
-		p += binom_coeffs[64][k] as f64 / 18446744073709551616.0 * N_SAMPLES_PER_ROUND as f64;
+	while k < N {
+		p += pmf[k];
 		cdf[k] = p as f32;
 		k += 1;
 	}
 	cdf
 }
 
+/** The pmf of Bin(bits, 0.5). In double precision, as the tails are far below what a `f32` resolves next to 1. */
+#[inline(always)]
+pub fn binom_pmf(bits: u32) -> &'static [f64] {
+	match bits {
+		16 => &BINOM16_PMF,
+		32 => &BINOM32_PMF,
+		64 => &BINOM64_PMF,
+		128 => &BINOM128_PMF,
+		_ => panic!("unsupported word width {}", bits),
+	}
+}
+
+/** The cdf of Bin(bits, 0.5), i.e. of the number of bit flips in a perfectly random word of the given width. */
+#[inline(always)]
+pub fn binom_cdf(bits: u32) -> &'static [f32] {
+	match bits {
+		16 => &BINOM16_CDF,
+		32 => &BINOM32_CDF,
+		64 => &BINOM64_CDF,
+		128 => &BINOM128_CDF,
+		_ => panic!("unsupported word width {}", bits),
+	}
+}
+
 /** Calculates the (unnormalised) 1-Wasserstein distance of a finite pmf on 0..=64 to Bin(64, 0.5). */
 #[inline(always)]
 pub fn of_distr(p: &[f32; 65]) -> f32 {
 	let mut d = 0_f32;
 	let mut t = 0_f32;
 	for i in 0..65 {
-		t = p[i] + t - BINOM64_PMF[i];
+		t = p[i] + t - BINOM64_PMF[i] as f32;
 		d += t.abs();
 	}
 	d //* (N_SAMPLES_PER_ROUND as f32).sqrt()
 }
 
-/** Calculates the (unnormalised) 1-Wasserstein distance from the raw case counts. This might be more efficient than
-scaling `p` itself, which would involve one floating point division per entry. Instead, we can avoid all this and only
-perform a single division at the end.
+/** Calculates the (unnormalised) 1-Wasserstein distance from the raw case counts on 0..=bits to Bin(bits, 0.5), where
+`bits = counts.len() - 1`. This might be more efficient than scaling `p` itself, which would involve one floating point
+division per entry. Instead, we scale the reference cdf (a multiplication) and only perform a single division at the end.
 
-_NOTE:_ The sum of the `p` vector must equal `N_SAMPLES_PER_ROUND`. */
+_NOTE:_ The sum of the `counts` vector must equal `n_samples`. */
 #[inline(always)]
-pub fn of_counts(counts: &[u32; 65]) -> f32 {
-	debug_assert!(counts.iter().sum::<u32>() == N_SAMPLES_PER_ROUND);
+pub fn of_counts(counts: &[u32], n_samples: u32) -> f32 {
+	debug_assert!(counts.iter().sum::<u32>() == n_samples);
+	let n = n_samples as f32;
 	let mut d = 0_f32;
 	let mut c_sum = 0_u32;
-	for (c, b) in counts.iter().zip(BINOM64_CDF_SCALED.iter()) {
+	for (c, b) in counts.iter().zip(binom_cdf(counts.len() as u32 - 1)) {
 		c_sum += c;
-		d += (c_sum as f32 - b).abs();
+		d += (c_sum as f32 - b * n).abs();
 	}
-	d / (N_SAMPLES_PER_ROUND as f32)//.sqrt()
+	d / n//.sqrt()
 }
 
-pub fn normalise(w1: f32) -> f32 {
-	w1 * (N_SAMPLES_PER_ROUND as f32).sqrt()
+pub fn normalise(w1: f32, n_samples: u32) -> f32 {
+	w1 * (n_samples as f32).sqrt()
 }
 
 
@@ -115,7 +141,7 @@ mod tests {
 		for n in 0..=64 {
 			binom_coeffs[n][0] = 1;
 			for k in 1..=n {
-				binom_coeffs[n][k] = binom_coeffs[n - 1][(k - 1)] + binom_coeffs[n - 1][k];
+				binom_coeffs[n][k] = binom_coeffs[n - 1][k - 1] + binom_coeffs[n - 1][k];
 			}
 		}
 		assert_eq!(1, binom_coeffs[2][0]);
@@ -124,9 +150,28 @@ mod tests {
 		assert_eq!(1832624140942590534, binom_coeffs[64][32]);
 	}
 
+	#[test]
+	fn test_binom_cdf() {
+		let mut p = 0_f32;
+		for (cdf, pmf) in BINOM64_CDF.iter().zip(BINOM64_PMF) {
+			p += pmf as f32;
+			assert!((cdf - p).abs() < 1e-6);
+		}
+		for bits in [16, 32, 64, 128] {
+			let cdf = binom_cdf(bits);
+			assert_eq!(bits as usize + 1, cdf.len());
+			assert_eq!(1.0, cdf[bits as usize]);
+			assert!((cdf[bits as usize / 2 - 1] - (1.0 - cdf[bits as usize / 2])).abs() < 1e-6);
+		}
+		assert_eq!(1.0 / 65536.0, BINOM16_CDF[0]);
+		assert_eq!(17.0 / 65536.0, BINOM16_CDF[1]);
+		assert_eq!(2_f64.powi(-128), binom_pmf(128)[128]);
+		assert!((binom_pmf(128).iter().sum::<f64>() - 1.0).abs() < 1e-12);
+	}
+
 	#[test]
 	fn test_wasserstein() {
-		assert_eq!(0.0, of_distr(&BINOM64_PMF));
+		assert_eq!(0.0, of_distr(&BINOM64_PMF.map(|p| p as f32)));
 		let mut rng = rand::thread_rng();
 		let mut counts = [0_u32; 65];
 		for _ in 0..10_000 {
@@ -164,6 +209,7 @@ mod tests {
 
 	#[test]
 	fn test_wasserstein_of_counts() {
+		const N_SAMPLES_PER_ROUND: u32 = 1000;
 		let mut rng = rand::thread_rng();
 		for _ in 0..100 {
 			let mut counts = [0_u32; 65];
@@ -173,7 +219,7 @@ mod tests {
 			}
 			let p = counts.map(|c| c as f32 / N_SAMPLES_PER_ROUND as f32);
 			let w1 = of_distr(&p);
-			let w2 = of_counts(&counts);
+			let w2 = of_counts(&counts, N_SAMPLES_PER_ROUND);
 			println!("{} ~ {}", w1, w2);
 			assert!(((w1 - w2) / w1).abs() <= 0.001);
 		}
@@ -0,0 +1,156 @@
+use std::{
+	fmt::{Debug, Display, LowerHex},
+	hash::Hash,
+	ops::{BitAnd, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr, ShrAssign},
+};
+
+use aligned_array::{Aligned, A32};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Serialize};
+
+/** Eight counters that can be added to with one AVX2 instruction. */
+pub type Chunk = Aligned<A32, [u32; 8]>;
+
+const ZERO_CHUNK: Chunk = Aligned([0; 8]);
+
+/** An unsigned integer type diffusion functions can operate on, i.e. one of `u16`, `u32`, `u64` and `u128`. The
+   methods mirror the inherent ones of the primitive types. */
+pub trait Word:
+	Copy + Default + Eq + Ord + Hash + Debug + Display + LowerHex + Send + Sync + 'static
+	+ BitAnd<Output = Self> + BitOr<Output = Self> + BitOrAssign + BitXor<Output = Self> + BitXorAssign
+	+ Not<Output = Self> + Shl<u32, Output = Self> + Shr<u32, Output = Self> + ShrAssign<u32>
+	+ Serialize + DeserializeOwned
+{
+	const BITS: u32;
+	const ZERO: Self;
+	const ONE: Self;
+	const MAX: Self;
+	/** Samples are always drawn as `u64`s. This is how many of them make up a word. */
+	const SAMPLE_LEN: usize;
+
+	/** `BITS` histograms of `BITS + 1` counters each, stored row by row (see `evaluation::bitflips::Bitflips`). Like
+	   `AvalancheCounts`, this is a type of the word because generic code can't compute array lengths from `BITS`. */
+	type FlipCounts: AsRef<[u32]> + AsMut<[u32]> + Debug + Eq;
+	const ZERO_FLIP_COUNTS: Self::FlipCounts;
+	/** `BITS` rows of `BITS / 8` chunks each (see `evaluation::avalanche::AvalancheDiagram`). */
+	type AvalancheCounts: AsRef<[Chunk]> + AsMut<[Chunk]> + Debug + Eq;
+	const ZERO_AVALANCHE_COUNTS: Self::AvalancheCounts;
+
+	/** Builds a word from `SAMPLE_LEN` samples, truncating them if the word is narrower. */
+	fn from_sample(sample: &[u64]) -> Self;
+
+	fn random(rng: &mut impl Rng) -> Self;
+
+	/** `None` if `x` doesn't fit. */
+	fn from_u128(x: u128) -> Option<Self>;
+
+	fn to_u128(self) -> u128;
+
+	/** The lowest 32 bits, e.g. to be used as a shift. */
+	fn low_u32(self) -> u32;
+
+	fn wrapping_add(self, rhs: Self) -> Self;
+
+	fn wrapping_sub(self, rhs: Self) -> Self;
+
+	fn wrapping_mul(self, rhs: Self) -> Self;
+
+	fn wrapping_shl(self, n: u32) -> Self;
+
+	fn wrapping_shr(self, n: u32) -> Self;
+
+	fn rotate_right(self, n: u32) -> Self;
+
+	fn count_ones(self) -> u32;
+}
+
+macro_rules! impl_word {
+	($t:ty, $sample_len:expr, |$sample:ident| $from_sample:expr) => {
+		impl Word for $t {
+			const BITS: u32 = <$t>::BITS;
+			const ZERO: Self = 0;
+			const ONE: Self = 1;
+			const MAX: Self = <$t>::MAX;
+			const SAMPLE_LEN: usize = $sample_len;
+
+			type FlipCounts = [u32; (<$t>::BITS * (<$t>::BITS + 1)) as usize];
+			const ZERO_FLIP_COUNTS: Self::FlipCounts = [0; (<$t>::BITS * (<$t>::BITS + 1)) as usize];
+			type AvalancheCounts = [Chunk; (<$t>::BITS * <$t>::BITS / 8) as usize];
+			const ZERO_AVALANCHE_COUNTS: Self::AvalancheCounts = [ZERO_CHUNK; (<$t>::BITS * <$t>::BITS / 8) as usize];
+
+			#[inline(always)]
+			fn from_sample($sample: &[u64]) -> Self { $from_sample }
+
+			#[inline(always)]
+			fn random(rng: &mut impl Rng) -> Self { rng.gen() }
+
+			#[inline(always)]
+			fn from_u128(x: u128) -> Option<Self> { x.try_into().ok() }
+
+			#[inline(always)]
+			fn to_u128(self) -> u128 { self as u128 }
+
+			#[inline(always)]
+			fn low_u32(self) -> u32 { self as u32 }
+
+			#[inline(always)]
+			fn wrapping_add(self, rhs: Self) -> Self { self.wrapping_add(rhs) }
+
+			#[inline(always)]
+			fn wrapping_sub(self, rhs: Self) -> Self { self.wrapping_sub(rhs) }
+
+			#[inline(always)]
+			fn wrapping_mul(self, rhs: Self) -> Self { self.wrapping_mul(rhs) }
+
+			#[inline(always)]
+			fn wrapping_shl(self, n: u32) -> Self { self.wrapping_shl(n) }
+
+			#[inline(always)]
+			fn wrapping_shr(self, n: u32) -> Self { self.wrapping_shr(n) }
+
+			#[inline(always)]
+			fn rotate_right(self, n: u32) -> Self { self.rotate_right(n) }
+
+			#[inline(always)]
+			fn count_ones(self) -> u32 { self.count_ones() }
+		}
+	};
+}
+
+impl_word!(u16, 1, |sample| sample[0] as u16);
+impl_word!(u32, 1, |sample| sample[0] as u32);
+impl_word!(u64, 1, |sample| sample[0]);
+impl_word!(u128, 2, |sample| (sample[0] as u128) << 64 | sample[1] as u128);
+
+/** The supported word widths, e.g. for `SearchConfig::word_width`. */
+pub const WIDTHS: [u32; 4] = [16, 32, 64, 128];
+
+/** The largest of `WIDTHS`, which sizes the buffers that have to fit words of any width. */
+pub const MAX_BITS: usize = 128;
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn check_word<W: Word>() {
+		assert_eq!(W::BITS, W::MAX.count_ones());
+		assert_eq!(W::ONE, W::MAX.wrapping_shr(W::BITS - 1));
+		assert_eq!(W::ZERO, W::MAX.wrapping_mul(W::ZERO));
+		assert_eq!(W::MAX, W::ZERO.wrapping_sub(W::ONE));
+		assert_eq!(W::ZERO, W::MAX.wrapping_add(W::ONE));
+		assert_eq!(Some(W::MAX), W::from_u128(W::MAX.to_u128()));
+		assert!(W::MAX.to_u128().checked_add(1).and_then(W::from_u128).is_none());
+		let samples = [u64::MAX; 2];
+		assert_eq!(W::MAX, W::from_sample(&samples[..W::SAMPLE_LEN]));
+	}
+
+	#[test]
+	fn test_words() {
+		check_word::<u16>();
+		check_word::<u32>();
+		check_word::<u64>();
+		check_word::<u128>();
+		assert_eq!(0x1_0000_0000_0000_0002, u128::from_sample(&[1, 2]));
+	}
+}
@@ -0,0 +1,103 @@
+use rand::{Error, RngCore, SeedableRng};
+
+/** The xoshiro256++ generator by Blackman and Vigna. Fast, small and, unlike the generators of `rand`, serialisable, so
+   that a stored search can go on with exactly the random numbers it would have drawn. */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Xoshiro256PlusPlus {
+	s: [u64; 4],
+}
+
+impl RngCore for Xoshiro256PlusPlus {
+	#[inline(always)]
+	fn next_u32(&mut self) -> u32 { (self.next_u64() >> 32) as u32 }
+
+	#[inline(always)]
+	fn next_u64(&mut self) -> u64 {
+		let s = &mut self.s;
+		let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+		let t = s[1] << 17;
+		s[2] ^= s[0];
+		s[3] ^= s[1];
+		s[1] ^= s[2];
+		s[0] ^= s[3];
+		s[2] ^= t;
+		s[3] = s[3].rotate_left(45);
+		result
+	}
+
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		for chunk in dest.chunks_mut(8) {
+			let bytes = self.next_u64().to_le_bytes();
+			chunk.copy_from_slice(&bytes[..chunk.len()]);
+		}
+	}
+
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+		self.fill_bytes(dest);
+		Ok(())
+	}
+}
+
+impl SeedableRng for Xoshiro256PlusPlus {
+	type Seed = [u8; 32];
+
+	/** The all-zero state would only ever produce zeros, so that seed is replaced. */
+	fn from_seed(seed: [u8; 32]) -> Self {
+		if seed.iter().all(|&b| b == 0) {
+			return Self::seed_from_u64(0);
+		}
+		let mut s = [0_u64; 4];
+		for (s, bytes) in s.iter_mut().zip(seed.chunks_exact(8)) {
+			*s = u64::from_le_bytes(bytes.try_into().unwrap());
+		}
+		Self { s }
+	}
+
+	/** Expands the seed with SplitMix64, as recommended by the authors. */
+	fn seed_from_u64(mut state: u64) -> Self {
+		Self { s: [(); 4].map(|_| splitmix64(&mut state)) }
+	}
+}
+
+impl Default for Xoshiro256PlusPlus {
+	fn default() -> Self { Self::seed_from_u64(0) }
+}
+
+/** Advances a SplitMix64 generator and returns its next output. Any state works, so this is also a good way to turn a
+   seed into well-mixed bits. */
+#[inline(always)]
+pub fn splitmix64(state: &mut u64) -> u64 {
+	*state = state.wrapping_add(0x9e3779b97f4a7c15);
+	let mut z = *state;
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+	z ^ (z >> 31)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_reference_outputs() {
+		// The state [1, 2, 3, 4], as in the reference implementation's tests.
+		let mut seed = [0_u8; 32];
+		for (i, chunk) in seed.chunks_exact_mut(8).enumerate() {
+			chunk[0] = i as u8 + 1;
+		}
+		let mut rng = Xoshiro256PlusPlus::from_seed(seed);
+		let expected = [41943041, 58720359, 3588806011781223, 3591011842654386, 9228616714210784205,
+			9973669472204895162, 14011001112246962877, 12406186145184390807, 15849039046786891736,
+			10450023813501588000];
+		for e in expected {
+			assert_eq!(e, rng.next_u64());
+		}
+		assert_ne!(Xoshiro256PlusPlus::from_seed([0; 32]).next_u64(), 0);
+		let mut bytes = [0_u8; 12];
+		Xoshiro256PlusPlus::seed_from_u64(7).fill_bytes(&mut bytes);
+		let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+		assert_eq!(rng.next_u64().to_le_bytes(), bytes[..8]);
+		assert_eq!(rng.next_u64().to_le_bytes()[..4], bytes[8..]);
+	}
+}